@@ -89,15 +89,21 @@ impl<'a> Button<'a> {
                     self.position.height(),
                 )
                 .expect("Failed to draw texture");
+        }
 
-            //text
+        //text, centered and cut off if it doesn't fit
+        if self.text.is_some() {
             let ttc = canvas.texture_creator();
             let (texture, text_width, text_height) = self.create_text_texture(&ttc, ttf_context);
-            let text_x = self.position.x + ((self.position.width() - text_width) / 2) as i32;
-            let text_y = self.position.y + ((self.position.height() - text_height) / 2) as i32;
+            let text_x =
+                self.position.x + (self.position.width().saturating_sub(text_width) / 2) as i32;
+            let text_y =
+                self.position.y + (self.position.height().saturating_sub(text_height) / 2) as i32;
 
             let target = Rect::new(text_x, text_y, text_width, text_height);
+            canvas.set_clip_rect(self.position);
             canvas.copy(&texture, None, Some(target)).unwrap();
+            canvas.set_clip_rect(None);
         }
     }
 
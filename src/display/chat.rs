@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::networking::shared::MAX_CHAT_LENGTH;
+
+pub const CHAT_LINES: usize = 8; // shown at once
+const CHAT_HISTORY: usize = 100;
+const CHAT_VISIBLE: Duration = Duration::from_secs(8); // before a line starts fading
+const CHAT_FADE: Duration = Duration::from_secs(2);
+
+pub struct ChatLine {
+    pub text: String,
+    pub from_server: bool, // joins, leaves, deaths
+    pub time: Instant,
+}
+
+// received messages and the line being typed
+#[derive(Default)]
+pub struct ChatLog {
+    lines: VecDeque<ChatLine>,
+    input: Option<String>, // some while typing
+    scroll: usize,         // lines scrolled back from the newest
+}
+
+impl ChatLog {
+    pub fn push(&mut self, text: String, from_server: bool, now: Instant) {
+        self.lines.push_back(ChatLine {
+            text,
+            from_server,
+            time: now,
+        });
+        if self.lines.len() > CHAT_HISTORY {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    pub fn open(&mut self) {
+        self.input = Some(String::new());
+        self.scroll = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.input = None;
+        self.scroll = 0;
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        if let Some(input) = &mut self.input {
+            for c in text.chars().filter(|c| !c.is_control()) {
+                if input.chars().count() >= MAX_CHAT_LENGTH {
+                    break;
+                }
+                input.push(c);
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(input) = &mut self.input {
+            input.pop();
+        }
+    }
+
+    // closes the input, returns what should be sent
+    pub fn submit(&mut self) -> Option<String> {
+        let text = self.input.take()?;
+        self.scroll = 0;
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    // only while typing, positive goes back in time
+    pub fn scroll(&mut self, lines: i32) {
+        if !self.is_open() {
+            return;
+        }
+        let max = self.lines.len().saturating_sub(CHAT_LINES);
+        self.scroll = (self.scroll as i64 + lines as i64).clamp(0, max as i64) as usize;
+    }
+
+    // what to draw, oldest first, with how opaque it is,
+    // everything stays visible while typing
+    pub fn visible(&self, now: Instant) -> Vec<(&ChatLine, u8)> {
+        let end = self.lines.len() - self.scroll;
+        let start = end.saturating_sub(CHAT_LINES);
+        self.lines
+            .range(start..end)
+            .filter_map(|line| {
+                if self.is_open() {
+                    return Some((line, 255));
+                }
+                let age = now.saturating_duration_since(line.time);
+                if age < CHAT_VISIBLE {
+                    Some((line, 255))
+                } else if age < CHAT_VISIBLE + CHAT_FADE {
+                    let faded = (age - CHAT_VISIBLE).as_secs_f64() / CHAT_FADE.as_secs_f64();
+                    Some((line, (255.0 * (1.0 - faded)) as u8))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
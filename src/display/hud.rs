@@ -1,5 +1,8 @@
 use crate::display::button::{self, HealthBar};
-use crate::networking::shared::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::display::chat::ChatLog;
+use crate::networking::player_packets::PlayerListEntry;
+use crate::networking::shared::{SCREEN_HEIGHT, SCREEN_WIDTH, player_name};
+use crate::networking::stats::NetworkStats;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::TextureCreator;
@@ -14,6 +17,7 @@ pub struct Hud<'a> {
     pub dropdown: button::Dropdown<'a>,
     pub health_bar: button::HealthBar,
     pub time_display: std::time::Instant,
+    pub seed: Option<u64>, // the server's, to report bugs with
 }
 
 impl<'a> Hud<'a> {
@@ -30,6 +34,7 @@ impl<'a> Hud<'a> {
             health_bar: health,
             dropdown: meni,
             time_display: time,
+            seed: None,
         }
     }
 
@@ -69,6 +74,197 @@ impl<'a> Hud<'a> {
         canvas.copy(&texture, None, rect).unwrap();
     }
 
+    // bottom left, right after the time
+    pub fn draw_seed(
+        &self,
+        canvas: &mut Canvas<Window>,
+        ttf_context: &ttf::Sdl2TtfContext,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) {
+        let Some(seed) = self.seed else {
+            return;
+        };
+        let font = ttf_context
+            .load_font("resources/fonts/manolomono.otf", 20)
+            .expect("Failed to load font");
+        let surface = font
+            .render(&format!("seed {}", seed))
+            .blended(Color::RGB(0, 0, 0))
+            .unwrap();
+        let texture = texture_creator
+            .create_texture_from_surface(&surface)
+            .unwrap();
+        let rect = Rect::new(
+            100,
+            (SCREEN_HEIGHT - 45) as i32,
+            texture.query().width,
+            texture.query().height,
+        );
+        canvas.copy(&texture, None, rect).unwrap();
+    }
+
+    // bottom left, newest line at the bottom, the input line below it while typing
+    pub fn draw_chat(
+        &self,
+        chat: &ChatLog,
+        canvas: &mut Canvas<Window>,
+        ttf_context: &ttf::Sdl2TtfContext,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) {
+        let font = ttf_context
+            .load_font("resources/fonts/Fixedsys62.ttf", 16)
+            .expect("Failed to load font");
+        let line_height = font.recommended_line_spacing();
+        let mut y = SCREEN_HEIGHT as i32 - 60 - line_height;
+
+        if let Some(input) = chat.input() {
+            canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, 150));
+            canvas
+                .fill_rect(Rect::new(
+                    5,
+                    y - 2,
+                    SCREEN_WIDTH / 2,
+                    line_height as u32 + 4,
+                ))
+                .unwrap();
+            draw_line(
+                canvas,
+                &font,
+                texture_creator,
+                &format!("> {}_", input),
+                Color::WHITE,
+                10,
+                y,
+            );
+            y -= line_height + 4;
+        }
+
+        let lines = chat.visible(std::time::Instant::now());
+        for (line, alpha) in lines.iter().rev() {
+            let color = if line.from_server {
+                Color::RGBA(255, 220, 90, *alpha)
+            } else {
+                Color::RGBA(255, 255, 255, *alpha)
+            };
+            draw_line(canvas, &font, texture_creator, &line.text, color, 10, y);
+            y -= line_height;
+        }
+    }
+
+    // top right, below the bar
+    pub fn draw_network_stats(
+        &self,
+        stats: &NetworkStats,
+        canvas: &mut Canvas<Window>,
+        ttf_context: &ttf::Sdl2TtfContext,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) {
+        let font = ttf_context
+            .load_font("resources/fonts/Fixedsys62.ttf", 16)
+            .expect("Failed to load font");
+        let line_height = font.recommended_line_spacing();
+        let lines = [
+            match stats.rtt {
+                Some(rtt) => format!("rtt  {} ms", rtt.as_millis()),
+                None => "rtt  -".to_string(),
+            },
+            format!(
+                "up   {} pkt/s {:.1} kB/s",
+                stats.sent.packets_per_second,
+                stats.sent.bytes_per_second as f64 / 1000.0
+            ),
+            format!(
+                "down {} pkt/s {:.1} kB/s",
+                stats.received.packets_per_second,
+                stats.received.bytes_per_second as f64 / 1000.0
+            ),
+        ];
+        let width = 230;
+        let x = SCREEN_WIDTH as i32 - width as i32 - 10;
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 150));
+        canvas
+            .fill_rect(Rect::new(
+                x - 5,
+                55,
+                width + 10,
+                (line_height * lines.len() as i32 + 10) as u32,
+            ))
+            .unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            let y = 60 + line_height * i as i32;
+            draw_line(canvas, &font, texture_creator, line, Color::WHITE, x, y);
+        }
+    }
+
+    // in the middle of the screen, we are marked
+    pub fn draw_player_list(
+        &self,
+        players: &[PlayerListEntry],
+        own_id: u64,
+        canvas: &mut Canvas<Window>,
+        ttf_context: &ttf::Sdl2TtfContext,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) {
+        let font = ttf_context
+            .load_font("resources/fonts/Fixedsys62.ttf", 16)
+            .expect("Failed to load font");
+        let line_height = font.recommended_line_spacing();
+        let mut lines = vec![format!("Players ({})", players.len())];
+        for entry in players {
+            let mut line = player_name(entry.player_id);
+            if entry.spectator {
+                line += " (spectating)";
+            }
+            if entry.player_id == own_id {
+                line += " *";
+            }
+            lines.push(line);
+        }
+        let width = 260;
+        let height = line_height * lines.len() as i32 + 10;
+        let x = (SCREEN_WIDTH as i32 - width) / 2;
+        let y = (SCREEN_HEIGHT as i32 - height) / 2;
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        canvas
+            .fill_rect(Rect::new(x, y, width as u32, height as u32))
+            .unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            let y = y + 5 + line_height * i as i32;
+            draw_line(
+                canvas,
+                &font,
+                texture_creator,
+                line,
+                Color::WHITE,
+                x + 10,
+                y,
+            );
+        }
+    }
+
+    // instead of the health bar, spectators have none
+    pub fn draw_spectating(
+        &self,
+        text: &str,
+        canvas: &mut Canvas<Window>,
+        ttf_context: &ttf::Sdl2TtfContext,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) {
+        let font = ttf_context
+            .load_font("resources/fonts/Fixedsys62.ttf", 16)
+            .expect("Failed to load font");
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 150));
+        canvas
+            .fill_rect(Rect::new(0, SCREEN_HEIGHT as i32 - 30, SCREEN_WIDTH, 30))
+            .unwrap();
+        let y = SCREEN_HEIGHT as i32 - 30 + (30 - font.height()) / 2;
+        draw_line(canvas, &font, texture_creator, text, Color::WHITE, 10, y);
+    }
+
     pub fn draw(
         &mut self,
         player_health: i32,
@@ -103,6 +299,7 @@ impl<'a> Hud<'a> {
 
         // narise time
         self.draw_time(canvas, ttf_context, texture_creator);
+        self.draw_seed(canvas, ttf_context, texture_creator);
 
         // narise health bar
         self.health_bar.draw(player_health, canvas);
@@ -112,3 +309,30 @@ impl<'a> Hud<'a> {
             .draw(canvas, ttf_context, texture_creator, texture_map);
     }
 }
+
+fn draw_line(
+    canvas: &mut Canvas<Window>,
+    font: &ttf::Font,
+    texture_creator: &TextureCreator<WindowContext>,
+    text: &str,
+    color: Color,
+    x: i32,
+    y: i32,
+) {
+    // ttf can't render empty strings
+    if text.is_empty() {
+        return;
+    }
+    let surface = font
+        .render(text)
+        .blended(Color::RGB(color.r, color.g, color.b))
+        .unwrap();
+    let mut texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .unwrap();
+    texture.set_alpha_mod(color.a);
+    let query = texture.query();
+    canvas
+        .copy(&texture, None, Rect::new(x, y, query.width, query.height))
+        .unwrap();
+}
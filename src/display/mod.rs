@@ -1,2 +1,4 @@
 pub mod button;
+pub mod chat;
 pub mod hud;
+pub mod server_browser;
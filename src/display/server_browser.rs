@@ -0,0 +1,205 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::ttf;
+use sdl2::video::{Window, WindowContext};
+
+use crate::display::button::{Button, ButtonAction};
+use crate::game::{GameState, find_sdl_gl_driver};
+use crate::networking::discovery::{DiscoveredServer, discover, lan_targets};
+use crate::networking::packet::PROTOCOL_VERSION;
+use crate::networking::shared::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::networking::transport::TransportKind;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+const ROW_HEIGHT: u32 = 50;
+const DISPLAYED_NAME_LENGTH: usize = 20; // so the rest of the row still fits
+
+// one row of the browser
+pub fn describe(server: &DiscoveredServer) -> String {
+    let announcement = &server.announcement;
+    let name: String = announcement
+        .name
+        .chars()
+        .take(DISPLAYED_NAME_LENGTH)
+        .collect();
+    let level = announcement
+        .level
+        .as_deref()
+        .and_then(|level| level.rsplit('/').next())
+        .unwrap_or("empty");
+    let mut line = format!(
+        "{}  {}/{}  {}",
+        name, announcement.players, announcement.max_players, level
+    );
+    if !compatible(server) {
+        line += "  (other version)";
+    }
+    line
+}
+
+fn compatible(server: &DiscoveredServer) -> bool {
+    server.announcement.protocol_version == PROTOCOL_VERSION
+}
+
+fn rows<'a>(servers: &[DiscoveredServer], chosen: &Rc<Cell<Option<usize>>>) -> Vec<Button<'a>> {
+    servers
+        .iter()
+        .enumerate()
+        .map(|(index, server)| {
+            let chosen = chosen.clone();
+            let (colour, action): (Color, Box<dyn FnMut()>) = if compatible(server) {
+                (
+                    Color::RGB(0, 56, 168),
+                    Box::new(move || chosen.set(Some(index))),
+                )
+            } else {
+                // we couldn't join anyway
+                (Color::RGB(90, 90, 90), Box::new(|| ()))
+            };
+            Button::new(
+                ButtonAction::Callback(action),
+                Some(describe(server)),
+                None,
+                colour,
+                Rect::new(
+                    50,
+                    100 + (index as u32 * (ROW_HEIGHT + 10)) as i32,
+                    SCREEN_WIDTH - 100,
+                    ROW_HEIGHT,
+                ),
+            )
+        })
+        .collect()
+}
+
+// a window with the servers on the local network, returns the address and transport
+// of the one that was clicked, nothing if the window was closed
+pub fn browse() -> Option<(String, TransportKind)> {
+    // asking takes a while, so it happens in the background
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            let servers = discover(&lan_targets(), ANSWER_TIMEOUT).unwrap_or_default();
+            if sender.send(servers).is_err() {
+                return; // the browser was closed
+            }
+            thread::sleep(REFRESH_INTERVAL);
+        }
+    });
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window("sea2d - servers", SCREEN_WIDTH, SCREEN_HEIGHT)
+        .position_centered()
+        .opengl()
+        .build()
+        .unwrap();
+    let mut canvas = window
+        .into_canvas()
+        .index(find_sdl_gl_driver().unwrap())
+        .build()
+        .unwrap();
+    let ttf_context = ttf::init().unwrap();
+    let font = ttf_context
+        .load_font("resources/fonts/Battle-Race.ttf", 32)
+        .unwrap();
+    let small_font = ttf_context
+        .load_font("resources/fonts/Fixedsys62.ttf", 16)
+        .unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture_map: HashMap<String, Texture> = HashMap::new();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let chosen: Rc<Cell<Option<usize>>> = Rc::new(Cell::new(None));
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    let mut buttons: Vec<Button> = Vec::new();
+    let mut searched = false;
+    let mut state = GameState::Running; // the buttons here don't change it
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                sdl2::event::Event::Quit { .. }
+                | sdl2::event::Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::ESCAPE),
+                    ..
+                } => return None,
+                _ => {
+                    for button in &mut buttons {
+                        button.handle_event(&event, &mut state);
+                    }
+                }
+            }
+        }
+        if let Some(index) = chosen.take() {
+            let server = &servers[index];
+            return Some((server.address.to_string(), server.announcement.transport));
+        }
+        if let Some(found) = receiver.try_iter().last() {
+            servers = found;
+            buttons = rows(&servers, &chosen);
+            searched = true;
+        }
+
+        canvas.set_draw_color(Color::RGB(20, 20, 40));
+        canvas.clear();
+        draw_text(
+            &mut canvas,
+            &font,
+            &texture_creator,
+            "Servers on the local network",
+            50,
+            30,
+        );
+        let status = match (searched, servers.is_empty()) {
+            (false, _) => "Looking for servers...",
+            (true, true) => "No servers found, still looking. Esc to quit.",
+            (true, false) => "Click a server to join it. Esc to quit.",
+        };
+        draw_text(
+            &mut canvas,
+            &small_font,
+            &texture_creator,
+            status,
+            50,
+            SCREEN_HEIGHT as i32 - 40,
+        );
+        for button in &mut buttons {
+            button.draw(
+                &mut canvas,
+                &ttf_context,
+                &texture_creator,
+                &mut texture_map,
+            );
+        }
+        canvas.present();
+        thread::sleep(Duration::from_millis(16));
+    }
+}
+
+fn draw_text(
+    canvas: &mut Canvas<Window>,
+    font: &ttf::Font,
+    texture_creator: &TextureCreator<WindowContext>,
+    text: &str,
+    x: i32,
+    y: i32,
+) {
+    let surface = font.render(text).blended(Color::WHITE).unwrap();
+    let texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .unwrap();
+    let query = texture.query();
+    canvas
+        .copy(&texture, None, Rect::new(x, y, query.width, query.height))
+        .unwrap();
+}
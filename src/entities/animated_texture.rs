@@ -68,6 +68,7 @@ impl<'a> AnimatedTexture {
             .unwrap();
     }
 
+    // only describes the frames, textures are loaded separately with load_textures
     pub fn load_animation(
         &mut self,
         path: String,
@@ -76,8 +77,6 @@ impl<'a> AnimatedTexture {
         width: u32,
         height: u32,
         frame_count: u32,
-        texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
-        texture_map: &mut std::collections::HashMap<String, sdl2::render::Texture<'a>>,
     ) {
         for i in 0..frame_count {
             let mut frame = TextureData::new(path.clone());
@@ -85,8 +84,17 @@ impl<'a> AnimatedTexture {
             frame.y = start_y as u32;
             frame.width = width;
             frame.height = height;
-            frame.load_texture(texture_creator, texture_map);
             self.frames.push(frame);
         }
     }
+
+    pub fn load_textures(
+        &mut self,
+        texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
+        texture_map: &mut std::collections::HashMap<String, sdl2::render::Texture<'a>>,
+    ) {
+        for frame in &mut self.frames {
+            frame.load_texture(texture_creator, texture_map);
+        }
+    }
 }
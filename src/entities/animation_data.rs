@@ -1,6 +1,6 @@
 use sdl2::{
-    render::{Canvas, Texture},
-    video::Window,
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
 };
 use serde::{Deserialize, Serialize};
 
@@ -16,6 +16,15 @@ pub enum AnimationState {
     Default,
 }
 
+// the way the player last walked, idling doesn't change it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Facing {
+    Front,
+    Back,
+    Left,
+    Right,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct AnimationData {
     pub front: Option<AnimatedTexture>,
@@ -25,6 +34,7 @@ pub struct AnimationData {
     pub idle: Option<AnimatedTexture>,
     pub default: Option<AnimatedTexture>,
     pub current_animation: AnimationState,
+    pub facing: Facing,
 }
 impl AnimationData {
     pub fn new() -> AnimationData {
@@ -36,9 +46,62 @@ impl AnimationData {
             idle: None,
             default: None,
             current_animation: AnimationState::Default,
+            facing: Facing::Front,
+        }
+    }
+
+    pub fn set_state(&mut self, state: AnimationState) {
+        match state {
+            AnimationState::Front => self.facing = Facing::Front,
+            AnimationState::Back => self.facing = Facing::Back,
+            AnimationState::Left => self.facing = Facing::Left,
+            AnimationState::Right => self.facing = Facing::Right,
+            AnimationState::Idle | AnimationState::Default => (),
+        }
+        self.current_animation = state;
+    }
+
+    fn animation(&self, state: &AnimationState) -> Option<&AnimatedTexture> {
+        match state {
+            AnimationState::Front => self.front.as_ref(),
+            AnimationState::Back => self.back.as_ref(),
+            AnimationState::Left => self.left.as_ref(),
+            AnimationState::Right => self.right.as_ref(),
+            AnimationState::Idle => self.idle.as_ref(),
+            AnimationState::Default => self.default.as_ref(),
+        }
+    }
+
+    fn animation_mut(&mut self, state: &AnimationState) -> Option<&mut AnimatedTexture> {
+        match state {
+            AnimationState::Front => self.front.as_mut(),
+            AnimationState::Back => self.back.as_mut(),
+            AnimationState::Left => self.left.as_mut(),
+            AnimationState::Right => self.right.as_mut(),
+            AnimationState::Idle => self.idle.as_mut(),
+            AnimationState::Default => self.default.as_mut(),
         }
     }
 
+    // frame of the current animation, 0 if it isn't loaded
+    pub fn frame(&self) -> u16 {
+        self.animation(&self.current_animation)
+            .map_or(0, |animation| animation.current_frame as u16)
+    }
+
+    // shows what the server says instead of animating on its own,
+    // frames past the end of the loaded animation are clamped
+    pub fn apply(&mut self, state: AnimationState, frame: u16, facing: Facing) {
+        if let Some(animation) = self.animation_mut(&state) {
+            let last = animation.frames.len().saturating_sub(1);
+            animation.current_frame = (frame as usize).min(last) as i32;
+            animation.previous_frame = animation.current_frame;
+            animation.current_time = 0.0;
+        }
+        self.current_animation = state;
+        self.facing = facing;
+    }
+
     pub fn draw(
         &self,
         canvas: &mut Canvas<Window>,
@@ -101,14 +164,58 @@ impl AnimationData {
         }
     }
 
+    pub fn load_textures<'a>(
+        &mut self,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        texture_map: &mut std::collections::HashMap<String, Texture<'a>>,
+    ) {
+        for animation in [
+            &mut self.front,
+            &mut self.back,
+            &mut self.left,
+            &mut self.right,
+            &mut self.idle,
+            &mut self.default,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            animation.load_textures(texture_creator, texture_map);
+        }
+    }
+
     pub fn update(&mut self, dt: f64) {
         match self.current_animation {
-            AnimationState::Default => if let Some(ref mut anim) = self.default { anim.update(dt) },
-            AnimationState::Front => if let Some(ref mut anim) = self.front { anim.update(dt) },
-            AnimationState::Back => if let Some(ref mut anim) = self.back { anim.update(dt) },
-            AnimationState::Left => if let Some(ref mut anim) = self.left { anim.update(dt) },
-            AnimationState::Right => if let Some(ref mut anim) = self.right { anim.update(dt) },
-            AnimationState::Idle => if let Some(ref mut anim) = self.idle { anim.update(dt) },
+            AnimationState::Default => {
+                if let Some(ref mut anim) = self.default {
+                    anim.update(dt)
+                }
+            }
+            AnimationState::Front => {
+                if let Some(ref mut anim) = self.front {
+                    anim.update(dt)
+                }
+            }
+            AnimationState::Back => {
+                if let Some(ref mut anim) = self.back {
+                    anim.update(dt)
+                }
+            }
+            AnimationState::Left => {
+                if let Some(ref mut anim) = self.left {
+                    anim.update(dt)
+                }
+            }
+            AnimationState::Right => {
+                if let Some(ref mut anim) = self.right {
+                    anim.update(dt)
+                }
+            }
+            AnimationState::Idle => {
+                if let Some(ref mut anim) = self.idle {
+                    anim.update(dt)
+                }
+            }
         }
     }
 }
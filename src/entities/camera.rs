@@ -1,4 +1,27 @@
 //use sdl2::{event::Event, keyboard::Keycode};
+use crate::entities::player::Player;
+
+const FLY_SPEED: f64 = 600.0; // pixels per second, for spectators
+
+// what a spectator's camera looks at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraTarget {
+    Free,
+    Player(u64),
+}
+
+impl CameraTarget {
+    // the next player by id, after the last one the camera is free again
+    pub fn next(self, players: &[u64]) -> CameraTarget {
+        let mut players = players.to_vec();
+        players.sort();
+        let next = match self {
+            CameraTarget::Free => players.first(),
+            CameraTarget::Player(current) => players.iter().find(|id| **id > current),
+        };
+        next.map_or(CameraTarget::Free, |id| CameraTarget::Player(*id))
+    }
+}
 
 pub struct Camera {
     pub x: f64,
@@ -18,6 +41,22 @@ impl Camera {
             //zoom : 1.0
         }
     }
+
+    pub fn center_on(&mut self, player: &Player) {
+        self.x = player.x + (player.size_x as i32 / 2 - self.width as i32 / 2) as f64;
+        self.y = player.y + (player.size_y as i32 / 2 - self.height as i32 / 2) as f64;
+    }
+
+    pub fn center_at(&mut self, x: f64, y: f64) {
+        self.x = x - (self.width / 2) as f64;
+        self.y = y - (self.height / 2) as f64;
+    }
+
+    // a free camera moves like a player would, without walls
+    pub fn fly(&mut self, move_x: i8, move_y: i8, dt: f64) {
+        self.x += move_x as f64 * FLY_SPEED * dt;
+        self.y += move_y as f64 * FLY_SPEED * dt;
+    }
     /*
     pub fn apply_zoom(&self, size: f32) -> f32 {
         size * self.zoom
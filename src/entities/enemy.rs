@@ -1,8 +1,9 @@
-use rand::seq::IndexedRandom;
+use rand::{Rng, seq::IndexedRandom};
 use sdl2::{
     render::{Canvas, Texture, TextureCreator},
     video::{Window, WindowContext},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
     entities::{
@@ -15,11 +16,9 @@ use crate::{
     },
     environment::{aabb::AABB, level::Level},
 };
-use std::{
-    collections::{HashMap, VecDeque},
-    time::Instant,
-};
+use std::collections::{HashMap, VecDeque};
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum EnemyType {
     Slime,
     Stonewalker,
@@ -28,7 +27,21 @@ pub enum EnemyType {
     Placeholder,
 }
 
+impl EnemyType {
+    // as written in maps
+    pub fn from_name(name: &str) -> Option<EnemyType> {
+        match name.to_lowercase().as_str() {
+            "slime" => Some(EnemyType::Slime),
+            "stonewalker" => Some(EnemyType::Stonewalker),
+            "wizard" => Some(EnemyType::Wizard),
+            "skull" => Some(EnemyType::Skull),
+            _ => None,
+        }
+    }
+}
+
 pub struct Enemy {
+    pub id: u64,
     pub x: f64,
     pub y: f64,
     pub animation_data: Option<AnimationData>,
@@ -45,11 +58,7 @@ pub struct Enemy {
 }
 
 impl Enemy {
-    pub fn new<'a>(
-        kind: EnemyType,
-        texture_creator: &'a TextureCreator<WindowContext>,
-        texture_map: &mut std::collections::HashMap<String, Texture<'a>>,
-    ) -> Enemy {
+    pub fn new(kind: EnemyType) -> Enemy {
         let mut ani_data: Option<AnimationData> = None;
         let mut size_x = 50;
         let mut size_y = 50;
@@ -64,16 +73,7 @@ impl Enemy {
                     .front
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/slime.png".to_string(),
-                        0,
-                        0,
-                        16,
-                        16,
-                        3,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/slime.png".to_string(), 0, 0, 16, 16, 3);
                 ani_data.as_mut().unwrap().current_animation = AnimationState::Front;
                 ani_data
                     .as_mut()
@@ -90,16 +90,7 @@ impl Enemy {
                     .default
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/slime.png".to_string(),
-                        0,
-                        0,
-                        16,
-                        16,
-                        1,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/slime.png".to_string(), 0, 0, 16, 16, 1);
             }
             EnemyType::Stonewalker => {
                 ani_data = Some(AnimationData::new());
@@ -117,8 +108,6 @@ impl Enemy {
                         16,
                         16,
                         4,
-                        texture_creator,
-                        texture_map,
                     );
                 ani_data.as_mut().unwrap().current_animation = AnimationState::Front;
 
@@ -136,8 +125,6 @@ impl Enemy {
                         16,
                         16,
                         1,
-                        texture_creator,
-                        texture_map,
                     );
             }
             EnemyType::Wizard => {
@@ -152,16 +139,7 @@ impl Enemy {
                     .idle
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/wizard.png".to_string(),
-                        0,
-                        0,
-                        32,
-                        64,
-                        1,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/wizard.png".to_string(), 0, 0, 32, 64, 1);
 
                 ani_data.as_mut().unwrap().front = Some(AnimatedTexture::new(1.0 / 5.0));
                 ani_data
@@ -170,16 +148,7 @@ impl Enemy {
                     .front
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/wizard.png".to_string(),
-                        0,
-                        0,
-                        32,
-                        64,
-                        6,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/wizard.png".to_string(), 0, 0, 32, 64, 6);
 
                 ani_data.as_mut().unwrap().right = Some(AnimatedTexture::new(1.0 / 5.0));
                 ani_data
@@ -188,16 +157,7 @@ impl Enemy {
                     .right
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/wizard.png".to_string(),
-                        0,
-                        64,
-                        32,
-                        64,
-                        6,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/wizard.png".to_string(), 0, 64, 32, 64, 6);
 
                 ani_data.as_mut().unwrap().left = Some(AnimatedTexture::new(1.0 / 5.0));
                 ani_data
@@ -213,8 +173,6 @@ impl Enemy {
                         32,
                         64,
                         6,
-                        texture_creator,
-                        texture_map,
                     );
 
                 ani_data.as_mut().unwrap().back = Some(AnimatedTexture::new(1.0 / 5.0));
@@ -231,8 +189,6 @@ impl Enemy {
                         32,
                         64,
                         6,
-                        texture_creator,
-                        texture_map,
                     );
 
                 ani_data.as_mut().unwrap().default = Some(AnimatedTexture::new(1.0));
@@ -242,16 +198,7 @@ impl Enemy {
                     .default
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/wizard.png".to_string(),
-                        0,
-                        0,
-                        32,
-                        64,
-                        1,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/wizard.png".to_string(), 0, 0, 32, 64, 1);
             }
             EnemyType::Skull => {
                 size_x = 32 * 2;
@@ -265,16 +212,7 @@ impl Enemy {
                     .idle
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/skull.png".to_string(),
-                        0,
-                        0,
-                        32,
-                        32,
-                        1,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/skull.png".to_string(), 0, 0, 32, 32, 1);
 
                 ani_data.as_mut().unwrap().front = Some(AnimatedTexture::new(1.0 / 5.0));
                 ani_data
@@ -283,16 +221,7 @@ impl Enemy {
                     .front
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/skull.png".to_string(),
-                        0,
-                        0,
-                        32,
-                        32,
-                        3,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/skull.png".to_string(), 0, 0, 32, 32, 3);
                 ani_data
                     .as_mut()
                     .unwrap()
@@ -308,16 +237,7 @@ impl Enemy {
                     .right
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/skull.png".to_string(),
-                        0,
-                        32,
-                        32,
-                        32,
-                        3,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/skull.png".to_string(), 0, 32, 32, 32, 3);
                 ani_data
                     .as_mut()
                     .unwrap()
@@ -333,16 +253,7 @@ impl Enemy {
                     .left
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/skull.png".to_string(),
-                        0,
-                        64,
-                        32,
-                        32,
-                        3,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/skull.png".to_string(), 0, 64, 32, 32, 3);
                 ani_data
                     .as_mut()
                     .unwrap()
@@ -358,16 +269,7 @@ impl Enemy {
                     .back
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/skull.png".to_string(),
-                        0,
-                        96,
-                        32,
-                        32,
-                        3,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/skull.png".to_string(), 0, 96, 32, 32, 3);
                 ani_data
                     .as_mut()
                     .unwrap()
@@ -383,16 +285,7 @@ impl Enemy {
                     .default
                     .as_mut()
                     .unwrap()
-                    .load_animation(
-                        "resources/enemies/skull.png".to_string(),
-                        0,
-                        0,
-                        32,
-                        32,
-                        1,
-                        texture_creator,
-                        texture_map,
-                    );
+                    .load_animation("resources/enemies/skull.png".to_string(), 0, 0, 32, 32, 1);
             }
             EnemyType::Placeholder => {}
         }
@@ -405,6 +298,7 @@ impl Enemy {
         };
 
         Enemy {
+            id: 0,
             x: 1000.,
             y: 1000.,
             animation_data: ani_data,
@@ -425,6 +319,16 @@ impl Enemy {
         }
     }
 
+    pub fn load_textures<'a>(
+        &mut self,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        texture_map: &mut std::collections::HashMap<String, Texture<'a>>,
+    ) {
+        if let Some(ref mut animation_data) = self.animation_data {
+            animation_data.load_textures(texture_creator, texture_map);
+        }
+    }
+
     pub fn draw(
         &self,
         canvas: &mut Canvas<Window>,
@@ -460,17 +364,25 @@ impl Enemy {
         &mut self,
         dt: f64,
         level: &Level,
-        player: &Player,
-        instant: &Instant,
+        players: &[Player],
+        time: f64,
         projectiles: &mut Vec<Projectile>,
+        rng: &mut impl Rng,
     ) {
-        if let Some(ref mut animation_data) = self.animation_data {
-            animation_data.update(dt);
+        self.animate(dt);
+
+        // chase whichever living player is closest
+        let Some(player) = players.iter().filter(|p| p.health > 0).min_by(|a, b| {
+            self.distance_to(a)
+                .partial_cmp(&self.distance_to(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }) else {
+            return;
         };
 
         // enemy behaviour depends on type
-        let distance_to_player = ((self.x - player.x).powi(2) + (self.y - player.y).powi(2)).sqrt();
-        let can_move = instant.elapsed().as_secs_f64() - self.last_time > 0.5;
+        let distance_to_player = self.distance_to(player);
+        let can_move = time - self.last_time > 0.5;
 
         if !self.spotted_player {
             match self.kind {
@@ -491,7 +403,7 @@ impl Enemy {
                     if self.spotted_player {
                         self.dir = self.calculate_player_direction(level, player);
                     } else {
-                        self.choose_random_move(level);
+                        self.choose_random_move(level, rng);
                     }
                 }
                 EnemyType::Wizard => {
@@ -513,12 +425,12 @@ impl Enemy {
                             self.dir = self.calculate_player_direction(level, player);
                         }
                     } else {
-                        self.choose_random_move(level);
+                        self.choose_random_move(level, rng);
                     }
                 }
                 _ => {}
             }
-            self.last_time = instant.elapsed().as_secs_f64();
+            self.last_time = time;
         }
         // move in the chosen direction
         match self.dir {
@@ -584,7 +496,24 @@ impl Enemy {
         self.hitbox.y = self.y + 5.;
     }
 
-    pub fn choose_random_move(&mut self, level: &Level) {
+    pub fn set_position(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+        self.hitbox.x = self.x + 5.;
+        self.hitbox.y = self.y + 5.;
+    }
+
+    pub fn animate(&mut self, dt: f64) {
+        if let Some(ref mut animation_data) = self.animation_data {
+            animation_data.update(dt);
+        };
+    }
+
+    fn distance_to(&self, player: &Player) -> f64 {
+        ((self.x - player.x).powi(2) + (self.y - player.y).powi(2)).sqrt()
+    }
+
+    pub fn choose_random_move(&mut self, level: &Level, rng: &mut impl Rng) {
         let mut possible_moves = Vec::new();
         if self.can_move_to_tile(
             level,
@@ -610,7 +539,7 @@ impl Enemy {
         ) {
             possible_moves.push(3);
         }
-        match possible_moves.choose(&mut rand::rng()) {
+        match possible_moves.choose(rng) {
             Some(&0) => self.dir = 0,
             Some(&1) => self.dir = 1,
             Some(&2) => self.dir = 2,
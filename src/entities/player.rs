@@ -1,12 +1,11 @@
 use crate::entities::animation_data::{AnimationData, AnimationState};
 use crate::entities::{animated_texture::AnimatedTexture, camera::Camera, enemy::Enemy};
 use crate::environment::{aabb::AABB, level::Level, tile_type::ExitTile};
-use crate::networking::packet::Packet;
 use crate::networking::shared::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::simulation::input::PlayerInput;
 use sdl2::render::Texture;
 use sdl2::render::{Canvas, TextureCreator};
 use sdl2::video::{Window, WindowContext};
-use std::time::Instant;
 
 pub enum PlayerHitState {
     Invincible,
@@ -27,11 +26,6 @@ pub struct Player {
     speed: f64,
     pub reached_end: Option<ExitTile>,
 
-    pub pressed_up: bool,
-    pub pressed_down: bool,
-    pub pressed_left: bool,
-    pub pressed_right: bool,
-
     pub current_level: String,
     pub hit_state: PlayerHitState,
     pub health: i32,
@@ -39,6 +33,7 @@ pub struct Player {
     last_moved_time: f64,
     invicibility_blinks: i32,
     last_blink_time: f64,
+    pub last_shot_time: f64,
     pub moved: bool,
 }
 
@@ -63,26 +58,19 @@ impl Player {
             colliding: false,
             speed: 250.0,
             reached_end: None,
-            pressed_up: false,
-            pressed_down: false,
-            pressed_left: false,
-            pressed_right: false,
             current_level: String::new(),
             hit_state: PlayerHitState::Vulnerable,
             health: 100,
             last_hit_time: 0.0,
             invicibility_blinks: 0,
             last_blink_time: 0.0,
+            last_shot_time: f64::NEG_INFINITY,
             moved: false,
             last_moved_time: 0.0,
         }
     }
 
-    pub fn load_player_texture<'a>(
-        &mut self,
-        texture_creator: &'a TextureCreator<WindowContext>,
-        texture_map: &mut std::collections::HashMap<String, Texture<'a>>,
-    ) {
+    pub fn load_player_texture(&mut self) {
         self.animation_data.front = Some(AnimatedTexture::new(1.0 / 12.));
         self.animation_data.front.as_mut().unwrap().load_animation(
             "resources/player_animation/pretnar_spritesheet.png".to_string(),
@@ -91,8 +79,6 @@ impl Player {
             32,
             48,
             6,
-            texture_creator,
-            texture_map,
         );
         self.animation_data.right = Some(AnimatedTexture::new(1.0 / 12.));
         self.animation_data.right.as_mut().unwrap().load_animation(
@@ -102,8 +88,6 @@ impl Player {
             32,
             48,
             6,
-            texture_creator,
-            texture_map,
         );
         self.animation_data.left = Some(AnimatedTexture::new(1.0 / 12.));
         self.animation_data.left.as_mut().unwrap().load_animation(
@@ -113,8 +97,6 @@ impl Player {
             32,
             48,
             6,
-            texture_creator,
-            texture_map,
         );
         self.animation_data.back = Some(AnimatedTexture::new(1.0 / 12.));
         self.animation_data.back.as_mut().unwrap().load_animation(
@@ -124,8 +106,6 @@ impl Player {
             32,
             48,
             6,
-            texture_creator,
-            texture_map,
         );
         self.animation_data.default = Some(AnimatedTexture::new(1.0));
         self.animation_data
@@ -139,8 +119,6 @@ impl Player {
                 32,
                 48,
                 1,
-                texture_creator,
-                texture_map,
             );
         self.animation_data.idle = Some(AnimatedTexture::new(1.0 / 3.0));
         self.animation_data.idle.as_mut().unwrap().load_animation(
@@ -150,18 +128,47 @@ impl Player {
             32,
             48,
             6,
-            texture_creator,
-            texture_map,
         );
     }
 
+    pub fn load_textures<'a>(
+        &mut self,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        texture_map: &mut std::collections::HashMap<String, Texture<'a>>,
+    ) {
+        self.animation_data
+            .load_textures(texture_creator, texture_map);
+    }
+
     pub fn reset_velocity(&mut self) {
         self.velocity_x = 0.0;
         self.velocity_y = 0.0;
-        self.pressed_down = false;
-        self.pressed_left = false;
-        self.pressed_right = false;
-        self.pressed_up = false;
+    }
+
+    pub fn apply_input(&mut self, input: &PlayerInput) {
+        self.velocity_x = input.move_x as f64 * self.speed;
+        self.velocity_y = input.move_y as f64 * self.speed;
+    }
+
+    // losing health makes the player blink for a while
+    pub fn set_health(&mut self, health: i32, time: f64) {
+        if health < self.health {
+            self.hit_state = PlayerHitState::Invincible;
+            self.last_hit_time = time;
+        }
+        self.health = health;
+    }
+
+    pub fn set_position(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+        self.hitbox.x = self.x + 20.;
+        self.hitbox.y = self.y + 76.;
+    }
+
+    pub fn spawn_at(&mut self, x: i32, y: i32) {
+        self.set_position(x as f64, y as f64);
+        self.reached_end = None;
     }
 
     pub fn draw(
@@ -169,21 +176,20 @@ impl Player {
         canvas: &mut Canvas<Window>,
         texture_map: &std::collections::HashMap<String, Texture>,
         camera: &Camera,
-        global_clock: &Instant,
+        time: f64,
     ) {
         match self.hit_state {
             PlayerHitState::Invincible => {
-                let time_since_last_blink =
-                    global_clock.elapsed().as_secs_f64() - self.last_blink_time;
+                let time_since_last_blink = time - self.last_blink_time;
                 if time_since_last_blink < 0.1 {
-                    return ;
+                    return;
                 }
                 let mut draw = false;
-                let time_since_hit = global_clock.elapsed().as_secs_f64() - self.last_hit_time;
+                let time_since_hit = time - self.last_hit_time;
                 for i in 0..4 {
                     if self.invicibility_blinks <= i && time_since_hit > (i as f64) / 4. {
                         self.invicibility_blinks += 1;
-                        self.last_blink_time = global_clock.elapsed().as_secs_f64();
+                        self.last_blink_time = time;
                         draw = true;
                     }
                 }
@@ -211,17 +217,9 @@ impl Player {
         }
     }
 
-    pub fn update(
-        &mut self,
-        dt: f64,
-        _tx: &std::sync::mpsc::Sender<Packet>,
-        level: &Level,
-        camera: &mut Camera,
-        enemies: &Vec<Enemy>,
-        global_clock: &Instant,
-    ) {
+    pub fn update(&mut self, dt: f64, level: &Level, enemies: &[Enemy], time: f64) {
         if self.id == 1_000_000 {
-            return ;
+            return;
         }
 
         self.moved = false;
@@ -244,19 +242,19 @@ impl Player {
 
         if self.moved {
             if self.velocity_x > 0.0 {
-                self.animation_data.current_animation = AnimationState::Right;
+                self.animation_data.set_state(AnimationState::Right);
             } else if self.velocity_x < 0.0 {
-                self.animation_data.current_animation = AnimationState::Left;
+                self.animation_data.set_state(AnimationState::Left);
             } else if self.velocity_y > 0.0 {
-                self.animation_data.current_animation = AnimationState::Front;
+                self.animation_data.set_state(AnimationState::Front);
             } else if self.velocity_y < 0.0 {
-                self.animation_data.current_animation = AnimationState::Back;
+                self.animation_data.set_state(AnimationState::Back);
             }
             self.animation_data.update(dt);
-            self.last_moved_time = global_clock.elapsed().as_secs_f64();
-        } else if self.last_moved_time + 5.0 < global_clock.elapsed().as_secs_f64() {
+            self.last_moved_time = time;
+        } else if self.last_moved_time + 5.0 < time {
             self.animation_data.update(dt);
-            self.animation_data.current_animation = AnimationState::Idle;
+            self.animation_data.set_state(AnimationState::Idle);
         }
 
         let collisions = level.check_collision(&self.hitbox);
@@ -268,17 +266,15 @@ impl Player {
         }
 
         for enemy in enemies {
-            if self.hitbox.intersects(&enemy.hitbox) {
-                if let PlayerHitState::Vulnerable = self.hit_state {
-                    self.hit_state = PlayerHitState::Invincible;
-                    self.health -= 15;
-                    println!("Health : {}", self.health);
-                    self.last_hit_time = global_clock.elapsed().as_secs_f64();
-                }
+            if self.hitbox.intersects(&enemy.hitbox)
+                && let PlayerHitState::Vulnerable = self.hit_state
+            {
+                self.set_health(self.health - 15, time);
+                println!("Health : {}", self.health);
             }
         }
 
-        if global_clock.elapsed().as_secs_f64() - self.last_hit_time > 1.0 {
+        if time - self.last_hit_time > 1.0 {
             self.hit_state = PlayerHitState::Vulnerable;
             self.invicibility_blinks = 0;
         }
@@ -286,76 +282,5 @@ impl Player {
         level.resolve_collision(&mut self.hitbox);
         self.x = self.hitbox.x - 20.;
         self.y = self.hitbox.y - 76.;
-        //let send = Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(PlayerPosition{x : self.x, y : self.y, player_id: self.id}));
-        //tx.send(send).unwrap();
-
-        camera.x = self.x + (self.size_x as i32 / 2 - SCREEN_WIDTH as i32 / 2) as f64;
-        camera.y = self.y + (self.size_y as i32 / 2 - SCREEN_HEIGHT as i32 / 2) as f64;
-    }
-
-    pub fn on_event(&mut self, event: &sdl2::event::Event) {
-        match event {
-            sdl2::event::Event::KeyDown {
-                keycode: Some(keycode),
-                ..
-            } => match *keycode {
-                sdl2::keyboard::Keycode::Up | sdl2::keyboard::Keycode::W => {
-                    self.velocity_y = -self.speed;
-                    self.pressed_up = true;
-                }
-                sdl2::keyboard::Keycode::Down | sdl2::keyboard::Keycode::S => {
-                    self.velocity_y = self.speed;
-                    self.pressed_down = true;
-                }
-                sdl2::keyboard::Keycode::Left | sdl2::keyboard::Keycode::A => {
-                    self.velocity_x = -self.speed;
-                    self.pressed_left = true;
-                }
-                sdl2::keyboard::Keycode::Right | sdl2::keyboard::Keycode::D => {
-                    self.velocity_x = self.speed;
-                    self.pressed_right = true;
-                }
-                _ => (),
-            },
-            sdl2::event::Event::KeyUp {
-                keycode: Some(keycode),
-                ..
-            } => match *keycode {
-                sdl2::keyboard::Keycode::Up | sdl2::keyboard::Keycode::W => {
-                    self.pressed_up = false;
-                    if self.pressed_down {
-                        self.velocity_y = self.speed;
-                    } else {
-                        self.velocity_y = 0.0;
-                    }
-                }
-                sdl2::keyboard::Keycode::Down | sdl2::keyboard::Keycode::S => {
-                    self.pressed_down = false;
-                    if self.pressed_up {
-                        self.velocity_y = -self.speed;
-                    } else {
-                        self.velocity_y = 0.0;
-                    }
-                }
-                sdl2::keyboard::Keycode::Left | sdl2::keyboard::Keycode::A => {
-                    self.pressed_left = false;
-                    if self.pressed_right {
-                        self.velocity_x = self.speed;
-                    } else {
-                        self.velocity_x = 0.0;
-                    }
-                }
-                sdl2::keyboard::Keycode::Right | sdl2::keyboard::Keycode::D => {
-                    self.pressed_right = false;
-                    if self.pressed_left {
-                        self.velocity_x = -self.speed;
-                    } else {
-                        self.velocity_x = 0.0;
-                    }
-                }
-                _ => (),
-            },
-            _ => (),
-        }
     }
 }
@@ -4,7 +4,7 @@
 /// poškoduje igralca ali sovražnike, odvisno od tega, kdo ga je izstrelil.
 use crate::{
     entities::{animated_texture::AnimatedTexture, camera::Camera, enemy::Enemy, player::Player},
-    environment::{aabb::AABB, level::Level, tile_type::TileType},
+    environment::{aabb::AABB, level::Level, texture_data::TextureData, tile_type::TileType},
 };
 
 /// Struktura, ki predstavlja izstrelek.
@@ -30,47 +30,43 @@ pub struct Projectile {
     pub hitbox: AABB,
     /// Ali je izstrelek izstrelil igralec.
     pub fired_by_player: bool,
+    /// Številka, po kateri odjemalec prepozna izstrelek v zaporednih posnetkih.
+    /// Dokler ga svet ne oštevilči, je 0.
+    pub id: u64,
 }
 
 impl Projectile {
+    /// Pot do teksture, ki si jo delijo vsi izstrelki.
+    pub const TEXTURE_PATH: &'static str = "resources/textures/projectile.png";
+
     /// Ustvari nov izstrelek z danim položajem, velikostjo, smerjo in označbo, kdo ga je izstrelil.
     ///
-    /// Privzeta hitrost je 400.
+    /// Privzeta hitrost je 400. Tekstura je le opisana, naloži se z `load_textures`.
     pub fn new(x: f64, y: f64, size: u32, direction: f64, fired_by_player: bool) -> Projectile {
+        let mut texture = AnimatedTexture::new(0.1);
+        texture.load_animation(Projectile::TEXTURE_PATH.to_string(), 0, 0, 16, 16, 1);
         Projectile {
             x,
             y,
             speed: 400.0,
             size,
             direction,
-            texture: None,
+            texture: Some(texture),
             hitbox: AABB::new(x, y, size, size),
             fired_by_player,
+            id: 0,
         }
     }
 
     /// Naloži animirano teksturo izstrelka.
     ///
-    /// Pot do teksture je trenutno nastavljena na `"resources/textures/projectile.png"`.
-    pub fn load_projectile_texture<'a>(
-        &mut self,
+    /// Ker si vsi izstrelki delijo isto teksturo, jo je dovolj naložiti enkrat.
+    pub fn load_textures<'a>(
         texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
         texture_map: &mut std::collections::HashMap<String, sdl2::render::Texture<'a>>,
     ) {
-        let path = "resources/textures/projectile.png".to_string();
-        self.texture = Some(AnimatedTexture::new(0.1));
-        self.texture.as_mut().unwrap().load_animation(
-            path,
-            0,
-            0,
-            16,
-            16,
-            1,
-            texture_creator,
-            texture_map,
-        );
-        self.hitbox.x = self.x;
-        self.hitbox.y = self.y;
+        let mut texture = TextureData::new(Projectile::TEXTURE_PATH.to_string());
+        texture.load_texture(texture_creator, texture_map);
     }
 
     /// Posodobi položaj izstrelka glede na časovni zamik `dt`.
@@ -83,6 +79,14 @@ impl Projectile {
         self.hitbox.y = self.y;
     }
 
+    /// Premakne izstrelek na dani položaj, skupaj z zadetno škatlo.
+    pub fn set_position(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+        self.hitbox.x = self.x;
+        self.hitbox.y = self.y;
+    }
+
     /// Izračuna kot (v radianih) med izhodiščem in ciljno točko.
     ///
     /// Uporablja funkcijo `atan2` za pravilno določanje kvadranta.
@@ -128,14 +132,14 @@ impl Projectile {
     /// Če pride do trka:
     /// - s trdnim okoljem → `true`;
     /// - s sovražnikom (če ga je izstrelil igralec) → zmanjša zdravje sovražnika in vrne `true`;
-    /// - z igralcem (če ga ni izstrelil igralec) → zmanjša zdravje igralca in vrne `true`.
+    /// - z igralcem (če ga ni izstrelil igralec) → zmanjša zdravje prvega zadetega igralca in vrne `true`.
     ///
     /// Vrne `true`, če je prišlo do trka.
     pub fn resolve_collision(
         &self,
         level: &Level,
-        enemies: &mut Vec<Enemy>,
-        player: &mut Player,
+        enemies: &mut [Enemy],
+        players: &mut [Player],
     ) -> bool {
         let mut ret = false;
 
@@ -159,9 +163,14 @@ impl Projectile {
             }
         }
 
-        if self.hitbox.intersects(&player.hitbox) && !self.fired_by_player {
-            player.health -= 15;
-            ret = true;
+        if !self.fired_by_player {
+            if let Some(player) = players
+                .iter_mut()
+                .find(|player| self.hitbox.intersects(&player.hitbox))
+            {
+                player.health -= 15;
+                ret = true;
+            }
         }
 
         ret
@@ -0,0 +1,471 @@
+use image::{Rgba, RgbaImage};
+use rand::{SeedableRng, prelude::IndexedRandom, rngs::StdRng};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{
+    entities::enemy::EnemyType,
+    environment::{
+        level::Level,
+        map::{EnemySpawn, MapExit, MapFile, MapInfo, MapLayer, MapPoint, MapTile},
+        tile_type::TileType,
+    },
+    wfc::{OverlappingModel, Symmetry, WFCState, WfcTile},
+};
+
+pub const LEARN_USAGE: &str = "usage: learn EXAMPLE.png OUT [--width N] [--height N] \
+[--pattern N] [--symmetry none|mirror|rotate|all] [--seed N] [--next LEVEL]";
+
+// generated levels are called after their seed and floor,
+// so the server and every client can make the same one again
+const GENERATED_PREFIX: &str = "generated:";
+
+// a few tries for when the floor is too cut up
+const ATTEMPTS: usize = 20;
+
+// how far from the spawn enemies have to start, in tiles
+const ENEMY_DISTANCE: usize = 6;
+
+const ENEMY_KINDS: [EnemyType; 4] = [
+    EnemyType::Slime,
+    EnemyType::Skull,
+    EnemyType::Stonewalker,
+    EnemyType::Wizard,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationParams {
+    pub width: usize, // in tiles, without the wall around the level
+    pub height: usize,
+    pub floor: u32, // deeper floors have more enemies
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        GenerationParams {
+            width: 32,
+            height: 24,
+            floor: 0,
+        }
+    }
+}
+
+// a level in the style of an example png, filled from the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct LearnParams {
+    pub width: usize, // in tiles, without the wall around the level
+    pub height: usize,
+    pub pattern: usize, // patterns are NxN tiles of the example
+    pub symmetry: Symmetry,
+    pub seed: Option<u64>, // a random one without
+    pub next: String,      // where the exit leads
+}
+
+impl Default for LearnParams {
+    fn default() -> Self {
+        LearnParams {
+            width: 32,
+            height: 24,
+            pattern: 3,
+            symmetry: Symmetry::All,
+            seed: None,
+            next: "resources/levels/level1.json".to_string(),
+        }
+    }
+}
+
+impl LearnParams {
+    pub fn from_args(args: &[String]) -> Result<LearnParams, String> {
+        let mut params = LearnParams::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--width" => params.width = parse(arg, value()?)?,
+                "--height" => params.height = parse(arg, value()?)?,
+                "--pattern" => params.pattern = parse(arg, value()?)?,
+                "--symmetry" => {
+                    let name = value()?;
+                    params.symmetry = Symmetry::from_name(name)
+                        .ok_or_else(|| format!("invalid value for --symmetry: {}", name))?;
+                }
+                "--seed" => params.seed = Some(parse(arg, value()?)?),
+                "--next" => params.next = value()?.clone(),
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if params.width < 2 || params.height < 2 {
+            return Err("learned levels need at least 2x2 tiles".to_string());
+        }
+        Ok(params)
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
+
+pub fn generated_level_name(seed: u64, floor: u32) -> String {
+    format!("{}{}:{}", GENERATED_PREFIX, seed, floor)
+}
+
+// the seed and floor of a generated level
+pub fn parse_generated_name(name: &str) -> Option<(u64, u32)> {
+    let (seed, floor) = name.strip_prefix(GENERATED_PREFIX)?.split_once(':')?;
+    Some((seed.parse().ok()?, floor.parse().ok()?))
+}
+
+// water turns into sand, sand into grass and grass into stone and rock,
+// tiles only fit next to tiles they share a socket with, mostly grass with a few trees
+pub fn level_tileset() -> Vec<WfcTile> {
+    use TileType::{Grass, Rock, Sand, Stone, Tree, Water};
+    vec![
+        WfcTile::uniform(Water, vec![Water]),
+        WfcTile::uniform(Sand, vec![Water, Sand]),
+        WfcTile::uniform(Grass, vec![Sand, Grass]).weighted(3.0),
+        WfcTile::uniform(Tree, vec![Grass]).weighted(0.5),
+        WfcTile::uniform(Stone, vec![Grass, Rock]),
+        WfcTile::uniform(Rock, vec![Rock]),
+    ]
+}
+
+fn walkable(tile_type: &TileType) -> bool {
+    matches!(
+        tile_type,
+        TileType::Sand | TileType::Grass | TileType::Stone
+    )
+}
+
+fn solid(tile_type: &TileType) -> bool {
+    matches!(
+        tile_type,
+        TileType::Water | TileType::Tree | TileType::Rock | TileType::Wall
+    )
+}
+
+// every floor of a seed gets its own random numbers
+fn floor_seed(seed: u64, floor: u32) -> u64 {
+    seed ^ (floor as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+// steps from the start to every walkable tile it can reach
+fn distances(tiles: &[Vec<TileType>], start: (usize, usize)) -> Vec<Vec<Option<usize>>> {
+    let (width, height) = (tiles[0].len(), tiles.len());
+    let mut distance = vec![vec![None; width]; height];
+    distance[start.1][start.0] = Some(0);
+    let mut queue = VecDeque::from([start]);
+    while let Some((x, y)) = queue.pop_front() {
+        let next = distance[y][x].unwrap() + 1;
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbours {
+            if nx < width && ny < height && walkable(&tiles[ny][nx]) && distance[ny][nx].is_none() {
+                distance[ny][nx] = Some(next);
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    distance
+}
+
+// the biggest walkable area, the level is played there
+fn largest_area(tiles: &[Vec<TileType>]) -> Vec<(usize, usize)> {
+    let (width, height) = (tiles[0].len(), tiles.len());
+    let mut seen = vec![vec![false; width]; height];
+    let mut largest = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if seen[y][x] || !walkable(&tiles[y][x]) {
+                continue;
+            }
+            let area: Vec<(usize, usize)> = distances(tiles, (x, y))
+                .iter()
+                .enumerate()
+                .flat_map(|(y, row)| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|(_, distance)| distance.is_some())
+                        .map(move |(x, _)| (x, y))
+                })
+                .collect();
+            for (x, y) in &area {
+                seen[*y][*x] = true;
+            }
+            if area.len() > largest.len() {
+                largest = area;
+            }
+        }
+    }
+    largest
+}
+
+// a map with a wall around it, the spawn somewhere in the biggest walkable area
+// and the exit as far away from it as that area goes
+pub fn generate_map(seed: u64, params: GenerationParams) -> Result<MapFile, String> {
+    let mut rng = StdRng::seed_from_u64(floor_seed(seed, params.floor));
+    let (width, height) = (params.width, params.height);
+    if width < 2 || height < 2 {
+        return Err("generated levels need at least 2x2 tiles".to_string());
+    }
+    for _ in 0..ATTEMPTS {
+        let wfc = WFCState::generate(width, height, level_tileset(), &mut rng)
+            .map_err(|err| format!("can't generate floor {}: {}", params.floor, err))?;
+        let tiles = wfc.tile_types();
+        let area = largest_area(&tiles);
+        // a quarter of the level at least, or it's just a few islands
+        if area.len() < (width * height / 4).max(2) {
+            continue;
+        }
+        let spawn = *area.choose(&mut rng).unwrap();
+        let distance = distances(&tiles, spawn);
+        let exit = *area.iter().max_by_key(|(x, y)| distance[*y][*x]).unwrap();
+
+        let far_away: Vec<(usize, usize)> = area
+            .iter()
+            .copied()
+            .filter(|(x, y)| distance[*y][*x].is_some_and(|d| d >= ENEMY_DISTANCE))
+            .filter(|position| *position != exit)
+            .collect();
+        let count = (1 + params.floor as usize / 2).min(8);
+        let enemies = far_away
+            .choose_multiple(&mut rng, count)
+            .map(|(x, y)| EnemySpawn {
+                kind: *ENEMY_KINDS.choose(&mut rng).unwrap(),
+                x: *x as i32 + 1,
+                y: *y as i32 + 1,
+            })
+            .collect();
+
+        return Ok(build_map(seed, params, &tiles, spawn, exit, enemies));
+    }
+    Err(format!(
+        "couldn't generate floor {} of seed {} in {} attempts",
+        params.floor, seed, ATTEMPTS
+    ))
+}
+
+fn build_map(
+    seed: u64,
+    params: GenerationParams,
+    tiles: &[Vec<TileType>],
+    spawn: (usize, usize),
+    exit: (usize, usize),
+    enemies: Vec<EnemySpawn>,
+) -> MapFile {
+    let (width, height) = (params.width + 2, params.height + 2);
+    let mut tileset = BTreeMap::new();
+    let mut ids: HashMap<&'static str, u16> = HashMap::new();
+    let mut id_of = |tile_type: &TileType| {
+        let next_id = ids.len() as u16 + 1;
+        let id = *ids.entry(tile_type.name()).or_insert(next_id);
+        tileset.entry(id).or_insert_with(|| MapTile {
+            tile: tile_type.name().to_string(),
+            solid: solid(tile_type),
+        });
+        id
+    };
+    let wall = TileType::Wall;
+    let exit_type = TileType::from_name("exit").unwrap();
+
+    let mut ground = Vec::with_capacity(height);
+    let mut exits = Vec::with_capacity(height);
+    for y in 0..height {
+        let mut ground_row = Vec::with_capacity(width);
+        let mut exit_row = Vec::with_capacity(width);
+        for x in 0..width {
+            let inside = x > 0 && y > 0 && x < width - 1 && y < height - 1;
+            let tile_type = match inside {
+                true => &tiles[y - 1][x - 1],
+                false => &wall,
+            };
+            ground_row.push(id_of(tile_type).to_string());
+            let exit_here = (x, y) == (exit.0 + 1, exit.1 + 1);
+            exit_row.push(match exit_here {
+                true => id_of(&exit_type).to_string(),
+                false => "0".to_string(),
+            });
+        }
+        ground.push(ground_row.join(","));
+        exits.push(exit_row.join(","));
+    }
+
+    MapFile {
+        info: MapInfo {
+            name: format!("Floor {}", u64::from(params.floor) + 1),
+            properties: BTreeMap::from([
+                ("seed".to_string(), seed.to_string()),
+                ("floor".to_string(), params.floor.to_string()),
+            ]),
+        },
+        width: width as u32,
+        height: height as u32,
+        tile_size: 60,
+        tileset,
+        layers: vec![
+            MapLayer {
+                name: "ground".to_string(),
+                rows: ground,
+            },
+            MapLayer {
+                name: "exits".to_string(),
+                rows: exits,
+            },
+        ],
+        spawns: vec![MapPoint {
+            x: spawn.0 as i32 + 1,
+            y: spawn.1 as i32 + 1,
+        }],
+        enemies,
+        // every floor leads one further down, the deepest one back to itself
+        exits: vec![MapExit {
+            x: exit.0 as i32 + 1,
+            y: exit.1 as i32 + 1,
+            target: generated_level_name(seed, params.floor.saturating_add(1)),
+        }],
+    }
+}
+
+// a png level, the ground with a wall around it in the first layer
+// and the spawn and exit in the second, like the levels were painted before
+pub struct LearnedLevel {
+    pub ground: RgbaImage,
+    pub objects: RgbaImage,
+    pub next: String,
+}
+
+impl LearnedLevel {
+    // name_1.png, name_2.png and name_exits.txt, Level::load takes the first one
+    pub fn save(&self, base: &str) -> Result<String, String> {
+        let path = format!("{}_1.png", base);
+        for (image, path) in [
+            (&self.ground, &path),
+            (&self.objects, &format!("{}_2.png", base)),
+        ] {
+            image
+                .save(path)
+                .map_err(|err| format!("can't write {}: {}", path, err))?;
+        }
+        let exits = format!("{}_exits.txt", base);
+        std::fs::write(&exits, format!("{}\n", self.next))
+            .map_err(|err| format!("can't write {}: {}", exits, err))?;
+        Ok(path)
+    }
+}
+
+fn opaque(color: (u8, u8, u8)) -> Rgba<u8> {
+    Rgba([color.0, color.1, color.2, 255])
+}
+
+// the spawn and exits of the example would come up all over the level,
+// they get the colour around them instead
+fn terrain(example: &RgbaImage) -> RgbaImage {
+    let marker = |pixel: &Rgba<u8>| {
+        let color = (pixel[0], pixel[1], pixel[2]);
+        color == TileType::PLAYER_SPAWN_COLOR || color == TileType::EXIT_COLOR
+    };
+    let (width, height) = example.dimensions();
+    let mut terrain = example.clone();
+    for (x, y, pixel) in example.enumerate_pixels() {
+        if !marker(pixel) {
+            continue;
+        }
+        let mut around: Vec<(Rgba<u8>, usize)> = Vec::new();
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbours {
+            if nx >= width || ny >= height || marker(example.get_pixel(nx, ny)) {
+                continue;
+            }
+            let neighbour = *example.get_pixel(nx, ny);
+            match around.iter_mut().find(|(colour, _)| *colour == neighbour) {
+                Some((_, count)) => *count += 1,
+                None => around.push((neighbour, 1)),
+            }
+        }
+        let most = around.iter().max_by_key(|(_, count)| *count);
+        terrain.put_pixel(
+            x,
+            y,
+            most.map_or(opaque(TileType::WALL_COLOR), |(colour, _)| *colour),
+        );
+    }
+    terrain
+}
+
+// a new level made of the example's patterns, with the spawn and exit placed like on generated floors
+pub fn learn_level(
+    example: &RgbaImage,
+    seed: u64,
+    params: &LearnParams,
+) -> Result<LearnedLevel, String> {
+    let model = OverlappingModel::learn(&terrain(example), params.pattern, params.symmetry)
+        .map_err(|err| format!("can't learn from the example: {}", err))?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (width, height) = (params.width, params.height);
+    for _ in 0..ATTEMPTS {
+        let image = model
+            .generate(width, height, &mut rng)
+            .map_err(|err| format!("can't generate a level like the example: {}", err))?;
+        // solid pixels and colours that aren't tiles are walls to walk around
+        let tiles: Vec<Vec<TileType>> = (0..height as u32)
+            .map(|y| {
+                (0..width as u32)
+                    .map(|x| {
+                        let pixel = image.get_pixel(x, y);
+                        match TileType::from_color((pixel[0], pixel[1], pixel[2])) {
+                            Some(tile_type) if pixel[3] < 128 => tile_type,
+                            _ => TileType::Wall,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let area = largest_area(&tiles);
+        if area.len() < (width * height / 4).max(2) {
+            continue;
+        }
+        let spawn = *area.choose(&mut rng).unwrap();
+        let distance = distances(&tiles, spawn);
+        let exit = *area.iter().max_by_key(|(x, y)| distance[*y][*x]).unwrap();
+
+        let (full_width, full_height) = (width as u32 + 2, height as u32 + 2);
+        let mut ground =
+            RgbaImage::from_pixel(full_width, full_height, opaque(TileType::WALL_COLOR));
+        image::imageops::replace(&mut ground, &image, 1, 1);
+        let mut objects = RgbaImage::new(full_width, full_height);
+        objects.put_pixel(
+            spawn.0 as u32 + 1,
+            spawn.1 as u32 + 1,
+            opaque(TileType::PLAYER_SPAWN_COLOR),
+        );
+        objects.put_pixel(
+            exit.0 as u32 + 1,
+            exit.1 as u32 + 1,
+            opaque(TileType::EXIT_COLOR),
+        );
+        return Ok(LearnedLevel {
+            ground,
+            objects,
+            next: params.next.clone(),
+        });
+    }
+    Err(format!(
+        "couldn't learn a level with enough room to walk in {} attempts",
+        ATTEMPTS
+    ))
+}
+
+impl Level {
+    pub fn generate(seed: u64, params: GenerationParams) -> Result<Level, String> {
+        Level::from_map(&generate_map(seed, params)?)
+    }
+}
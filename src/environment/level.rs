@@ -1,31 +1,87 @@
 use crate::environment::autotiler::TileSetType;
-use ::image::RgbaImage;
 use sdl2::render::{Texture, TextureCreator};
-use std::{collections::HashMap, io::BufRead};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path};
 
 use crate::{
-    entities::{camera::Camera, point::Point},
+    entities::{camera::Camera, enemy::EnemyType, point::Point},
     environment::{
         aabb::AABB,
         autotiler::Autotiler,
+        generator::{GenerationParams, parse_generated_name},
+        map::MapFile,
         texture_data::TextureData,
         tile::Tile,
         tile_type::{ExitTile, TileType},
+        tiled::load_tiled,
     },
 };
 
+// where the levels clients may ask for live
+pub const LEVELS_DIR: &str = "resources/levels";
+
+// generated levels and level files in LEVELS_DIR, nothing a client could use to reach other files
+pub fn is_known_level(path: &str) -> bool {
+    if parse_generated_name(path).is_some() {
+        return true;
+    }
+    let path = Path::new(path);
+    path.starts_with(LEVELS_DIR)
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        && path.is_file()
+}
+
 pub struct Level {
     pub tiles: Vec<HashMap<Point<i32>, Tile>>, // vector for each layer, hashmap for fast position queries
     pub player_spawn: (i32, i32),
+    pub spawn_points: Vec<(i32, i32)>,
+    pub enemy_spawns: Vec<(EnemyType, f64, f64)>, // only the server spawns enemies
+    pub name: String,
     pub tile_size: i32,
     pub autotiler: Autotiler,
 }
 
+// everything a client needs to play a level without having its files,
+// textures are stored once and tiles point to them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LevelData {
+    pub tile_size: i32,
+    pub player_spawn: (i32, i32),
+    pub spawn_points: Vec<(i32, i32)>,
+    pub textures: Vec<TextureData>,
+    pub layers: Vec<Vec<TileData>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileData {
+    pub x: i32, // in tiles, not pixels
+    pub y: i32,
+    pub tile_type: TileType, // exits know where they lead
+    pub texture: Option<u32>,
+    pub solid: bool,
+}
+
+// exits only trigger in their middle
+fn exit_bounding_box(x: i32, y: i32, tile_size: i32) -> Option<AABB> {
+    Some(AABB::new(
+        (x * tile_size + tile_size / 4) as f64,
+        (y * tile_size + tile_size / 4) as f64,
+        tile_size as u32 / 2,
+        tile_size as u32 / 2,
+    ))
+}
+
 impl<'a> Level {
     pub fn new() -> Level {
         Level {
             tiles: Vec::new(),
             player_spawn: (0, 0),
+            spawn_points: Vec::new(),
+            enemy_spawns: Vec::new(),
+            name: String::new(),
             tile_size: 60,
             autotiler: Autotiler::new(),
         }
@@ -84,323 +140,196 @@ impl<'a> Level {
         );
     }
 
-    pub fn load_from_file(
-        &mut self,
-        path: String,
-        texture_creator: &'a TextureCreator<sdl2::video::WindowContext>,
-        texture_map: &mut HashMap<String, Texture<'a>>,
-    ) {
-        // delete previous level (if any)
-        self.tiles.clear();
+    // generated levels, json maps, tiled maps, and png levels the old way until they're converted
+    pub fn load(path: &str) -> Result<Level, String> {
+        if let Some((seed, floor)) = parse_generated_name(path) {
+            let params = GenerationParams {
+                floor,
+                ..GenerationParams::default()
+            };
+            return Level::generate(seed, params);
+        }
+        let map = if path.ends_with(".png") {
+            MapFile::from_png(path)?
+        } else if path.ends_with(".tmx") || path.ends_with(".tmj") {
+            load_tiled(path)?
+        } else {
+            MapFile::load(path)?
+        };
+        Level::from_map(&map)
+    }
 
-        // load exits file
-        let mut exits_strs = path.clone();
-        exits_strs = exits_strs
-            .chars()
-            .take(exits_strs.chars().count() - 5)
+    pub fn from_map(map: &MapFile) -> Result<Level, String> {
+        map.validate()?;
+        let mut level = Level::new();
+        level.tile_size = map.tile_size;
+        level.name = map.info.name.clone();
+        level.autotiler_init();
+        let size = map.tile_size;
+        level.spawn_points = map
+            .spawns
+            .iter()
+            .map(|spawn| (spawn.x * size, spawn.y * size))
             .collect();
-        exits_strs.push_str(String::from("exits.txt").as_str());
-        let mut exits: Vec<String> = Vec::new();
-        if ::std::path::Path::new(&exits_strs).exists() {
-            let exit = ::std::fs::File::open(exits_strs).expect("Failed to read exits file");
-            let exit = ::std::io::BufReader::new(exit);
-            exits = ::std::io::BufReader::new(exit)
-                .lines()
-                .filter_map(Result::ok)
-                .collect();
-            exits.reverse();
-        }
+        level.player_spawn = level.spawn_points.first().copied().unwrap_or((0, 0));
+        level.enemy_spawns = map
+            .enemies
+            .iter()
+            .map(|enemy| (enemy.kind, (enemy.x * size) as f64, (enemy.y * size) as f64))
+            .collect();
+
+        for map_layer in &map.layers {
+            let grid = map.grid(map_layer)?;
+            let mut layer: HashMap<Point<i32>, Tile> = HashMap::new();
+            for (y, row) in grid.iter().enumerate() {
+                for (x, id) in row.iter().enumerate() {
+                    let Some((mut tile_type, solid)) = map.tile_type(*id)? else {
+                        continue;
+                    };
+                    let (x, y) = (x as i32, y as i32);
+                    let pos: Point<i32> = Point::new(x * size, y * size);
 
-        // initialize autotiler
-        self.autotiler_init();
+                    // get neihbours for autotiler
+                    // true if they have the same tile id as the current tile
+                    let mut neighbours = [[false; 3]; 3]; // 3x3 grid of neighbours, center is the current tile, y pol x
+                    for yoff in -1..2 {
+                        for xoff in -1..2 {
+                            let neighbour = grid
+                                .get((y + yoff) as usize)
+                                .and_then(|row| row.get((x + xoff) as usize));
+                            neighbours[(yoff + 1) as usize][(xoff + 1) as usize] =
+                                neighbour == Some(id);
+                        }
+                    }
+
+                    let bounding_box = match tile_type {
+                        TileType::Exit(ref mut exit) => {
+                            exit.next_level = map.exit_target(x, y).unwrap_or_default().to_string();
+                            exit_bounding_box(x, y, size)
+                        }
+                        _ if solid => Some(AABB::new(
+                            pos.x as f64,
+                            pos.y as f64,
+                            size as u32,
+                            size as u32,
+                        )),
+                        _ => None,
+                    };
+                    let texture_data = match tile_type {
+                        TileType::Exit(_) => {
+                            Some(TextureData::new("resources/textures/exit.png".to_string()))
+                        }
+                        TileType::Inventory => Some(TextureData::new(
+                            "resources/textures/cogwheel.png".to_string(),
+                        )),
+                        _ => level
+                            .autotiler
+                            .get_tile_texture(neighbours, tile_type.clone()),
+                    };
+                    let mut tile = Tile::new(pos.x, pos.y, size as u32, tile_type, bounding_box);
+                    tile.texture_data = texture_data;
+                    layer.insert(pos, tile);
+                }
+            }
+            level.tiles.push(layer);
+        }
+        Ok(level)
+    }
 
-        // load layer by layer from file, change path for each layer from "layer1_1.png" to "layer_2.png" while you can
-        self.load_layer(path.clone(), texture_creator, texture_map, &mut exits);
+    // players are spread over the spawn points by id
+    pub fn spawn_point(&self, player_id: u64) -> (i32, i32) {
+        if self.spawn_points.is_empty() {
+            return self.player_spawn;
+        }
+        self.spawn_points[(player_id % self.spawn_points.len() as u64) as usize]
+    }
 
-        let mut i = 2;
-        loop {
-            let mut new_path = path.clone();
-            new_path = new_path
-                .chars()
-                .take(new_path.chars().count() - 5)
+    // the same tiles always give the same data, so it can be checksummed
+    pub fn to_data(&self) -> LevelData {
+        let mut textures: Vec<TextureData> = Vec::new();
+        let mut texture_ids: HashMap<TextureData, u32> = HashMap::new();
+        let mut layers = Vec::with_capacity(self.tiles.len());
+        for layer in &self.tiles {
+            let mut tiles: Vec<&Tile> = layer.values().collect();
+            tiles.sort_by_key(|tile| (tile.y, tile.x));
+            let tiles = tiles
+                .into_iter()
+                .map(|tile| {
+                    let texture = tile.texture_data.as_ref().map(|texture| {
+                        *texture_ids.entry(texture.clone()).or_insert_with(|| {
+                            textures.push(texture.clone());
+                            textures.len() as u32 - 1
+                        })
+                    });
+                    TileData {
+                        x: tile.x / self.tile_size,
+                        y: tile.y / self.tile_size,
+                        tile_type: tile.tile_type.clone(),
+                        texture,
+                        solid: tile.bounding_box.is_some(),
+                    }
+                })
                 .collect();
-            new_path.push_str(format!("{}.png", i).as_str());
-            if !::std::path::Path::new(&new_path).exists() {
-                break;
+            layers.push(tiles);
+        }
+        LevelData {
+            tile_size: self.tile_size,
+            player_spawn: self.player_spawn,
+            spawn_points: self.spawn_points.clone(),
+            textures,
+            layers,
+        }
+    }
+
+    pub fn from_data(data: &LevelData) -> Level {
+        let mut level = Level::new();
+        level.tile_size = data.tile_size;
+        level.player_spawn = data.player_spawn;
+        level.spawn_points = data.spawn_points.clone();
+        let size = data.tile_size;
+        for tiles in &data.layers {
+            let mut layer = HashMap::with_capacity(tiles.len());
+            for tile in tiles {
+                let pos = Point::new(tile.x * size, tile.y * size);
+                let bounding_box = match tile.tile_type {
+                    TileType::Exit(_) => exit_bounding_box(tile.x, tile.y, size),
+                    _ if tile.solid => Some(AABB::new(
+                        pos.x as f64,
+                        pos.y as f64,
+                        size as u32,
+                        size as u32,
+                    )),
+                    _ => None,
+                };
+                let mut new_tile = Tile::new(
+                    pos.x,
+                    pos.y,
+                    size as u32,
+                    tile.tile_type.clone(),
+                    bounding_box,
+                );
+                new_tile.texture_data = tile
+                    .texture
+                    .and_then(|index| data.textures.get(index as usize).cloned());
+                layer.insert(pos, new_tile);
             }
-            self.load_layer(new_path, texture_creator, texture_map, &mut exits);
-            i += 1;
+            level.tiles.push(layer);
         }
+        level
     }
 
-    fn load_layer(
+    pub fn load_textures(
         &mut self,
-        path: String,
         texture_creator: &'a TextureCreator<sdl2::video::WindowContext>,
         texture_map: &mut HashMap<String, Texture<'a>>,
-        exits: &mut Vec<String>,
     ) {
-        let img = ::image::ImageReader::open(path)
-            .expect("Failed to load image")
-            .decode()
-            .expect("Failed to decode image");
-        let img: RgbaImage = img.to_rgba8();
-        let (width, height) = (img.dimensions().0 as i32, img.dimensions().1 as i32);
-
-        let mut layer: HashMap<Point<i32>, Tile> = HashMap::new();
-
-        for y in 0..height {
-            for x in 0..width {
-                let pixel_rgb = img.get_pixel(x as u32, y as u32);
-
-                let mut default_bounding_box = None;
-                if pixel_rgb[3] >= 128 {
-                    default_bounding_box = Some(AABB::new(
-                        (x * self.tile_size) as f64,
-                        (y * self.tile_size) as f64,
-                        self.tile_size as u32,
-                        self.tile_size as u32,
-                    ));
-                }
-                let pixel = (pixel_rgb[0], pixel_rgb[1], pixel_rgb[2]);
-                //println!("Pixel: {:?}",pixel);
-                let pos: Point<i32> =
-                    Point::new(x * self.tile_size, y * self.tile_size);
-
-                // get neihbours for autotiler
-                // 0/1 depending if they match the current pixel
-
-                let mut neighbours = [[false; 3]; 3]; // 3x3 grid of neighbours, center is the current pixel, y pol x
-                neighbours[1][1] = true; // center pixel is always true (current pixel)
-                for xoff in -1..2 {
-                    for yoff in -1..2 {
-                        if xoff == 0 && yoff == 0 {
-                            continue; // skip the current pixel
-                        }
-                        let neighbour_x = x + xoff;
-                        let neighbour_y = y + yoff;
-                        if neighbour_x < width
-                            && neighbour_y < height
-                            && neighbour_x >= 0
-                            && neighbour_y >= 0
-                        {
-                            let neighbour_pixel =
-                                img.get_pixel(neighbour_x as u32, neighbour_y as u32);
-                            neighbours[(yoff + 1) as usize][(xoff + 1) as usize] =
-                                neighbour_pixel == pixel_rgb;
-                        }
-                    }
-                }
-
-                match pixel {
-                    TileType::STONE_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Stone,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Stone);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::WATER_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Water,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Water);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::GRASS_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Grass,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Grass);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::SAND_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Sand,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Sand);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::ROCK_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Rock,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Rock);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::TREE_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Tree,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Tree);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::WALL_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Wall,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            self.autotiler.get_tile_texture(neighbours, TileType::Wall);
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::PLAYER_SPAWN_COLOR => {
-                        self.player_spawn = (x * self.tile_size, y * self.tile_size)
-                    }
-                    TileType::EXIT_COLOR => {
-                        let last = exits.pop().unwrap();
-                        let exit_bb = Some(AABB::new(
-                            (x * self.tile_size + self.tile_size / 4) as f64,
-                            (y * self.tile_size + self.tile_size / 4) as f64,
-                            self.tile_size as u32 / 2,
-                            self.tile_size as u32 / 2,
-                        ));
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Exit(ExitTile {
-                                    next_level: last.clone(),
-                                }),
-                                exit_bb,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data =
-                            Some(TextureData::new("resources/textures/exit.png".to_string()));
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    TileType::INVENTORY_COLOR => {
-                        layer.insert(
-                            pos,
-                            Tile::new(
-                                pos.x,
-                                pos.y,
-                                self.tile_size as u32,
-                                TileType::Inventory,
-                                default_bounding_box,
-                            ),
-                        );
-                        layer.get_mut(&pos).unwrap().texture_data = Some(TextureData::new(
-                            "resources/textures/cogwheel.png".to_string(),
-                        ));
-                        layer
-                            .get_mut(&pos)
-                            .unwrap()
-                            .texture_data
-                            .as_mut()
-                            .unwrap()
-                            .load_texture(texture_creator, texture_map);
-                    }
-                    _ => (),
+        for layer in &mut self.tiles {
+            for tile in layer.values_mut() {
+                if let Some(ref mut texture_data) = tile.texture_data {
+                    texture_data.load_texture(texture_creator, texture_map);
                 }
             }
         }
-
-        self.tiles.push(layer);
     }
 
     pub fn draw(
@@ -455,11 +384,13 @@ impl<'a> Level {
                 );
 
                 for layer in &self.tiles {
-                    if let Some(tile) = layer.get(&offset_pos) { if let Some(ref bounding_box) = tile.bounding_box {
-                        if hitbox.intersects(bounding_box) {
-                            ret.push(tile.clone());
+                    if let Some(tile) = layer.get(&offset_pos) {
+                        if let Some(ref bounding_box) = tile.bounding_box {
+                            if hitbox.intersects(bounding_box) {
+                                ret.push(tile.clone());
+                            }
                         }
-                    } }
+                    }
                 }
             }
         }
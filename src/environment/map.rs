@@ -0,0 +1,370 @@
+use ::image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::BufRead,
+    path::Path,
+};
+
+use crate::{
+    entities::enemy::EnemyType,
+    environment::{tile_type::TileType, tiled::load_tiled},
+};
+
+// a level as it is stored on disk, meant to be edited by hand,
+// all coordinates are in tiles
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapFile {
+    pub info: MapInfo,
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: i32,
+    // the ids used in the layers, 0 is always empty
+    pub tileset: BTreeMap<u16, MapTile>,
+    pub layers: Vec<MapLayer>,
+    // players are spread over these, the first one is the main spawn
+    pub spawns: Vec<MapPoint>,
+    #[serde(default)]
+    pub enemies: Vec<EnemySpawn>,
+    #[serde(default)]
+    pub exits: Vec<MapExit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct MapInfo {
+    pub name: String,
+    // anything else worth knowing about the level (author, music, ...)
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapTile {
+    pub tile: String, // TileType::name
+    #[serde(default)]
+    pub solid: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapLayer {
+    pub name: String,
+    // comma separated tile ids, one string per row
+    pub rows: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MapPoint {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnemySpawn {
+    pub kind: EnemyType,
+    pub x: i32,
+    pub y: i32,
+}
+
+// where the exit tile at x, y leads, on whichever layer it is
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapExit {
+    pub x: i32,
+    pub y: i32,
+    pub target: String,
+}
+
+impl MapFile {
+    pub fn load(path: &str) -> Result<MapFile, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("can't read map {}: {}", path, err))?;
+        let map: MapFile = serde_json::from_str(&text)
+            .map_err(|err| format!("map {} is broken: {}", path, err))?;
+        map.validate()
+            .map_err(|err| format!("map {} is invalid: {}", path, err))?;
+        Ok(map)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).expect("Failed to serialize map");
+        std::fs::write(path, text + "\n").map_err(|err| format!("can't write {}: {}", path, err))
+    }
+
+    // the tile ids of a layer, row by row
+    pub fn grid(&self, layer: &MapLayer) -> Result<Vec<Vec<u16>>, String> {
+        if layer.rows.len() != self.height as usize {
+            return Err(format!(
+                "layer {} has {} rows instead of {}",
+                layer.name,
+                layer.rows.len(),
+                self.height
+            ));
+        }
+        let mut grid = Vec::with_capacity(layer.rows.len());
+        for (y, row) in layer.rows.iter().enumerate() {
+            let ids = row
+                .split(',')
+                .map(|id| id.trim().parse::<u16>())
+                .collect::<Result<Vec<u16>, _>>()
+                .map_err(|_| format!("layer {} row {} has a bad tile id", layer.name, y))?;
+            if ids.len() != self.width as usize {
+                return Err(format!(
+                    "layer {} row {} has {} tiles instead of {}",
+                    layer.name,
+                    y,
+                    ids.len(),
+                    self.width
+                ));
+            }
+            grid.push(ids);
+        }
+        Ok(grid)
+    }
+
+    // the tile with this id, None for empty
+    pub fn tile_type(&self, id: u16) -> Result<Option<(TileType, bool)>, String> {
+        if id == 0 {
+            return Ok(None);
+        }
+        let tile = self
+            .tileset
+            .get(&id)
+            .ok_or_else(|| format!("tile id {} is not in the tileset", id))?;
+        let tile_type =
+            TileType::from_name(&tile.tile).ok_or_else(|| format!("unknown tile {}", tile.tile))?;
+        Ok(Some((tile_type, tile.solid)))
+    }
+
+    pub fn exit_target(&self, x: i32, y: i32) -> Option<&str> {
+        self.exits
+            .iter()
+            .find(|exit| exit.x == x && exit.y == y)
+            .map(|exit| exit.target.as_str())
+    }
+
+    fn inside(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32
+    }
+
+    // everything Level::from_map relies on
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tile_size <= 0 {
+            return Err("tile_size has to be positive".to_string());
+        }
+        if self.tileset.contains_key(&0) {
+            return Err("tile id 0 is reserved for empty tiles".to_string());
+        }
+        let mut exit_tiles = Vec::new();
+        for layer in &self.layers {
+            for (y, row) in self.grid(layer)?.iter().enumerate() {
+                for (x, id) in row.iter().enumerate() {
+                    if let Some((TileType::Exit(_), _)) = self.tile_type(*id)? {
+                        exit_tiles.push((x as i32, y as i32));
+                    }
+                }
+            }
+        }
+        for (x, y) in &exit_tiles {
+            if self.exit_target(*x, *y).is_none() {
+                return Err(format!("the exit at {}, {} leads nowhere", x, y));
+            }
+        }
+        for exit in &self.exits {
+            if !exit_tiles.contains(&(exit.x, exit.y)) {
+                return Err(format!("there is no exit tile at {}, {}", exit.x, exit.y));
+            }
+        }
+        let points = self
+            .spawns
+            .iter()
+            .map(|spawn| (spawn.x, spawn.y))
+            .chain(self.enemies.iter().map(|enemy| (enemy.x, enemy.y)));
+        for (x, y) in points {
+            if !self.inside(x, y) {
+                return Err(format!("spawn at {}, {} is outside the map", x, y));
+            }
+        }
+        Ok(())
+    }
+
+    // reads an old png level: a colour per tile, an alpha of at least 128 for solid ones,
+    // extra layers in "_2.png", "_3.png", ... and exit targets in "_exits.txt" in scan order
+    pub fn from_png(path: &str) -> Result<MapFile, String> {
+        let base = legacy_base(path)?;
+        let mut exits: Vec<String> = Vec::new();
+        let exits_path = format!("{}_exits.txt", base);
+        if Path::new(&exits_path).exists() {
+            let file = std::fs::File::open(&exits_path)
+                .map_err(|err| format!("can't read {}: {}", exits_path, err))?;
+            exits = std::io::BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .collect();
+            exits.reverse();
+        }
+
+        let mut map = MapFile {
+            info: MapInfo {
+                name: legacy_name(&base),
+                properties: BTreeMap::new(),
+            },
+            width: 0,
+            height: 0,
+            tile_size: 60,
+            tileset: BTreeMap::new(),
+            layers: Vec::new(),
+            spawns: Vec::new(),
+            enemies: Vec::new(),
+            exits: Vec::new(),
+        };
+        // a tile id for every tile and collision pair
+        let mut ids: HashMap<(&'static str, bool), u16> = HashMap::new();
+        let mut layer_path = path.to_string();
+        let mut number = 1;
+        while Path::new(&layer_path).exists() {
+            let img: RgbaImage = ::image::ImageReader::open(&layer_path)
+                .map_err(|err| format!("can't read {}: {}", layer_path, err))?
+                .decode()
+                .map_err(|err| format!("can't decode {}: {}", layer_path, err))?
+                .to_rgba8();
+            map.width = map.width.max(img.width());
+            map.height = map.height.max(img.height());
+
+            let mut rows = Vec::with_capacity(img.height() as usize);
+            for y in 0..img.height() {
+                let mut row = Vec::with_capacity(img.width() as usize);
+                for x in 0..img.width() {
+                    let pixel = img.get_pixel(x, y);
+                    let color = (pixel[0], pixel[1], pixel[2]);
+                    if color == TileType::PLAYER_SPAWN_COLOR {
+                        map.spawns.push(MapPoint {
+                            x: x as i32,
+                            y: y as i32,
+                        });
+                    }
+                    let Some(tile_type) = TileType::from_color(color) else {
+                        row.push(0);
+                        continue;
+                    };
+                    if let TileType::Exit(_) = tile_type {
+                        let target = exits
+                            .pop()
+                            .ok_or_else(|| format!("{} has more exits than targets", path))?;
+                        map.exits.push(MapExit {
+                            x: x as i32,
+                            y: y as i32,
+                            target: migrated_target(&target),
+                        });
+                    }
+                    let solid = pixel[3] >= 128;
+                    let next_id = ids.len() as u16 + 1;
+                    let id = *ids.entry((tile_type.name(), solid)).or_insert(next_id);
+                    map.tileset.entry(id).or_insert_with(|| MapTile {
+                        tile: tile_type.name().to_string(),
+                        solid,
+                    });
+                    row.push(id);
+                }
+                rows.push(row);
+            }
+            map.layers.push(MapLayer {
+                name: format!("layer{}", number),
+                rows: rows
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|id| id.to_string())
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    })
+                    .collect(),
+            });
+            number += 1;
+            layer_path = format!("{}_{}.png", base, number);
+        }
+        if map.layers.is_empty() {
+            return Err(format!("{} doesn't exist", path));
+        }
+        // the png levels had the wizard in the same place on every level
+        map.enemies.push(EnemySpawn {
+            kind: EnemyType::Wizard,
+            x: 16.min(map.width as i32 - 1),
+            y: 16.min(map.height as i32 - 1),
+        });
+        // layers of different sizes are padded with empty tiles
+        let width = map.width as usize;
+        for layer in &mut map.layers {
+            for row in &mut layer.rows {
+                let missing = width - row.split(',').count();
+                row.push_str(&",0".repeat(missing));
+            }
+            let empty = vec!["0"; width].join(",");
+            layer.rows.resize(map.height as usize, empty);
+        }
+        Ok(map)
+    }
+}
+
+// "resources/levels/level1_1.png" -> "resources/levels/level1"
+fn legacy_base(path: &str) -> Result<String, String> {
+    path.strip_suffix(".png")
+        .and_then(|stem| stem.rsplit_once('_'))
+        .map(|(base, _)| base.to_string())
+        .ok_or_else(|| format!("{} is not named like a png level (name_1.png)", path))
+}
+
+fn legacy_name(base: &str) -> String {
+    Path::new(base)
+        .file_name()
+        .map_or(base.to_string(), |name| name.to_string_lossy().to_string())
+}
+
+fn is_tiled(path: &str) -> bool {
+    path.ends_with(".tmx") || path.ends_with(".tmj")
+}
+
+// where a png or tiled level ends up once it's converted
+pub fn migrated_path(path: &str) -> Option<String> {
+    if is_tiled(path) {
+        let stem = path.rsplit_once('.').map(|(stem, _)| stem)?;
+        return Some(format!("{}.json", stem));
+    }
+    legacy_base(path).ok().map(|base| format!("{}.json", base))
+}
+
+// exits to png levels lead to their converted versions
+fn migrated_target(target: &str) -> String {
+    match migrated_path(target) {
+        Some(migrated) if target.ends_with(".png") => migrated,
+        _ => target.to_string(),
+    }
+}
+
+// writes a json map next to every png or tiled level, returns the written paths
+pub fn convert_levels(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut written = Vec::new();
+    for path in paths {
+        let map = match is_tiled(path) {
+            true => load_tiled(path)?,
+            false => MapFile::from_png(path)?,
+        };
+        let out = migrated_path(path).ok_or_else(|| format!("can't convert {}", path))?;
+        map.save(&out)?;
+        written.push(out);
+    }
+    Ok(written)
+}
+
+// the first layer of every png level in a directory
+pub fn legacy_levels(dir: &str) -> Vec<String> {
+    let mut paths: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path().to_string_lossy().to_string())
+                .filter(|path| path.ends_with("_1.png"))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
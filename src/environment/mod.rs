@@ -1,6 +1,9 @@
 pub mod aabb;
 pub mod autotiler;
+pub mod generator;
 pub mod level;
+pub mod map;
 pub mod texture_data;
 pub mod tile;
 pub mod tile_type;
+pub mod tiled;
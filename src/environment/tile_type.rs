@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TileType {
     Grass,
     Water,
@@ -12,7 +14,7 @@ pub enum TileType {
     Exit(ExitTile),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExitTile {
     pub next_level: String,
 }
@@ -46,4 +48,55 @@ impl TileType {
             // Add other variants here...
         }
     }
+
+    // the legacy png levels, the spawn colour is not a tile
+    pub fn from_color(color: (u8, u8, u8)) -> Option<TileType> {
+        match color {
+            TileType::GRASS_COLOR => Some(TileType::Grass),
+            TileType::WATER_COLOR => Some(TileType::Water),
+            TileType::SAND_COLOR => Some(TileType::Sand),
+            TileType::ROCK_COLOR => Some(TileType::Rock),
+            TileType::TREE_COLOR => Some(TileType::Tree),
+            TileType::STONE_COLOR => Some(TileType::Stone),
+            TileType::WALL_COLOR => Some(TileType::Wall),
+            TileType::INVENTORY_COLOR => Some(TileType::Inventory),
+            TileType::EXIT_COLOR => Some(TileType::Exit(ExitTile {
+                next_level: String::new(),
+            })),
+            _ => None,
+        }
+    }
+
+    // how tiles are called in map files
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TileType::Grass => "grass",
+            TileType::Water => "water",
+            TileType::Sand => "sand",
+            TileType::Rock => "rock",
+            TileType::Tree => "tree",
+            TileType::Stone => "stone",
+            TileType::Wall => "wall",
+            TileType::Inventory => "inventory",
+            TileType::Exit(..) => "exit",
+        }
+    }
+
+    // exits get their target from the map, not the name
+    pub fn from_name(name: &str) -> Option<TileType> {
+        match name {
+            "grass" => Some(TileType::Grass),
+            "water" => Some(TileType::Water),
+            "sand" => Some(TileType::Sand),
+            "rock" => Some(TileType::Rock),
+            "tree" => Some(TileType::Tree),
+            "stone" => Some(TileType::Stone),
+            "wall" => Some(TileType::Wall),
+            "inventory" => Some(TileType::Inventory),
+            "exit" => Some(TileType::Exit(ExitTile {
+                next_level: String::new(),
+            })),
+            _ => None,
+        }
+    }
 }
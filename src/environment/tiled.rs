@@ -0,0 +1,580 @@
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    entities::enemy::EnemyType,
+    environment::{
+        map::{EnemySpawn, MapExit, MapFile, MapInfo, MapLayer, MapPoint, MapTile},
+        tile_type::TileType,
+    },
+};
+
+// tiled keeps flipping and rotation in the top bits of a gid, we ignore them
+const GID_MASK: u32 = 0x0FFF_FFFF;
+
+// a tiled map reduced to what the game understands, the same for tmx and tmj
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    properties: HashMap<String, String>,
+    tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    objects: Vec<Object>,
+}
+
+struct Tileset {
+    first_gid: u32,
+    tiles: HashMap<u32, TiledTile>, // by local id
+}
+
+// tiles say what they are with their class (or a "tile" property)
+// and collide if their "solid" property is true
+struct TiledTile {
+    class: String,
+    properties: HashMap<String, String>,
+}
+
+struct TileLayer {
+    name: String,
+    gids: Vec<u32>,
+}
+
+// spawns, exits and enemies, told apart by class (or name)
+struct Object {
+    class: String,
+    name: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    tile: bool, // tile objects have their origin at the bottom
+    properties: HashMap<String, String>,
+}
+
+// tmx and tmj maps, tilesets can be embedded or in tsx and tsj files next to them
+pub fn load_tiled(path: &str) -> Result<MapFile, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("can't read map {}: {}", path, err))?;
+    let path = Path::new(path);
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path
+        .file_stem()
+        .map_or(String::new(), |name| name.to_string_lossy().to_string());
+    let map = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tmx") => parse_tmx(&text, dir, &name),
+        Some("tmj") => parse_tmj(&text, dir, &name),
+        _ => Err("tiled maps end in .tmx or .tmj".to_string()),
+    };
+    map.map_err(|err| format!("tiled map {} can't be used: {}", path.display(), err))
+}
+
+pub fn parse_tmx(text: &str, dir: &Path, name: &str) -> Result<MapFile, String> {
+    let document = roxmltree::Document::parse(text).map_err(|err| err.to_string())?;
+    let root = document.root_element();
+    if root.attribute("infinite") == Some("1") {
+        return Err("infinite maps aren't supported".to_string());
+    }
+    let mut map = TiledMap {
+        width: attribute(root, "width")?,
+        height: attribute(root, "height")?,
+        tile_width: attribute(root, "tilewidth")?,
+        tile_height: attribute(root, "tileheight")?,
+        properties: tmx_properties(root),
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    for tileset in root.children().filter(|node| node.has_tag_name("tileset")) {
+        let first_gid = attribute(tileset, "firstgid")?;
+        let tileset = match tileset.attribute("source") {
+            Some(source) => load_tileset(dir, source, first_gid)?,
+            None => tmx_tileset(tileset, first_gid),
+        };
+        map.tilesets.push(tileset);
+    }
+    tmx_layers(root, &mut map)?;
+    map.into_map(name)
+}
+
+// layers and object groups, groups are flattened
+fn tmx_layers(parent: roxmltree::Node, map: &mut TiledMap) -> Result<(), String> {
+    for node in parent.children().filter(roxmltree::Node::is_element) {
+        match node.tag_name().name() {
+            "layer" => {
+                let name = node.attribute("name").unwrap_or_default().to_string();
+                let data = node
+                    .children()
+                    .find(|child| child.has_tag_name("data"))
+                    .ok_or_else(|| format!("layer {} has no data", name))?;
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => parse_csv(data.text().unwrap_or_default())?,
+                    None => data
+                        .children()
+                        .filter(|child| child.has_tag_name("tile"))
+                        .map(|tile| attribute_or(tile, "gid", 0))
+                        .collect::<Result<Vec<u32>, String>>()?,
+                    Some(encoding) => {
+                        return Err(format!(
+                            "layer {} is stored as {}, save it as CSV",
+                            name, encoding
+                        ));
+                    }
+                };
+                map.layers.push(TileLayer { name, gids });
+            }
+            "objectgroup" => {
+                for object in node.children().filter(|child| child.has_tag_name("object")) {
+                    let class = object
+                        .attribute("class")
+                        .or(object.attribute("type"))
+                        .unwrap_or_default();
+                    map.objects.push(Object {
+                        class: class.to_string(),
+                        name: object.attribute("name").unwrap_or_default().to_string(),
+                        x: attribute(object, "x")?,
+                        y: attribute(object, "y")?,
+                        width: attribute_or(object, "width", 0.0)?,
+                        height: attribute_or(object, "height", 0.0)?,
+                        tile: object.attribute("gid").is_some(),
+                        properties: tmx_properties(object),
+                    });
+                }
+            }
+            "group" => tmx_layers(node, map)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn tmx_tileset(node: roxmltree::Node, first_gid: u32) -> Tileset {
+    let tiles = node
+        .children()
+        .filter(|child| child.has_tag_name("tile"))
+        .filter_map(|tile| {
+            let id = tile.attribute("id")?.parse().ok()?;
+            let class = tile.attribute("class").or(tile.attribute("type"));
+            let tile = TiledTile {
+                class: class.unwrap_or_default().to_string(),
+                properties: tmx_properties(tile),
+            };
+            Some((id, tile))
+        })
+        .collect();
+    Tileset { first_gid, tiles }
+}
+
+// the value is an attribute, or the text for multi line strings
+fn tmx_properties(node: roxmltree::Node) -> HashMap<String, String> {
+    node.children()
+        .filter(|child| child.has_tag_name("properties"))
+        .flat_map(|properties| properties.children())
+        .filter(|property| property.has_tag_name("property"))
+        .filter_map(|property| {
+            let value = property.attribute("value").or(property.text());
+            Some((
+                property.attribute("name")?.to_string(),
+                value.unwrap_or_default().to_string(),
+            ))
+        })
+        .collect()
+}
+
+fn attribute<T: FromStr>(node: roxmltree::Node, name: &str) -> Result<T, String> {
+    let value = node
+        .attribute(name)
+        .ok_or_else(|| format!("<{}> has no {}", node.tag_name().name(), name))?;
+    value
+        .parse()
+        .map_err(|_| format!("<{}> has a bad {}: {}", node.tag_name().name(), name, value))
+}
+
+fn attribute_or<T: FromStr>(node: roxmltree::Node, name: &str, default: T) -> Result<T, String> {
+    match node.attribute(name) {
+        Some(_) => attribute(node, name),
+        None => Ok(default),
+    }
+}
+
+fn parse_csv(text: &str) -> Result<Vec<u32>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|gid| !gid.is_empty())
+        .map(|gid| gid.parse().map_err(|_| format!("bad tile id {}", gid)))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct TmjMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+    layers: Vec<TmjLayer>,
+    tilesets: Vec<TmjTileset>,
+}
+
+#[derive(Deserialize)]
+struct TmjLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    objects: Vec<TmjObject>,
+    #[serde(default)]
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+struct TmjObject {
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Deserialize)]
+struct TmjTileset {
+    #[serde(default)]
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TmjTile>,
+}
+
+#[derive(Deserialize)]
+struct TmjTile {
+    id: u32,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Deserialize)]
+struct TmjProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn tmj_properties(properties: &[TmjProperty]) -> HashMap<String, String> {
+    properties
+        .iter()
+        .map(|property| {
+            let value = match &property.value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (property.name.clone(), value)
+        })
+        .collect()
+}
+
+// tiled writes the class as "type" in older versions
+fn tmj_class(class: &str, kind: &str) -> String {
+    if class.is_empty() { kind } else { class }.to_string()
+}
+
+impl TmjTileset {
+    fn into_tileset(self, first_gid: u32) -> Tileset {
+        let tiles = self
+            .tiles
+            .into_iter()
+            .map(|tile| {
+                let class = tmj_class(&tile.class, &tile.kind);
+                let properties = tmj_properties(&tile.properties);
+                (tile.id, TiledTile { class, properties })
+            })
+            .collect();
+        Tileset { first_gid, tiles }
+    }
+}
+
+pub fn parse_tmj(text: &str, dir: &Path, name: &str) -> Result<MapFile, String> {
+    let tmj: TmjMap = serde_json::from_str(text).map_err(|err| err.to_string())?;
+    if tmj.infinite {
+        return Err("infinite maps aren't supported".to_string());
+    }
+    let mut map = TiledMap {
+        width: tmj.width,
+        height: tmj.height,
+        tile_width: tmj.tilewidth,
+        tile_height: tmj.tileheight,
+        properties: tmj_properties(&tmj.properties),
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+    for tileset in tmj.tilesets {
+        let tileset = match tileset.source {
+            Some(ref source) => load_tileset(dir, source, tileset.firstgid)?,
+            None => {
+                let first_gid = tileset.firstgid;
+                tileset.into_tileset(first_gid)
+            }
+        };
+        map.tilesets.push(tileset);
+    }
+    tmj_layers(tmj.layers, &mut map)?;
+    map.into_map(name)
+}
+
+fn tmj_layers(layers: Vec<TmjLayer>, map: &mut TiledMap) -> Result<(), String> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let gids = match layer.data {
+                    Some(serde_json::Value::Array(gids)) => gids
+                        .iter()
+                        .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                        .collect::<Option<Vec<u32>>>()
+                        .ok_or_else(|| format!("layer {} has a bad tile id", layer.name))?,
+                    _ => {
+                        return Err(format!(
+                            "layer {} isn't stored as CSV, save it as CSV",
+                            layer.name
+                        ));
+                    }
+                };
+                map.layers.push(TileLayer {
+                    name: layer.name,
+                    gids,
+                });
+            }
+            "objectgroup" => {
+                for object in layer.objects {
+                    map.objects.push(Object {
+                        class: tmj_class(&object.class, &object.kind),
+                        name: object.name,
+                        x: object.x,
+                        y: object.y,
+                        width: object.width,
+                        height: object.height,
+                        tile: object.gid.is_some(),
+                        properties: tmj_properties(&object.properties),
+                    });
+                }
+            }
+            "group" => tmj_layers(layer.layers, map)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// tsx or tsj, relative to the map
+fn load_tileset(dir: &Path, source: &str, first_gid: u32) -> Result<Tileset, String> {
+    let path = dir.join(source);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|err| format!("can't read tileset {}: {}", path.display(), err))?;
+    if source.ends_with(".tsx") {
+        let document = roxmltree::Document::parse(&text).map_err(|err| err.to_string())?;
+        Ok(tmx_tileset(document.root_element(), first_gid))
+    } else {
+        let tileset: TmjTileset = serde_json::from_str(&text)
+            .map_err(|err| format!("tileset {} is broken: {}", path.display(), err))?;
+        Ok(tileset.into_tileset(first_gid))
+    }
+}
+
+impl Object {
+    // the tile under the middle of the object
+    fn tile_position(&self) -> (f64, f64) {
+        let top = if self.tile {
+            self.y - self.height
+        } else {
+            self.y
+        };
+        (self.x + self.width / 2.0, top + self.height / 2.0)
+    }
+}
+
+impl TiledMap {
+    // the tile a gid points to, None for empty
+    fn tile(&self, gid: u32) -> Result<Option<(&'static str, bool)>, String> {
+        let gid = gid & GID_MASK;
+        if gid == 0 {
+            return Ok(None);
+        }
+        let tileset = self
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)
+            .ok_or_else(|| format!("tile {} is in no tileset", gid))?;
+        let tile = tileset
+            .tiles
+            .get(&(gid - tileset.first_gid))
+            .ok_or_else(|| format!("tile {} has no class", gid))?;
+        let class = tile.properties.get("tile").unwrap_or(&tile.class);
+        let tile_type = TileType::from_name(&class.to_lowercase())
+            .ok_or_else(|| format!("tile {} is a {}, which the game doesn't know", gid, class))?;
+        let solid = tile
+            .properties
+            .get("solid")
+            .is_some_and(|solid| solid == "true");
+        Ok(Some((tile_type.name(), solid)))
+    }
+
+    fn into_map(self, file_name: &str) -> Result<MapFile, String> {
+        if self.tile_width != self.tile_height {
+            return Err("tiles have to be square".to_string());
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err("the map has no tiles".to_string());
+        }
+        let size = self.tile_width as f64;
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut tileset = BTreeMap::new();
+        let mut ids: HashMap<(&'static str, bool), u16> = HashMap::new();
+        let mut id_for = |tile: (&'static str, bool)| {
+            let next_id = ids.len() as u16 + 1;
+            let id = *ids.entry(tile).or_insert(next_id);
+            tileset.entry(id).or_insert_with(|| MapTile {
+                tile: tile.0.to_string(),
+                solid: tile.1,
+            });
+            id
+        };
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+        let mut exit_tiles = Vec::new();
+        for layer in &self.layers {
+            if layer.gids.len() != width * height {
+                return Err(format!(
+                    "layer {} has {} tiles instead of {}",
+                    layer.name,
+                    layer.gids.len(),
+                    width * height
+                ));
+            }
+            let mut rows = Vec::with_capacity(height);
+            for (y, row) in layer.gids.chunks(width).enumerate() {
+                let mut ids = Vec::with_capacity(width);
+                for (x, gid) in row.iter().enumerate() {
+                    let id = match self.tile(*gid)? {
+                        Some(tile) => {
+                            if tile.0 == "exit" {
+                                exit_tiles.push((x as i32, y as i32));
+                            }
+                            id_for(tile)
+                        }
+                        None => 0,
+                    };
+                    ids.push(id.to_string());
+                }
+                rows.push(ids.join(","));
+            }
+            layers.push(MapLayer {
+                name: layer.name.clone(),
+                rows,
+            });
+        }
+
+        let mut spawns = Vec::new();
+        let mut enemies = Vec::new();
+        let mut exits = Vec::new();
+        // exits without a tile under them get one on a layer of their own
+        let mut exit_layer = vec![vec![0u16; width]; height];
+        for object in &self.objects {
+            let (x, y) = object.tile_position();
+            let (x, y) = ((x / size).floor() as i32, (y / size).floor() as i32);
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                return Err(format!(
+                    "{} at {}, {} is outside the map",
+                    object.class, x, y
+                ));
+            }
+            let class = if object.class.is_empty() {
+                &object.name
+            } else {
+                &object.class
+            };
+            match class.to_lowercase().as_str() {
+                "spawn" | "player_spawn" => spawns.push(MapPoint { x, y }),
+                "enemy" => {
+                    let kind = object.properties.get("kind").unwrap_or(&object.name);
+                    let kind = EnemyType::from_name(kind)
+                        .ok_or_else(|| format!("there is no enemy called {}", kind))?;
+                    enemies.push(EnemySpawn { kind, x, y });
+                }
+                "exit" => {
+                    let target = object
+                        .properties
+                        .get("next_level")
+                        .ok_or_else(|| format!("the exit at {}, {} has no next_level", x, y))?;
+                    exits.push(MapExit {
+                        x,
+                        y,
+                        target: target.clone(),
+                    });
+                    if !exit_tiles.contains(&(x, y)) {
+                        exit_layer[y as usize][x as usize] = id_for(("exit", false));
+                        exit_tiles.push((x, y));
+                    }
+                }
+                _ => (), // notes and whatever else designers put there
+            }
+        }
+        if exit_layer.iter().flatten().any(|id| *id != 0) {
+            layers.push(MapLayer {
+                name: "exits".to_string(),
+                rows: exit_layer
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|id| id.to_string())
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    })
+                    .collect(),
+            });
+        }
+
+        let mut properties: BTreeMap<String, String> = self.properties.into_iter().collect();
+        let name = properties
+            .remove("name")
+            .unwrap_or_else(|| file_name.to_string());
+        let map = MapFile {
+            info: MapInfo { name, properties },
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_width as i32,
+            tileset,
+            layers,
+            spawns,
+            enemies,
+            exits,
+        };
+        map.validate()?;
+        Ok(map)
+    }
+}
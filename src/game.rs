@@ -1,17 +1,40 @@
 use crate::display::button::{Badge, Button, ButtonAction, Dropdown, HealthBar};
+use crate::display::chat::ChatLog;
 use crate::display::hud::Hud;
-use crate::entities::enemy::EnemyType;
 use crate::entities::projectile::Projectile;
-use crate::entities::{camera::Camera, enemy::Enemy, player::Player};
-use crate::environment::{level::Level, texture_data::TextureData};
-use crate::networking::{packet::Packet, player_packets::*, shared::*};
+use crate::entities::{
+    camera::{Camera, CameraTarget},
+    player::Player,
+};
+use crate::environment::level::{Level, LevelData};
+use crate::environment::texture_data::TextureData;
+use crate::networking::{
+    client::{ServerConnection, connect_in_background},
+    connection_packets::ConnectionPacket,
+    level_transfer::{LevelDownload, load_local},
+    packet::{ChatMessage, Packet},
+    player_packets::*,
+    shared::*,
+    simulated::NetworkConditions,
+    transport::TransportKind,
+    world_packets::{LevelRequest, WorldPacket},
+};
+use crate::simulation::{
+    TIME_STEP,
+    input::InputState,
+    interpolation::{EntitySnapshots, RenderClock, SnapshotBuffer},
+    prediction::Prediction,
+    world::World,
+};
 use sdl2::image::{self};
 use sdl2::pixels::Color;
 use sdl2::rect;
 use sdl2::rect::Rect;
 use sdl2::render::Texture;
 use sdl2::render::TextureQuery;
+use sdl2::render::{Canvas, TextureCreator};
 use sdl2::ttf;
+use sdl2::video::{Window, WindowContext};
 use std::collections::HashMap;
 use std::sync::mpsc as mspc;
 
@@ -20,12 +43,33 @@ pub enum GameState {
     Running,
     Paused,
     GameOver,
+    ConnectionLost,
 }
 
 pub struct Game {
-    packet_receiver: mspc::Receiver<Packet>,
-    packet_sender: mspc::Sender<Packet>,
+    // kept for reconnecting
+    address: String,
+    transport: TransportKind,
+    conditions: Option<NetworkConditions>,
+    connection: Option<ServerConnection>, // none after the connection was lost
+    reconnecting: Option<mspc::Receiver<Result<ServerConnection, String>>>,
+    disconnect_reason: Option<String>,
+    session: Option<u64>, // lets us resume our player after reconnecting
+    spectating: bool,     // watching without a player of our own
     game_state: GameState,
+    player_id: u64,
+    prediction: Prediction,
+    render_clock: RenderClock,
+    remote_positions: HashMap<u64, SnapshotBuffer>, // other players
+    enemy_positions: EntitySnapshots,
+    projectile_positions: EntitySnapshots,
+    levels: HashMap<u64, LevelData>, // every level we've played, by checksum
+    level_checksum: Option<u64>,     // of the level we're on, if the server told us
+    download: Option<LevelDownload>,
+    chat: ChatLog,
+    player_list: Vec<PlayerListEntry>,
+    start_level: String, // where we join, and start over after reconnecting
+    seed: Option<u64>,   // the server's, once it said hello
 }
 
 pub fn find_sdl_gl_driver() -> Option<u32> {
@@ -39,48 +83,174 @@ pub fn find_sdl_gl_driver() -> Option<u32> {
 
 impl Game {
     pub fn new(
-        packet_sender: mspc::Sender<Packet>,
-        packet_receiver: mspc::Receiver<Packet>,
+        address: String,
+        transport: TransportKind,
+        conditions: Option<NetworkConditions>,
+        connection: ServerConnection,
+        spectating: bool,
+        start_level: String,
     ) -> Game {
         Game {
-            packet_receiver,
-            packet_sender,
+            address,
+            transport,
+            conditions,
+            connection: Some(connection),
+            reconnecting: None,
+            disconnect_reason: None,
+            session: None,
+            spectating,
             game_state: GameState::Running,
+            player_id: 1_000_000,
+            prediction: Prediction::new(),
+            render_clock: RenderClock::default(),
+            remote_positions: HashMap::new(),
+            enemy_positions: EntitySnapshots::default(),
+            projectile_positions: EntitySnapshots::default(),
+            levels: HashMap::new(),
+            level_checksum: None,
+            download: None,
+            chat: ChatLog::default(),
+            player_list: Vec::new(),
+            start_level,
+            seed: None,
         }
     }
 
-    fn handle_receive<'a>(
+    // a lost connection is noticed when receiving, so sending can't fail here
+    fn send(&self, packet: Packet) {
+        if let Some(connection) = &self.connection {
+            connection.send(packet);
+        }
+    }
+
+    fn lose_connection(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+        let reason = self
+            .disconnect_reason
+            .get_or_insert_with(|| "connection lost".to_string());
+        println!("Disconnected: {}", reason);
+        self.game_state = GameState::ConnectionLost;
+    }
+
+    // starts over with a new connection, the server sends our id again
+    fn reset(&mut self, connection: ServerConnection) {
+        self.connection = Some(connection);
+        self.disconnect_reason = None;
+        self.game_state = GameState::Running;
+        self.player_id = 1_000_000;
+        self.prediction = Prediction::new();
+        self.render_clock.reset();
+        self.remote_positions.clear();
+        self.enemy_positions.clear();
+        self.projectile_positions.clear();
+        self.level_checksum = None;
+        self.download = None;
+        self.player_list.clear();
+    }
+
+    // switches to a level we already have, otherwise asks the server for it
+    fn enter_level<'a>(
+        &mut self,
+        world: &mut World,
+        level: String,
+        checksum: u64,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        texture_map: &mut HashMap<String, Texture<'a>>,
+    ) {
+        let cached = self.levels.get(&checksum).cloned();
+        let Some(data) = cached.or_else(|| load_local(&level, checksum)) else {
+            println!("Downloading level {}", level);
+            self.send(Packet::WorldPacket(WorldPacket::LevelRequestPacket(
+                LevelRequest { checksum },
+            )));
+            self.download = Some(LevelDownload::new(level, checksum));
+            return;
+        };
+        self.download = None;
+        world.set_level(level, Level::from_data(&data));
+        self.levels.insert(checksum, data);
+        world.level.load_textures(texture_creator, texture_map);
+        self.level_checksum = Some(checksum);
+        // timestamps from the old level mean nothing here
+        self.render_clock.reset();
+        self.remote_positions.clear();
+        self.enemy_positions.clear();
+        self.projectile_positions.clear();
+    }
+
+    // a client world with only our player in it, the server sends the rest,
+    // spectators start out with an empty one
+    fn new_world<'a>(
         &self,
-        player: &mut Player,
+        level: String,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        texture_map: &mut HashMap<String, Texture<'a>>,
+    ) -> World {
+        // nothing to show without the level we start on
+        let mut world = World::new_client(level).unwrap_or_else(|err| {
+            println!("{}", err);
+            std::process::exit(1);
+        });
+        world.level.load_textures(texture_creator, texture_map);
+        if self.spectating {
+            return world;
+        }
+        let mut player = Player::new(self.player_id);
+        player.load_player_texture();
+        player.load_textures(texture_creator, texture_map);
+        world.add_player(player);
+        world
+    }
+
+    fn handle_receive<'a>(
+        &mut self,
+        world: &mut World,
         other_players: &mut HashMap<u64, Player>,
-        _texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
-        _texture_map: &mut HashMap<String, Texture<'a>>,
+        texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
+        texture_map: &mut HashMap<String, Texture<'a>>,
     ) {
-        match self.packet_receiver.try_recv() {
-            Ok(packet) => {
+        // the server sends several packets per tick, so handle everything that arrived
+        while let Some(connection) = &self.connection {
+            let packet = match connection.receiver.try_recv() {
+                Ok(packet) => packet,
+                Err(mspc::TryRecvError::Empty) => break,
+                Err(mspc::TryRecvError::Disconnected) => {
+                    self.lose_connection();
+                    break;
+                }
+            };
+            {
                 //println!("Lmao");
                 match packet {
                     Packet::PlayerPacket(player_packet) => {
                         match player_packet {
                             PlayerPacket::PlayerPositionPacket(pos) => {
                                 //println!("Got fake positoin'");
-                                if let Some(other_player) = other_players.get_mut(&pos.player_id) {
-                                    if pos.x != other_player.x || pos.y != other_player.y {
-                                        //println!("Got a position :{:?}", pos);
-                                    }
-                                    other_player.x = pos.x;
-                                    other_player.y = pos.y;
+                                // the server decides where we are, we replay what it hasn't seen
+                                if pos.player_id == self.player_id {
+                                    self.prediction.reconcile(world, &pos);
+                                    self.render_clock.observe(pos.time, world.time);
+                                } else {
+                                    // drawn later, interpolated between positions
+                                    self.remote_positions
+                                        .entry(pos.player_id)
+                                        .or_default()
+                                        .push(pos.time, pos.x, pos.y);
                                 }
                             }
                             PlayerPacket::PlayerWelcomePacket(welc) => {
                                 println!("Got a welcome packet");
                                 // if self or already received return
                                 let found = other_players.contains_key(&welc.player_id)
-                                    || welc.player_id == player.id;
+                                    || welc.player_id == self.player_id;
                                 if !found {
                                     let mut temp = Player::new(welc.player_id);
                                     temp.x = welc.x;
                                     temp.y = welc.y;
+                                    temp.load_player_texture();
+                                    temp.load_textures(texture_creator, texture_map);
 
                                     other_players.insert(temp.id, temp);
                                 }
@@ -88,85 +258,161 @@ impl Game {
                             PlayerPacket::PlayerDisconnectPacket(disconnected) => {
                                 println!("Got a disconnect packet");
                                 other_players.remove(&disconnected.id);
+                                self.remote_positions.remove(&disconnected.id);
                             }
-                            PlayerPacket::PlayerAnimationPacket(_) => {
-                                println!(
-                                    "Got an animation packet, but animations are disabled for now"
-                                );
+                            // our own player is animated by prediction
+                            PlayerPacket::PlayerAnimationPacket(animation) => {
+                                if let Some(other_player) =
+                                    other_players.get_mut(&animation.player_id)
+                                {
+                                    other_player.animation_data.apply(
+                                        animation.state,
+                                        animation.frame,
+                                        animation.facing,
+                                    );
+                                }
                             }
-                            //PlayerPacket::PlayerAnimationPacket(animation) => {
-                            //    println!("Got an animation packet");
-                            //    if let Some(other_player) = other_players.get_mut(&animation.id) {
-                            //        other_player.animation_data =
-                            //            Some(animation.animation_data.clone());
-                            //        other_player
-                            //            .animation_data
-                            //            .as_mut()
-                            //            .unwrap()
-                            //            .load_animation(
-                            //                animation.animation_data.frames[0].path.clone(),
-                            //                0,
-                            //                0,
-                            //                16,
-                            //                16,
-                            //                3,
-                            //                &texture_creator,
-                            //                texture_map,
-                            //            );
-                            //    }
-                            //}
                             PlayerPacket::PlayerLevelPacket(level) => {
                                 println!("Got a level packet");
-                                if let Some(other_player) = other_players.get_mut(&level.player_id)
+                                if level.player_id == self.player_id {
+                                    let changed = level.level != world.level_path
+                                        || Some(level.checksum) != self.level_checksum;
+                                    let downloading =
+                                        self.download.as_ref().is_some_and(|download| {
+                                            download.checksum == level.checksum
+                                        });
+                                    // the server moved us through an exit
+                                    if changed && !downloading {
+                                        self.enter_level(
+                                            world,
+                                            level.level,
+                                            level.checksum,
+                                            texture_creator,
+                                            texture_map,
+                                        );
+                                    }
+                                } else if let Some(other_player) =
+                                    other_players.get_mut(&level.player_id)
                                 {
                                     other_player.current_level = level.level.clone();
+                                    self.remote_positions.remove(&level.player_id);
                                 }
                             }
+                            PlayerPacket::PlayerListPacket(list) => {
+                                self.player_list = list.players;
+                            }
+                            // only clients send these
+                            PlayerPacket::PlayerInputPacket(_)
+                            | PlayerPacket::PlayerRespawnPacket(_)
+                            | PlayerPacket::PlayerSpectatePacket(_) => (),
                         }
                     }
+                    Packet::WorldPacket(WorldPacket::LevelChunkPacket(chunk)) => {
+                        let Some(download) = &mut self.download else {
+                            continue;
+                        };
+                        let (level, checksum) = (download.level.clone(), download.checksum);
+                        match download.push(chunk) {
+                            Ok(Some(data)) => {
+                                self.levels.insert(checksum, data);
+                                self.enter_level(
+                                    world,
+                                    level,
+                                    checksum,
+                                    texture_creator,
+                                    texture_map,
+                                );
+                            }
+                            Ok(None) => (),
+                            Err(err) => {
+                                println!("{}, downloading it again", err);
+                                self.send(Packet::WorldPacket(WorldPacket::LevelRequestPacket(
+                                    LevelRequest { checksum },
+                                )));
+                            }
+                        }
+                    }
+                    // only clients send these
+                    Packet::WorldPacket(WorldPacket::LevelRequestPacket(_)) => (),
+                    Packet::WorldPacket(WorldPacket::WorldStatePacket(state)) => {
+                        if state.level != world.level_path {
+                            continue;
+                        }
+                        // without a player of our own the snapshots are the only clock
+                        if self.spectating {
+                            self.render_clock.observe(state.time, world.time);
+                        }
+                        // drawn later, interpolated like other players
+                        self.enemy_positions.push(
+                            state.time,
+                            state
+                                .enemies
+                                .iter()
+                                .map(|enemy| (enemy.id, enemy.x, enemy.y)),
+                        );
+                        self.projectile_positions.push(
+                            state.time,
+                            state.projectiles.iter().map(|p| (p.id, p.x, p.y)),
+                        );
+                        if world.apply_snapshot(&state) {
+                            for enemy in &mut world.enemies {
+                                enemy.load_textures(texture_creator, texture_map);
+                            }
+                        }
+                    }
+                    // the network thread stops after this, the reason is shown once we notice
+                    Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(goodbye)) => {
+                        self.disconnect_reason = Some(goodbye.reason.to_string());
+                    }
+                    // like a goodbye, the server wouldn't let us join its level
+                    Packet::ConnectionPacket(ConnectionPacket::RejectPacket(reject)) => {
+                        self.disconnect_reason = Some(reject.reason.to_string());
+                    }
+                    Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(info)) => {
+                        self.seed = Some(info.seed);
+                    }
+                    // the handshake is over before the game starts
+                    Packet::ConnectionPacket(_) => (),
+                    Packet::ChatPacket(message) => {
+                        let text = match message.sender {
+                            Some(sender) => format!("{}: {}", player_name(sender), message.text),
+                            None => message.text,
+                        };
+                        self.chat
+                            .push(text, message.sender.is_none(), std::time::Instant::now());
+                    }
                     Packet::ClientIDPacket(id) => {
                         println!("Got an id :{}", id.id);
-                        if player.id == 1_000_000 {
-                            player.id = id.id;
+                        if self.player_id == 1_000_000 {
+                            if let Some(player) = world.player_mut(self.player_id) {
+                                player.id = id.id;
+                            }
+                            self.player_id = id.id;
+                        }
+                        // only sent with our own id
+                        if id.session.is_some() {
+                            self.session = id.session;
                         }
-                        let data = PlayerWelcome {
-                            player_id: player.id,
-                            x: player.x,
-                            y: player.y,
+                        let join = match world.player(self.player_id) {
+                            Some(player) => PlayerPacket::PlayerLevelPacket(PlayerLevel {
+                                player_id: player.id,
+                                level: player.current_level.clone(),
+                                checksum: 0, // the server knows better
+                            }),
+                            None => PlayerPacket::PlayerSpectatePacket(PlayerSpectate {
+                                level: world.level_path.clone(),
+                            }),
                         };
-                        self.packet_sender
-                            .send(Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(
-                                data,
-                            )))
-                            .unwrap();
-                        //let data = PlayerAnimation {
-                        //    id: player.id,
-                        //    animation_data: player.animation_data.clone().unwrap(),
-                        //};
-                        //self.packet_sender
-                        //    .send(Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(
-                        //        data,
-                        //    )))
-                        //    .unwrap();
-                        self.packet_sender
-                            .send(Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(
-                                PlayerLevel {
-                                    player_id: player.id,
-                                    level: player.current_level.clone(),
-                                },
-                            )))
-                            .unwrap();
+                        self.send(Packet::PlayerPacket(join));
                     }
                 }
             }
-            Err(mspc::TryRecvError::Empty) => (),
-            Err(mspc::TryRecvError::Disconnected) => panic!("Disconnected"),
         }
     }
 
     // main game loop
     pub fn run(&mut self) {
-        let initial_level = "resources/levels/level1_1.png".to_string();
+        let initial_level = self.start_level.clone();
 
         // initalize sdl2 stuff
         let sdl_context = sdl2::init().unwrap();
@@ -188,6 +434,7 @@ impl Game {
         // Load a font
         let font_path = "resources/fonts/Battle-Race.ttf";
         let font = ttf_context.load_font(font_path, 56).unwrap();
+        let small_font = ttf_context.load_font(font_path, 24).unwrap();
 
         //sound
         //mixer::init(mixer::InitFlag::MP3 | mixer::InitFlag::OGG).unwrap();
@@ -210,37 +457,21 @@ impl Game {
         let texture_creator = canvas.texture_creator();
         let mut texture_map: HashMap<String, Texture> = HashMap::new();
 
-        // level loading
-        let mut level = Level::new();
-        level.load_from_file(initial_level.clone(), &texture_creator, &mut texture_map);
-
-        // player setup
-        let mut player = Player::new(1_000_000);
-        player.load_player_texture(&texture_creator, &mut texture_map);
-
-        player.x = level.player_spawn.0 as f64;
-        player.y = level.player_spawn.1 as f64;
-        player.hitbox.x = player.x + 16.;
-        player.hitbox.y = player.y + 40.;
-        player.current_level = initial_level.clone();
-
-        // camera
-        let mut camera = Camera::new(
-            player.x + (player.size_x as i32 / 2 - SCREEN_WIDTH as i32 / 2) as f64,
-            player.y + (player.size_y as i32 / 2 - SCREEN_HEIGHT as i32 / 2) as f64,
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-        );
-
-        // enemies
-        let mut enemies: Vec<Enemy> = Vec::new();
-        enemies.push(Enemy::new(
-            EnemyType::Wizard,
-            &texture_creator,
-            &mut texture_map,
-        ));
+        // simulation setup, enemies and projectiles are simulated by the server
+        let mut world = self.new_world(initial_level.clone(), &texture_creator, &mut texture_map);
+        Projectile::load_textures(&texture_creator, &mut texture_map);
+        let mut input = InputState::new();
 
-        let mut projectiles = Vec::new();
+        // camera, spectators start at the spawn and can follow players
+        let mut camera = Camera::new(0.0, 0.0, SCREEN_WIDTH, SCREEN_HEIGHT);
+        match world.player(self.player_id) {
+            Some(player) => camera.center_on(player),
+            None => camera.center_at(
+                world.level.player_spawn.0 as f64,
+                world.level.player_spawn.1 as f64,
+            ),
+        }
+        let mut camera_target = CameraTarget::Free;
 
         // hud
         let pavza = Button::new(
@@ -302,10 +533,8 @@ impl Game {
             ],
         );
 
-        let global_clock = std::time::Instant::now();
         let mut current_time = std::time::Instant::now();
-        let time_step = 1.0 / 60.0;
-        let mut last_time_clicked = 0.0;
+        let mut accumulator = 0.0;
 
         let mut hud = Hud::new(
             vec![pavza, resume],
@@ -316,16 +545,56 @@ impl Game {
         );
         let mut draw_hitboxes = false;
         let mut draw_hud = true;
+        let mut draw_network = false;
+        let mut draw_player_list = false;
 
         self.game_state = GameState::Running;
 
+        // sdl starts with text input on, it's only needed for chat
+        let text_input = video_subsystem.text_input();
+        text_input.stop();
+
         'running: loop {
             // event polling
             for event in event_pump.poll_iter() {
+                // while typing every key goes to the chat
+                if self.chat.is_open() {
+                    match event {
+                        sdl2::event::Event::Quit { .. } => break 'running,
+                        sdl2::event::Event::TextInput { text, .. } => self.chat.type_text(&text),
+                        sdl2::event::Event::KeyDown {
+                            keycode: Some(sdl2::keyboard::Keycode::BACKSPACE),
+                            ..
+                        } => self.chat.backspace(),
+                        sdl2::event::Event::KeyDown {
+                            keycode:
+                                Some(
+                                    sdl2::keyboard::Keycode::RETURN
+                                    | sdl2::keyboard::Keycode::KP_ENTER,
+                                ),
+                            ..
+                        } => {
+                            if let Some(text) = self.chat.submit() {
+                                self.send(Packet::ChatPacket(ChatMessage { sender: None, text }));
+                            }
+                            text_input.stop();
+                        }
+                        sdl2::event::Event::KeyDown {
+                            keycode: Some(sdl2::keyboard::Keycode::ESCAPE),
+                            ..
+                        } => {
+                            self.chat.close();
+                            text_input.stop();
+                        }
+                        sdl2::event::Event::MouseWheel { y, .. } => self.chat.scroll(y),
+                        _ => (),
+                    }
+                    continue;
+                }
                 match self.game_state {
-                    GameState::Running => player.on_event(&event),
-                    GameState::Paused => player.reset_velocity(),
-                    GameState::GameOver => (),
+                    GameState::Running => input.on_event(&event),
+                    GameState::Paused => input.reset(),
+                    GameState::GameOver | GameState::ConnectionLost => (),
                 }
                 //camera.handle_zoom(&event);
                 match event {
@@ -334,30 +603,76 @@ impl Game {
                         keycode: Some(sdl2::keyboard::Keycode::ESCAPE),
                         ..
                     } => break 'running,
+                    sdl2::event::Event::KeyDown {
+                        keycode:
+                            Some(sdl2::keyboard::Keycode::RETURN | sdl2::keyboard::Keycode::KP_ENTER),
+                        ..
+                    } if self.connection.is_some() => {
+                        // nobody to talk to without a server
+                        self.chat.open();
+                        input.reset();
+                        text_input.start();
+                    }
                     sdl2::event::Event::KeyDown {
                         keycode: Some(sdl2::keyboard::Keycode::H),
                         ..
                     } => {
                         draw_hitboxes = !draw_hitboxes;
                     }
+                    sdl2::event::Event::KeyDown {
+                        keycode: Some(sdl2::keyboard::Keycode::N),
+                        ..
+                    } => {
+                        draw_network = !draw_network;
+                    }
+                    sdl2::event::Event::KeyDown {
+                        keycode: Some(sdl2::keyboard::Keycode::TAB),
+                        ..
+                    } => {
+                        draw_player_list = !draw_player_list;
+                    }
+                    sdl2::event::Event::KeyDown {
+                        keycode: Some(sdl2::keyboard::Keycode::C),
+                        ..
+                    } if self.spectating => {
+                        let players: Vec<u64> = other_players.keys().copied().collect();
+                        camera_target = camera_target.next(&players);
+                    }
                     sdl2::event::Event::KeyDown {
                         keycode: Some(sdl2::keyboard::Keycode::P),
                         ..
                     } => match self.game_state {
                         GameState::Paused => self.game_state = GameState::Running,
                         GameState::Running => self.game_state = GameState::Paused,
-                        GameState::GameOver => (),
+                        GameState::GameOver | GameState::ConnectionLost => (),
                     },
                     sdl2::event::Event::KeyDown {
                         keycode: Some(sdl2::keyboard::Keycode::R),
                         ..
-                    } => if let GameState::GameOver = self.game_state {
-                        self.game_state = GameState::Running;
-                        player.health = 100;
-                        player.pressed_down = false;
-                        player.pressed_left = false;
-                        player.pressed_right = false;
-                        player.pressed_up = false;
+                    } => match self.game_state {
+                        GameState::GameOver => {
+                            self.game_state = GameState::Running;
+                            input.reset();
+                            let player = world.player_mut(self.player_id).unwrap();
+                            player.health = 100;
+                            player.reset_velocity();
+                            self.send(Packet::PlayerPacket(PlayerPacket::PlayerRespawnPacket(
+                                PlayerRespawn {
+                                    player_id: self.player_id,
+                                },
+                            )));
+                        }
+                        GameState::ConnectionLost if self.reconnecting.is_none() => {
+                            println!("Reconnecting to {}", self.address);
+                            self.reconnecting = Some(connect_in_background(
+                                self.address.clone(),
+                                self.transport,
+                                self.conditions,
+                                self.session,
+                            ));
+                        }
+                        GameState::ConnectionLost => (),
+                        GameState::Running | GameState::Paused => (),
                     },
                     sdl2::event::Event::KeyDown {
                         keycode: Some(sdl2::keyboard::Keycode::T),
@@ -381,29 +696,19 @@ impl Game {
                         x,
                         y,
                     } => {
-                        if mouse_btn == sdl2::mouse::MouseButton::Left {
-                            // delay to prevent spam
-                            if last_time_clicked + 0.25
-                                < std::time::Instant::elapsed(&global_clock).as_secs_f32()
-                            {
-                                last_time_clicked =
-                                    std::time::Instant::elapsed(&global_clock).as_secs_f32();
-
-                                projectiles.push(Projectile::new(
-                                    player.x + player.size_x as f64 / 2.0,
-                                    player.y + player.size_y as f64 / 2.0,
-                                    15,
-                                    ((y - (SCREEN_HEIGHT / 2) as i32) as f64)
-                                        .atan2((x - (SCREEN_WIDTH / 2) as i32) as f64),
-                                    true,
-                                ));
-                                projectiles
-                                    .last_mut()
-                                    .unwrap()
-                                    .load_projectile_texture(&texture_creator, &mut texture_map);
-                            }
+                        if mouse_btn == sdl2::mouse::MouseButton::Left
+                            && let GameState::Running = self.game_state
+                        {
+                            input.shoot(
+                                ((y - (SCREEN_HEIGHT / 2) as i32) as f64)
+                                    .atan2((x - (SCREEN_WIDTH / 2) as i32) as f64),
+                            );
                         }
 
+                        // pausing doesn't make sense without a server
+                        if let GameState::ConnectionLost = self.game_state {
+                            continue;
+                        }
                         for but in &mut hud.buttons {
                             but.handle_event(&event, &mut self.game_state);
                         }
@@ -418,93 +723,116 @@ impl Game {
                 }
             }
 
-            // check if we need to load a new level
-            if let Some(exit) = player.reached_end.clone() {
-                level.load_from_file(exit.next_level.clone(), &texture_creator, &mut texture_map);
-                player.x = level.player_spawn.0 as f64;
-                player.y = level.player_spawn.1 as f64;
-                player.hitbox.x = player.x + 20.0;
-                player.hitbox.y = player.y + 76.0;
-                camera.x = player.x + (player.size_x as i32 / 2 - SCREEN_WIDTH as i32 / 2) as f64;
-                camera.y = player.y + (player.size_y as i32 / 2 - SCREEN_HEIGHT as i32 / 2) as f64;
-                player.reached_end = None;
-                player.current_level = exit.next_level.clone();
-                self.packet_sender
-                    .send(Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(
-                        PlayerLevel {
-                            player_id: player.id,
-                            level: player.current_level.clone(),
-                        },
-                    )))
-                    .unwrap();
+            // the handshake takes a while, the game keeps drawing in the meantime
+            if let Some(reconnecting) = &self.reconnecting {
+                let result = match reconnecting.try_recv() {
+                    Ok(result) => Some(result),
+                    Err(mspc::TryRecvError::Empty) => None,
+                    Err(mspc::TryRecvError::Disconnected) => {
+                        Some(Err("connection lost".to_string()))
+                    }
+                };
+                match result {
+                    Some(Ok(connection)) => {
+                        self.reconnecting = None;
+                        self.reset(connection);
+                        input.reset();
+                        other_players.clear();
+                        camera_target = CameraTarget::Free;
+                        world = self.new_world(
+                            initial_level.clone(),
+                            &texture_creator,
+                            &mut texture_map,
+                        );
+                    }
+                    Some(Err(reason)) => {
+                        self.reconnecting = None;
+                        self.disconnect_reason = Some(reason);
+                    }
+                    None => (),
+                }
             }
 
             // time handling
             let new_time = std::time::Instant::now();
-            let mut frame_time = new_time - current_time;
+            let frame_time = (new_time - current_time).as_secs_f64();
             current_time = new_time;
+            // don't try to catch up after long stalls (window dragging etc.)
+            accumulator += f64::min(frame_time, 0.25);
 
             // update
-            //  while frame_time > std::time::Duration::from_secs_f64(0.0){
-            let delta_time = f64::min(frame_time.as_secs_f64(), time_step);
-
-            if let GameState::Running = self.game_state {
-                for enemy in &mut enemies {
-                    let prev_size = projectiles.len();
-                    enemy.update(delta_time, &level, &player, &global_clock, &mut projectiles);
-                    if projectiles.len() > prev_size {
-                        // if new projectiles were added, we need to load their textures
-                        projectiles
-                            .last_mut()
-                            .unwrap()
-                            .load_projectile_texture(&texture_creator, &mut texture_map);
+            while accumulator >= TIME_STEP {
+                accumulator -= TIME_STEP;
+                if let GameState::Running = self.game_state {
+                    let player_input = input.take_input();
+                    // spectators have nothing to send, the keys move the camera
+                    if self.spectating {
+                        if camera_target == CameraTarget::Free {
+                            camera.fly(player_input.move_x, player_input.move_y, TIME_STEP);
+                        }
+                        world.step(TIME_STEP, &HashMap::new());
+                        continue;
                     }
+                    // every step is sent so the server can replay our movement exactly
+                    if self.player_id != 1_000_000 {
+                        let command = self.prediction.record(self.player_id, player_input);
+                        self.send(Packet::PlayerPacket(PlayerPacket::PlayerInputPacket(
+                            command,
+                        )));
+                    }
+                    let inputs = HashMap::from([(self.player_id, player_input)]);
+                    // exits and deaths are decided by the server
+                    world.step(TIME_STEP, &inputs);
                 }
-                player.update(
-                    delta_time,
-                    &self.packet_sender,
-                    &level,
-                    &mut camera,
-                    &enemies,
-                    &global_clock,
-                );
-                for other_player in other_players.values_mut() {
-                    other_player.animation_data.update(delta_time);
+            }
+            if let Some(render_time) = self.render_clock.render_time(world.time) {
+                for (id, other_player) in other_players.iter_mut() {
+                    if let Some((x, y)) = self
+                        .remote_positions
+                        .get(id)
+                        .and_then(|positions| positions.sample(render_time))
+                    {
+                        other_player.set_position(x, y);
+                    }
                 }
-                // update projectiles
-                let mut remove_projectiles = Vec::new();
-                for projectile in &mut projectiles {
-                    projectile.update(delta_time);
-                    if projectile.resolve_collision(&level, &mut enemies, &mut player) {
-                        // remove projectile if it collides with something
-                        remove_projectiles.push(projectile.clone());
+                for enemy in &mut world.enemies {
+                    if let Some((x, y)) = self.enemy_positions.sample(enemy.id, render_time) {
+                        enemy.set_position(x, y);
                     }
                 }
-                for projectile in &remove_projectiles {
-                    if let Some(pos) = projectiles
-                        .iter()
-                        .position(|p| p.x == projectile.x && p.y == projectile.y)
+                for projectile in &mut world.projectiles {
+                    if let Some((x, y)) =
+                        self.projectile_positions.sample(projectile.id, render_time)
                     {
-                        projectiles.remove(pos);
+                        projectile.set_position(x, y);
                     }
                 }
-                // remove dead enemies
-                enemies.retain(|enemy| enemy.health > 0);
             }
-
-            frame_time -= std::time::Duration::from_secs_f64(delta_time);
-            std::thread::sleep(frame_time);
-            // }
-
-            if player.health <= 0 {
+            if let Some(player) = world.player(self.player_id) {
+                camera.center_on(player);
+            } else if let CameraTarget::Player(id) = camera_target {
+                match other_players.get(&id) {
+                    Some(followed) => camera.center_on(followed),
+                    // it left, the camera stays where it was
+                    None => camera_target = CameraTarget::Free,
+                }
+            }
+            if world
+                .player(self.player_id)
+                .is_some_and(|player| player.health <= 0)
+            {
                 match self.game_state {
-                    GameState::GameOver => (),
+                    GameState::GameOver | GameState::ConnectionLost => (),
                     _ => {
                         self.game_state = GameState::GameOver;
-                        player.reset_velocity();
+                        input.reset();
+                        world.player_mut(self.player_id).unwrap().reset_velocity();
                     }
                 }
             }
+            std::thread::sleep(std::time::Duration::from_secs_f64(
+                (TIME_STEP - accumulator).max(0.0),
+            ));
 
             // drawing
             canvas.set_blend_mode(sdl2::render::BlendMode::None);
@@ -516,60 +844,93 @@ impl Game {
             canvas.set_viewport(viewport);
 
             // draw level
-            level.draw(&mut canvas, &texture_map, &camera);
+            world.level.draw(&mut canvas, &texture_map, &camera);
             if draw_hitboxes {
-                level.draw_hitboxes(&mut canvas, &camera);
+                world.level.draw_hitboxes(&mut canvas, &camera);
             }
             // draw enemies
-            for enemy in &enemies {
+            for enemy in &world.enemies {
                 enemy.draw(&mut canvas, &texture_map, &camera);
             }
 
             // draw projectiles
-            for projectile in &mut projectiles {
+            for projectile in &world.projectiles {
                 projectile.draw(&mut canvas, &texture_map, &camera);
             }
 
             //draw other player if on the same level
             for other_player in other_players.values_mut() {
-                if other_player.current_level == player.current_level {
-                    other_player.draw(&mut canvas, &texture_map, &camera, &global_clock);
+                if other_player.current_level == world.level_path {
+                    other_player.draw(&mut canvas, &texture_map, &camera, world.time);
                 }
             }
             // draw self
-            player.draw(&mut canvas, &texture_map, &camera, &global_clock);
-            let player_hitbox_color = if player.colliding {
-                Color::RED
-            } else {
-                Color::GREEN
-            };
+            let time = world.time;
+            if let Some(player) = world.player_mut(self.player_id) {
+                player.draw(&mut canvas, &texture_map, &camera, time);
+                let player_hitbox_color = if player.colliding {
+                    Color::RED
+                } else {
+                    Color::GREEN
+                };
+                if draw_hitboxes {
+                    player
+                        .hitbox
+                        .draw(&mut canvas, player_hitbox_color, &camera);
+                }
+            }
 
             if draw_hitboxes {
-                player
-                    .hitbox
-                    .draw(&mut canvas, player_hitbox_color, &camera);
-                for enemy in &enemies {
+                for enemy in &world.enemies {
                     enemy.hitbox.draw(&mut canvas, Color::RED, &camera);
                 }
-                for projectile in &projectiles {
+                for projectile in &world.projectiles {
                     projectile.hitbox.draw(&mut canvas, Color::RED, &camera);
                 }
             }
 
             //hud
+            hud.seed = self.seed;
             if draw_hud {
-                hud.draw(
-                    player.health,
+                match world.player(self.player_id) {
+                    Some(player) => hud.draw(
+                        player.health,
+                        &mut canvas,
+                        &ttf_context,
+                        &texture_creator,
+                        &mut texture_map,
+                    ),
+                    None => {
+                        let text = match camera_target {
+                            CameraTarget::Free => {
+                                "Spectating, WASD moves the camera, C follows a player".to_string()
+                            }
+                            CameraTarget::Player(id) => {
+                                format!("Following {}, C for the next one", player_name(id))
+                            }
+                        };
+                        hud.draw_spectating(&text, &mut canvas, &ttf_context, &texture_creator);
+                    }
+                }
+            }
+            hud.draw_chat(&self.chat, &mut canvas, &ttf_context, &texture_creator);
+            if draw_player_list {
+                hud.draw_player_list(
+                    &self.player_list,
+                    self.player_id,
                     &mut canvas,
                     &ttf_context,
                     &texture_creator,
-                    &mut texture_map,
                 );
             }
+            if draw_network && let Some(connection) = &self.connection {
+                let stats = connection.stats.lock().unwrap().clone();
+                hud.draw_network_stats(&stats, &mut canvas, &ttf_context, &texture_creator);
+            }
 
             // clear screen
             match self.game_state {
-                GameState::Paused | GameState::GameOver => {
+                GameState::Paused | GameState::GameOver | GameState::ConnectionLost => {
                     canvas.set_draw_color(sdl2::pixels::Color::RGBA(00, 00, 255, 150));
                     canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
                     canvas
@@ -600,30 +961,65 @@ impl Game {
                 // Clear the screen and draw the texture
                 canvas.copy(&texture, None, Some(dest_rect)).unwrap();
             }
+            if let GameState::ConnectionLost = self.game_state {
+                let center = (SCREEN_HEIGHT / 2) as i32;
+                draw_text(
+                    &mut canvas,
+                    &texture_creator,
+                    &font,
+                    "Connection lost",
+                    center - 80,
+                );
+                let reason = self
+                    .disconnect_reason
+                    .as_deref()
+                    .unwrap_or("connection lost");
+                draw_text(&mut canvas, &texture_creator, &small_font, reason, center);
+                let hint = match self.reconnecting {
+                    Some(_) => "Reconnecting...",
+                    None => "Press R to reconnect",
+                };
+                draw_text(
+                    &mut canvas,
+                    &texture_creator,
+                    &small_font,
+                    hint,
+                    center + 40,
+                );
+            }
 
             canvas.present();
 
-            // send updates
-            if player.id != 1_000_000 && player.moved {
-                //println!("sendingk");
-                self.packet_sender
-                    .send(Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(
-                        PlayerPosition {
-                            player_id: player.id,
-                            x: player.x,
-                            y: player.y,
-                        },
-                    )))
-                    .unwrap();
-            }
-
             // receive
             self.handle_receive(
-                &mut player,
+                &mut world,
                 &mut other_players,
                 &texture_creator,
                 &mut texture_map,
             );
         }
+
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
     }
 }
+
+// one line of text centered horizontally
+fn draw_text(
+    canvas: &mut Canvas<Window>,
+    texture_creator: &TextureCreator<WindowContext>,
+    font: &ttf::Font,
+    text: &str,
+    y: i32,
+) {
+    let surface = font.render(text).blended(Color::WHITE).unwrap();
+    let texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .unwrap();
+    let TextureQuery { width, height, .. } = texture.query();
+    let x = (SCREEN_WIDTH as i32 - width as i32) / 2;
+    canvas
+        .copy(&texture, None, Some(Rect::new(x, y, width, height)))
+        .unwrap();
+}
@@ -3,8 +3,21 @@ mod entities;
 mod environment;
 mod game;
 mod networking;
+mod simulation;
 mod wfc;
-use crate::networking::{client::client, server::server, shared::CLIENT_LOCAL};
+use crate::display::server_browser::browse;
+use crate::environment::{
+    generator::{LEARN_USAGE, LearnParams, generated_level_name, learn_level},
+    map::{convert_levels, legacy_levels},
+};
+use crate::networking::{
+    client::client,
+    server::{request_shutdown, server},
+    server_config::{ServerConfig, USAGE},
+    shared::CLIENT_LOCAL,
+    simulated::NetworkConditions,
+    transport::TransportKind,
+};
 use crate::wfc::run_wfc;
 use std::env;
 use std::thread;
@@ -13,26 +26,122 @@ mod tests;
 
 fn main() {
     let args = env::args().collect::<Vec<String>>();
+    let transport = TransportKind::from_args(&args);
 
     if args.len() >= 2 && args[1] == "client" {
-        if args.len() >= 3 {
-            client(&args[2]);
-        } else {
-            client(CLIENT_LOCAL);
+        let conditions = conditions_from_args(&args[2..]);
+        // --spectate joins without a player
+        let spectate = args.contains(&"--spectate".to_string());
+        let level = start_level(&args, seed_from_args(&args).unwrap_or_else(rand::random));
+        if args.len() >= 3 && !args[2].starts_with("--") {
+            client(&args[2], transport, conditions, spectate, level);
+        } else if let Some((address, transport)) = browse() {
+            // the server says which transport it uses
+            client(&address, transport, conditions, spectate, level);
         }
     } else if args.len() >= 2 && args[1] == "server" {
-        println!("Running server on localhost:6000");
-        server();
+        match ServerConfig::from_args(&args[2..]) {
+            Ok(config) => {
+                // say goodbye to the clients instead of just dropping them
+                ctrlc::set_handler(request_shutdown).expect("Failed to set the Ctrl-C handler");
+                server(config);
+            }
+            Err(err) => {
+                println!("{}\n{}", err, USAGE);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() >= 2 && args[1] == "convert" {
+        // png levels to json maps, all of them if none are given
+        let paths = match args.len() {
+            2 => legacy_levels("resources/levels"),
+            _ => args[2..].to_vec(),
+        };
+        match convert_levels(&paths) {
+            Ok(written) => {
+                for path in written {
+                    println!("Wrote {}", path);
+                }
+            }
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() >= 2 && args[1] == "learn" {
+        // a new png level in the style of an example
+        let learned = match args.len() {
+            0..4 => Err("learn needs an example and where to write the level".to_string()),
+            _ => LearnParams::from_args(&args[4..])
+                .and_then(|params| learn(&args[2], &args[3], params)),
+        };
+        match learned {
+            Ok(path) => println!("Wrote {}", path),
+            Err(err) => {
+                println!("{}\n{}", err, LEARN_USAGE);
+                std::process::exit(1);
+            }
+        }
     } else if args.contains(&"--wfc".to_string()) {
-        return run_wfc();
+        return run_wfc(seed_from_args(&args));
     } else {
         println!("Running server-client on localhost:6000");
-        let _server = thread::spawn(|| {
-            server();
+        let conditions = conditions_from_args(&args[1..]);
+        // the same seed for the server and the generated levels, so the one on screen replays both
+        let seed = seed_from_args(&args).unwrap_or_else(rand::random);
+        let level = start_level(&args, seed);
+        let _server = thread::spawn(move || {
+            server(ServerConfig {
+                transport,
+                seed: Some(seed),
+                ..ServerConfig::default()
+            });
         });
-        let client = thread::spawn(|| {
-            client(CLIENT_LOCAL);
+        let client = thread::spawn(move || {
+            client(CLIENT_LOCAL, transport, conditions, false, level);
         });
         client.join().unwrap();
     }
 }
+
+fn learn(example: &str, out: &str, params: LearnParams) -> Result<String, String> {
+    let image = ::image::ImageReader::open(example)
+        .map_err(|err| format!("can't read {}: {}", example, err))?
+        .decode()
+        .map_err(|err| format!("can't decode {}: {}", example, err))?
+        .to_rgba8();
+    let seed = params.seed.unwrap_or_else(rand::random);
+    println!("Learning from {} with seed {}", example, seed);
+    learn_level(&image, seed, &params)?.save(out)
+}
+
+// --endless starts on the first floor of the seed's generated descent
+fn start_level(args: &[String], seed: u64) -> String {
+    match args.contains(&"--endless".to_string()) {
+        true => generated_level_name(seed, 0),
+        false => "resources/levels/level1.json".to_string(),
+    }
+}
+
+// --seed plays the same game again
+fn seed_from_args(args: &[String]) -> Option<u64> {
+    let index = args.iter().position(|arg| arg == "--seed")?;
+    match args.get(index + 1).map(|value| value.parse()) {
+        Some(Ok(seed)) => Some(seed),
+        _ => {
+            println!("--seed needs a number");
+            std::process::exit(1);
+        }
+    }
+}
+
+// --latency, --jitter and --loss for testing on localhost
+fn conditions_from_args(args: &[String]) -> Option<NetworkConditions> {
+    match NetworkConditions::from_args(args) {
+        Ok(conditions) => conditions,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,52 +1,283 @@
 use crate::game::Game;
-use crate::networking::helpers::{
-    NetworkResult, deserialize_to_packet, serialize_and_send, try_read_tcp,
+use crate::networking::connection_packets::{
+    ConnectionPacket, Goodbye, GoodbyeReason, Hello, Resume,
 };
-use crate::networking::packet::Packet;
-use std::net::TcpStream;
-use std::sync::mpsc as mspc;
-use std::thread;
+use crate::networking::packet::{PROTOCOL_VERSION, Packet};
+use crate::networking::simulated::{NetworkConditions, SimulatedClientTransport};
+use crate::networking::stats::{NetworkStats, Pinger};
+use crate::networking::tcp::TcpClientTransport;
+use crate::networking::transport::{ClientTransport, TransportKind};
+use crate::networking::udp::UdpClientTransport;
+use mio::Waker;
+use std::sync::{Arc, Mutex, mpsc as mspc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-pub fn client(address: &str) {
-    let mut client = TcpStream::connect(address).expect("Failed to connect");
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// says hello (or asks to resume a session) and waits for an id, returns everything that arrived until then
+fn handshake(
+    client: &mut dyn ClientTransport,
+    session: Option<u64>,
+) -> Result<Vec<Packet>, String> {
+    let hello = match session {
+        Some(session) => ConnectionPacket::ResumePacket(Resume {
+            protocol_version: PROTOCOL_VERSION,
+            session,
+        }),
+        None => ConnectionPacket::HelloPacket(Hello {
+            protocol_version: PROTOCOL_VERSION,
+        }),
+    };
     client
-        .set_nonblocking(true)
-        .expect("Failed to initialize non-blocking client");
-    println!("Running client on address {}", address);
+        .send(Packet::ConnectionPacket(hello))
+        .map_err(|err| format!("{:?}", err))?;
+
+    let started = Instant::now();
+    let mut received = Vec::new();
+    while started.elapsed() < HANDSHAKE_TIMEOUT {
+        for packet in client.poll().map_err(|err| format!("{:?}", err))? {
+            match packet {
+                Packet::ConnectionPacket(ConnectionPacket::RejectPacket(reject)) => {
+                    return Err(reject.reason.to_string());
+                }
+                Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(info)) => {
+                    println!(
+                        "{} ({}/{} players, seed {})",
+                        info.motd, info.players, info.max_players, info.seed
+                    );
+                    // the game shows the seed
+                    received.push(Packet::ConnectionPacket(
+                        ConnectionPacket::ServerInfoPacket(info),
+                    ));
+                }
+                packet => received.push(packet),
+            }
+        }
+        if received
+            .iter()
+            .any(|packet| matches!(packet, Packet::ClientIDPacket(_)))
+        {
+            return Ok(received);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Err("the server didn't answer".to_string())
+}
+
+// the game's side of a connection, packets go through the network thread
+pub struct ServerConnection {
+    sender: mspc::Sender<Packet>,
+    pub receiver: mspc::Receiver<Packet>,
+    pub stats: Arc<Mutex<NetworkStats>>,
+    waker: Option<Arc<Waker>>, // the network thread sleeps until the server sends something
+    thread: JoinHandle<()>,
+}
+
+impl ServerConnection {
+    // a lost connection is noticed when receiving
+    pub fn send(&self, packet: Packet) {
+        let _ = self.sender.send(packet);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = &self.waker {
+            let _ = waker.wake();
+        }
+    }
+
+    // says goodbye to the server and waits until it's sent
+    pub fn close(self) {
+        let ServerConnection {
+            sender,
+            receiver,
+            waker,
+            thread,
+            ..
+        } = self;
+        drop(sender);
+        if let Some(waker) = waker {
+            let _ = waker.wake();
+        }
+        let _ = thread.join();
+        drop(receiver);
+    }
+}
+
+// with a session token from an earlier connection the server gives us our old player back,
+// with conditions the connection is made worse on purpose
+pub fn connect(
+    address: &str,
+    transport: TransportKind,
+    conditions: Option<NetworkConditions>,
+    session: Option<u64>,
+) -> Result<ServerConnection, String> {
+    let mut client: Box<dyn ClientTransport> = match transport {
+        TransportKind::Tcp => {
+            Box::new(TcpClientTransport::connect(address).map_err(|err| err.to_string())?)
+        }
+        TransportKind::Udp => {
+            Box::new(UdpClientTransport::connect(address).map_err(|err| err.to_string())?)
+        }
+    };
+    if let Some(conditions) = conditions {
+        println!("Simulating a bad connection: {:?}", conditions);
+        client = Box::new(SimulatedClientTransport::new(client, conditions));
+    }
+    println!("Running client on address {} ({:?})", address, transport);
+
+    let received = handshake(client.as_mut(), session)?;
 
     let (tx, rx) = mspc::channel::<Packet>(); // send from game thread to connection thread
     let (tx2, rx2) = mspc::channel::<Packet>(); // send to game thread from connection thread
+    for packet in received {
+        tx2.send(packet).unwrap();
+    }
 
-    thread::spawn(move || {
+    let stats = Arc::new(Mutex::new(NetworkStats::default()));
+    let thread_stats = stats.clone();
+    let waker = client.waker();
+    let thread = thread::spawn(move || {
+        let mut pinger = Pinger::default();
         loop {
+            let now = Instant::now();
+            thread_stats.lock().unwrap().update(now);
+            if let Some(ping) = pinger.ping(now) {
+                thread_stats.lock().unwrap().sent.count(&ping, now);
+                if client.send(ping).is_err() {
+                    println!("Connection lost client");
+                    return;
+                }
+            }
+
             // read from server and send to game thread
-            match try_read_tcp(&mut client) {
-                NetworkResult::Ok(buf) => {
-                    if let Some(packet) = deserialize_to_packet(buf) { tx2
-                    .send(packet)
-                    .expect("Failed to send packet to game thread") };
+            match client.poll() {
+                Ok(packets) => {
+                    let now = Instant::now();
+                    for packet in packets {
+                        let mut stats = thread_stats.lock().unwrap();
+                        stats.received.count(&packet, now);
+                        // answers to our pings stay here
+                        if let Packet::ConnectionPacket(ConnectionPacket::PongPacket(pong)) =
+                            &packet
+                        {
+                            if let Some(rtt) = pinger.pong(pong, now) {
+                                stats.rtt = Some(rtt);
+                            }
+                            continue;
+                        }
+                        drop(stats);
+                        // the server is closing the connection, nothing else will arrive
+                        let goodbye = matches!(
+                            packet,
+                            Packet::ConnectionPacket(
+                                ConnectionPacket::GoodbyePacket(_)
+                                    | ConnectionPacket::RejectPacket(_)
+                            )
+                        );
+                        if tx2.send(packet).is_err() {
+                            say_goodbye(client.as_mut());
+                            return;
+                        }
+                        if goodbye {
+                            return;
+                        }
+                    }
                 }
-                NetworkResult::WouldBlock => (),
-                NetworkResult::ConnectionLost => {
-                    println!("Connection lost client");
-                    break;
+                Err(err) => {
+                    println!("Connection lost client: {:?}", err);
+                    return;
                 }
             };
 
-            // send to server
-            match rx.try_recv() {
-                Ok(packet) => serialize_and_send(&mut client, packet).unwrap(),
-                Err(mspc::TryRecvError::Empty) => (),
-                Err(mspc::TryRecvError::Disconnected) => {
-                    println!("Connection lost client");
-                    break;
+            // send to server, everything the game queued since we last woke up
+            loop {
+                match rx.try_recv() {
+                    Ok(packet) => {
+                        thread_stats
+                            .lock()
+                            .unwrap()
+                            .sent
+                            .count(&packet, Instant::now());
+                        if client.send(packet).is_err() {
+                            println!("Connection lost client");
+                            return;
+                        }
+                    }
+                    Err(mspc::TryRecvError::Empty) => break,
+                    // the game is closing
+                    Err(mspc::TryRecvError::Disconnected) => {
+                        say_goodbye(client.as_mut());
+                        return;
+                    }
                 }
             }
+
+            // until the server sends something, the game wakes us when it has something to send
+            client.wait(pinger.until_next(Instant::now()));
         }
     });
 
+    Ok(ServerConnection {
+        sender: tx,
+        receiver: rx2,
+        stats,
+        waker,
+        thread,
+    })
+}
+
+fn say_goodbye(client: &mut dyn ClientTransport) {
+    let _ = client.send(Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(
+        Goodbye {
+            reason: GoodbyeReason::Quit,
+        },
+    )));
+    // flushes whatever is still waiting to be sent
+    let _ = client.poll();
+}
+
+// connects on another thread so the game can keep drawing, the result arrives once it's done
+pub fn connect_in_background(
+    address: String,
+    transport: TransportKind,
+    conditions: Option<NetworkConditions>,
+    session: Option<u64>,
+) -> mspc::Receiver<Result<ServerConnection, String>> {
+    let (tx, rx) = mspc::channel();
+    thread::spawn(move || {
+        // nobody is waiting anymore if the game was closed in the meantime
+        let _ = tx.send(connect(&address, transport, conditions, session));
+    });
+    rx
+}
+
+// a spectator joins without a player and only watches
+pub fn client(
+    address: &str,
+    transport: TransportKind,
+    conditions: Option<NetworkConditions>,
+    spectate: bool,
+    level: String,
+) {
+    let connection = match connect(address, transport, conditions, None) {
+        Ok(connection) => connection,
+        Err(reason) => {
+            println!("Could not join the server: {}", reason);
+            return;
+        }
+    };
+
     // run game in main thread
-    let mut game = Game::new(tx, rx2);
+    let mut game = Game::new(
+        address.to_string(),
+        transport,
+        conditions,
+        connection,
+        spectate,
+        level,
+    );
     game.run();
     println!("Bye bye!");
 }
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// everything about joining a server, before the client has a player
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+// named like the variants of the other packet enums
+#[allow(clippy::enum_variant_names)]
+pub enum ConnectionPacket {
+    HelloPacket(Hello),
+    RejectPacket(Reject),
+    ServerInfoPacket(ServerInfo),
+    GoodbyePacket(Goodbye),
+    ResumePacket(Resume),
+    PingPacket(Ping),
+    PongPacket(Pong),
+}
+
+// the first packet a client sends
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub protocol_version: u16,
+}
+
+// sent instead of a hello by a client that lost its connection,
+// it gets its old player back if the server still has it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Resume {
+    pub protocol_version: u16,
+    pub session: u64,
+}
+
+// the server won't let the client in, it should disconnect
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reject {
+    pub reason: RejectReason,
+}
+
+// sent right before the client id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub motd: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub seed: u64, // shown in the hud, to replay a game with server --seed
+}
+
+// either side is closing the connection on purpose
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Goodbye {
+    pub reason: GoodbyeReason,
+}
+
+// the client measures the round trip time with these, the server answers every ping right away
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ping {
+    pub sequence: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Pong {
+    pub sequence: u32, // of the ping it answers
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GoodbyeReason {
+    Quit,
+    ServerShutdown,
+}
+
+impl fmt::Display for GoodbyeReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoodbyeReason::Quit => write!(f, "quit"),
+            GoodbyeReason::ServerShutdown => write!(f, "the server shut down"),
+        }
+    }
+}
+
+// IncompatibleVersion has to stay first, so old clients can still read it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    IncompatibleVersion { server_version: u16 },
+    ServerFull { max_players: u32 },
+    UnknownLevel { level: String }, // the client asked for a level the server can't load
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RejectReason::IncompatibleVersion { server_version } => write!(
+                f,
+                "incompatible server version (server speaks protocol {}, we speak {})",
+                server_version,
+                crate::networking::packet::PROTOCOL_VERSION
+            ),
+            RejectReason::ServerFull { max_players } => {
+                write!(f, "the server is full ({} players)", max_players)
+            }
+            RejectReason::UnknownLevel { level } => {
+                write!(f, "the server doesn't have the level {}", level)
+            }
+        }
+    }
+}
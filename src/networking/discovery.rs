@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::networking::shared::DISCOVERY_PORT;
+use crate::networking::transport::TransportKind;
+
+const QUERY: &[u8] = b"sea2d?";
+const ANSWER: &[u8] = b"sea2d!";
+// in bytes, longer server names are refused when the server starts
+pub const MAX_NAME_LENGTH: usize = 64;
+// the name is short, but a level path can be as long as the file system allows
+const MAX_ANSWER_SIZE: usize = 8 * 1024;
+
+// what a server tells clients looking for games on the local network
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerAnnouncement {
+    pub server_id: u64, // random, the same server can answer on several addresses
+    pub name: String,
+    pub port: u16, // of the game itself, discovery has its own
+    pub transport: TransportKind,
+    pub protocol_version: u16,
+    pub players: u32,
+    pub max_players: u32,
+    pub level: Option<String>, // where most players are, none on an empty server
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr, // to connect to
+    pub announcement: ServerAnnouncement,
+}
+
+// the server's side, answers every query that arrived since the last call
+pub struct DiscoveryResponder {
+    socket: UdpSocket,
+}
+
+impl DiscoveryResponder {
+    pub fn bind(address: &str) -> std::io::Result<DiscoveryResponder> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(DiscoveryResponder { socket })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // the announcement is only made if someone asked
+    pub fn answer(&self, announcement: impl FnOnce() -> ServerAnnouncement) {
+        let mut buf = [0; 64];
+        let mut askers = Vec::new();
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((size, from)) if &buf[..size] == QUERY => askers.push(from),
+                Ok(_) => (), // not for us
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                // windows reports closed ports of earlier askers here
+                Err(_) => continue,
+            }
+        }
+        if askers.is_empty() {
+            return;
+        }
+        let mut answer = ANSWER.to_vec();
+        answer.extend(bincode::serialize(&announcement()).expect("Failed to serialize"));
+        for asker in askers {
+            let _ = self.socket.send_to(&answer, asker);
+        }
+    }
+}
+
+// the broadcast doesn't always come back to this machine, so localhost is asked as well
+pub fn lan_targets() -> Vec<SocketAddr> {
+    vec![
+        SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)),
+        SocketAddr::from((Ipv4Addr::LOCALHOST, DISCOVERY_PORT)),
+    ]
+}
+
+// asks the targets and waits for answers, every server is listed once
+pub fn discover(
+    targets: &[SocketAddr],
+    timeout: Duration,
+) -> std::io::Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    for target in targets {
+        // no network, or broadcasts aren't allowed, the other targets can still answer
+        let _ = socket.send_to(QUERY, target);
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut seen = HashSet::new();
+    let mut servers = Vec::new();
+    let mut buf = [0; MAX_ANSWER_SIZE];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(left))?;
+        let (size, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref err)
+                if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut =>
+            {
+                break;
+            }
+            Err(_) => continue,
+        };
+        let Some(announcement) = buf[..size]
+            .strip_prefix(ANSWER)
+            .and_then(|data| bincode::deserialize::<ServerAnnouncement>(data).ok())
+        else {
+            continue;
+        };
+        if seen.insert(announcement.server_id) {
+            servers.push(DiscoveredServer {
+                address: SocketAddr::new(from.ip(), announcement.port),
+                announcement,
+            });
+        }
+    }
+    Ok(servers)
+}
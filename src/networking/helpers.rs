@@ -1,110 +1,107 @@
-use std::io::{ErrorKind, Read, Write};
-use std::net::TcpStream;
+use std::fmt;
 
-use crate::networking::packet::{ClientID, Packet, PacketInternal};
-use crate::networking::player_packets::{
-    PlayerAnimation, PlayerDisconnect, PlayerLevel, PlayerPacket, PlayerPosition, PlayerWelcome,
-};
-use crate::networking::shared::MAX_PACKET_SIZE;
+use crate::networking::packet::{DecodeError, Packet, PacketInternal};
+use crate::networking::shared::MAX_FRAME_SIZE;
 
-pub enum NetworkResult {
-    Ok(Vec<u8>),
-    WouldBlock,
-    ConnectionLost,
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    // more than the size prefix can say or the other side accepts
+    TooLarge { size: usize, limit: usize },
 }
 
-pub fn try_read_tcp(stream: &mut TcpStream) -> NetworkResult {
-    let mut size = vec![0; 2];
-    match stream.read_exact(&mut size) {
-        Ok(_) => {
-            let size = u16::from_le_bytes([size[0], size[1]]) as usize;
-            let mut buf = vec![0; size];
-            match stream.read_exact(&mut buf) {
-                Ok(_) => NetworkResult::Ok(buf),
-                Err(ref err) if err.kind() == ErrorKind::WouldBlock => NetworkResult::WouldBlock,
-                Err(_) => NetworkResult::ConnectionLost,
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::TooLarge { size, limit } => {
+                write!(f, "frame of {} bytes is over the limit of {}", size, limit)
             }
         }
-        Err(ref err) if err.kind() == ErrorKind::WouldBlock => NetworkResult::WouldBlock,
-        Err(_) => NetworkResult::ConnectionLost,
     }
 }
 
-pub fn prepend_size(buf: &mut Vec<u8>) {
-    let size = (buf.len() as u16).to_le_bytes();
-    buf.insert(0, size[0]);
-    buf.insert(1, size[1]);
-    if buf.len() > MAX_PACKET_SIZE {
-        panic!("Packet too large");
-    }
+// turns the bytes of one stream back into frames, a read can stop anywhere,
+// even in the middle of a frame's size, so bytes are kept until the whole frame is there
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    limit: usize,
+    // after a bad size we don't know where the next frame starts, the stream is useless
+    failed: Option<FrameError>,
 }
 
-pub fn serialize_and_send(stream: &mut TcpStream, packet: Packet) -> Option<()> {
-    //println!("serializing packet {:?}", packet);
-    let packet_int = match packet.clone() {
-        Packet::ClientIDPacket(inner) => PacketInternal::new(inner).unwrap(),
-        Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(inner)) => {
-            PacketInternal::new(inner).unwrap()
+impl FrameDecoder {
+    pub fn new(limit: usize) -> FrameDecoder {
+        FrameDecoder {
+            buf: Vec::new(),
+            limit: limit.min(MAX_FRAME_SIZE),
+            failed: None,
         }
-        Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(inner)) => {
-            PacketInternal::new(inner).unwrap()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if self.failed.is_none() {
+            self.buf.extend_from_slice(data);
         }
-        Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(inner)) => {
-            PacketInternal::new(inner).unwrap()
+    }
+
+    // the next whole frame, none if it hasn't fully arrived yet
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameError> {
+        if let Some(err) = &self.failed {
+            return Err(err.clone());
         }
-        Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(inner)) => {
-            PacketInternal::new(inner).unwrap()
+        if self.buf.len() < 2 {
+            return Ok(None);
         }
-        Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(inner)) => {
-            PacketInternal::new(inner).unwrap()
+        let size = u16::from_le_bytes([self.buf[0], self.buf[1]]) as usize;
+        if size > self.limit {
+            let err = FrameError::TooLarge {
+                size,
+                limit: self.limit,
+            };
+            self.buf = Vec::new();
+            self.failed = Some(err.clone());
+            return Err(err);
         }
-    };
-    //println!("internal packet {:?}", packet_int);
-    let mut send = bincode::serialize(&packet_int).unwrap();
-    prepend_size(&mut send);
-    //println!("message sent {:?}", packet);
-    //println!("data sent {:?}", send);
-    stream.write_all(&send).ok()
+        if self.buf.len() < 2 + size {
+            return Ok(None);
+        }
+        let frame = self.buf[2..2 + size].to_vec();
+        self.buf.drain(..2 + size);
+        Ok(Some(frame))
+    }
 }
 
-pub fn deserialize_to_packet(buf: Vec<u8>) -> Option<Packet> {
-    let packet_int = bincode::deserialize::<PacketInternal>(&buf);
-    //println!("Received packet: {:?}", packet_int);
-
-    match packet_int {
-        Ok(packet_int) => {
-            if let Some(packet) = packet_int.try_deserialize::<ClientID>() { return Some(Packet::ClientIDPacket(packet)) };
-
-            if let Some(packet) = packet_int.try_deserialize::<PlayerAnimation>() {
-                return Some(Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(
-                    packet,
-                )));
-            };
+pub fn prepend_size(buf: &mut Vec<u8>) -> Result<(), FrameError> {
+    if buf.len() > MAX_FRAME_SIZE {
+        return Err(FrameError::TooLarge {
+            size: buf.len(),
+            limit: MAX_FRAME_SIZE,
+        });
+    }
+    let size = (buf.len() as u16).to_le_bytes();
+    buf.splice(0..0, size);
+    Ok(())
+}
 
-            if let Some(packet) = packet_int.try_deserialize::<PlayerPosition>() {
-                return Some(Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(
-                    packet,
-                )));
-            };
+// what goes over a stream for one packet
+pub fn frame_packet(packet: Packet) -> Result<Vec<u8>, FrameError> {
+    let mut send = serialize_packet(packet);
+    prepend_size(&mut send)?;
+    //println!("message sent {:?}", packet);
+    //println!("data sent {:?}", send);
+    Ok(send)
+}
 
-            if let Some(packet) = packet_int.try_deserialize::<PlayerWelcome>() {
-                return Some(Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(
-                    packet,
-                )));
-            };
+// the bytes of a packet without any framing, shared by every transport
+pub fn serialize_packet(packet: Packet) -> Vec<u8> {
+    //println!("serializing packet {:?}", packet);
+    let packet_int = PacketInternal::encode(&packet);
+    //println!("internal packet {:?}", packet_int);
+    bincode::serialize(&packet_int).unwrap()
+}
 
-            if let Some(packet) = packet_int.try_deserialize::<PlayerDisconnect>() {
-                return Some(Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(
-                    packet,
-                )));
-            };
-            if let Some(packet) = packet_int.try_deserialize::<PlayerLevel>() {
-                return Some(Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(
-                    packet,
-                )));
-            }
-            None
-        }
-        Err(err) => panic!("Failed to deserialize packet: {:?}", err),
-    }
+// never panics, whatever the other side sent
+pub fn deserialize_to_packet(buf: Vec<u8>) -> Result<Packet, DecodeError> {
+    let packet_int = bincode::deserialize::<PacketInternal>(&buf)?;
+    //println!("Received packet: {:?}", packet_int);
+    packet_int.decode()
 }
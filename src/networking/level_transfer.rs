@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::environment::generator::parse_generated_name;
+use crate::environment::level::{Level, LevelData};
+use crate::networking::world_packets::LevelChunk;
+
+// small enough to fit into a single frame or datagram with room to spare
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+// fnv-1a, only has to be stable between builds, not secure
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn level_checksum(data: &LevelData) -> u64 {
+    checksum(&bincode::serialize(data).expect("Failed to serialize level"))
+}
+
+// serialized levels on the server, made the first time a player is sent to one
+#[derive(Default)]
+pub struct LevelCache {
+    levels: HashMap<String, (u64, Vec<u8>)>,
+}
+
+impl LevelCache {
+    pub fn checksum(&mut self, name: &str, level: &Level) -> u64 {
+        self.levels
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let bytes =
+                    bincode::serialize(&level.to_data()).expect("Failed to serialize level");
+                (checksum(&bytes), bytes)
+            })
+            .0
+    }
+
+    // nothing if no loaded level has this checksum
+    pub fn chunks(&self, checksum: u64) -> Vec<LevelChunk> {
+        let Some((_, bytes)) = self.levels.values().find(|(sum, _)| *sum == checksum) else {
+            return Vec::new();
+        };
+        let count = bytes.len().div_ceil(CHUNK_SIZE) as u32;
+        bytes
+            .chunks(CHUNK_SIZE)
+            .enumerate()
+            .map(|(index, data)| LevelChunk {
+                checksum,
+                index: index as u32,
+                count,
+                data: data.to_vec(),
+            })
+            .collect()
+    }
+
+    // drops levels nobody plays on anymore, generated ones would pile up otherwise
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.levels.retain(|name, _| keep(name));
+    }
+}
+
+// a level the client is waiting for, chunks can come in any order
+pub struct LevelDownload {
+    pub level: String,
+    pub checksum: u64,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl LevelDownload {
+    pub fn new(level: String, checksum: u64) -> LevelDownload {
+        LevelDownload {
+            level,
+            checksum,
+            chunks: Vec::new(),
+        }
+    }
+
+    // the level once everything arrived, an error if it doesn't match the checksum
+    pub fn push(&mut self, chunk: LevelChunk) -> Result<Option<LevelData>, String> {
+        if chunk.checksum != self.checksum || chunk.index >= chunk.count {
+            return Ok(None); // left over from another download
+        }
+        if self.chunks.len() != chunk.count as usize {
+            self.chunks = vec![None; chunk.count as usize];
+        }
+        self.chunks[chunk.index as usize] = Some(chunk.data);
+        if self.chunks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let bytes: Vec<u8> = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+        if checksum(&bytes) != self.checksum {
+            return Err(format!("level {} doesn't match its checksum", self.level));
+        }
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|err| format!("level {} is broken: {}", self.level, err))
+    }
+}
+
+// a level the client already has on disk or can generate itself,
+// if it's the same one the server uses
+pub fn load_local(path: &str, checksum: u64) -> Option<LevelData> {
+    if parse_generated_name(path).is_none() && !std::path::Path::new(path).is_file() {
+        return None;
+    }
+    let data = Level::load(path).ok()?.to_data();
+    (level_checksum(&data) == checksum).then_some(data)
+}
@@ -1,6 +1,17 @@
 pub mod client;
+pub mod connection_packets;
+pub mod discovery;
 pub mod helpers;
+pub mod level_transfer;
 pub mod packet;
 pub mod player_packets;
+pub mod poller;
 pub mod server;
+pub mod server_config;
 pub mod shared;
+pub mod simulated;
+pub mod stats;
+pub mod tcp;
+pub mod transport;
+pub mod udp;
+pub mod world_packets;
@@ -1,16 +1,23 @@
-use fnv::FnvHasher;
 use serde_derive::{Deserialize, Serialize};
-use std::error::Error;
+use std::fmt;
 
+use crate::networking::connection_packets::ConnectionPacket;
 use crate::networking::player_packets::PlayerPacket;
+use crate::networking::world_packets::WorldPacket;
 
-use std::hash::{Hash, Hasher};
+// bump whenever a packet changes shape or a new one is registered,
+// clients and servers only talk to each other if theirs match
+pub const PROTOCOL_VERSION: u16 = 14;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-
+// every kind of packet is named after the enum it wraps
+#[allow(clippy::enum_variant_names)]
 pub enum Packet {
     ClientIDPacket(ClientID),
     PlayerPacket(PlayerPacket),
+    WorldPacket(WorldPacket),
+    ConnectionPacket(ConnectionPacket),
+    ChatPacket(ChatMessage),
 }
 
 pub enum ServerPacket {
@@ -27,37 +34,205 @@ pub struct ServerInternal {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ClientID {
     pub id: u64,
+    // only the player the id belongs to gets the token, it's needed to resume after a lost connection
+    pub session: Option<u64>,
+}
+
+// the server fills in the sender, messages without one come from the server itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub sender: Option<u64>,
+    pub text: String,
+}
+
+// every packet that can go over the wire and its id, ids are never reused or changed
+pub const PACKET_REGISTRY: &[(u16, &str)] = &[
+    (0, "ClientID"),
+    (1, "Hello"),
+    (2, "Reject"),
+    (3, "PlayerWelcome"),
+    (4, "PlayerPosition"),
+    (5, "PlayerDisconnect"),
+    (6, "PlayerAnimation"),
+    (7, "PlayerLevel"),
+    (8, "PlayerInputCommand"),
+    (9, "PlayerRespawn"),
+    (10, "WorldState"),
+    (11, "ServerInfo"),
+    (12, "Goodbye"),
+    (13, "Resume"),
+    (14, "LevelRequest"),
+    (15, "LevelChunk"),
+    (16, "ChatMessage"),
+    (17, "Ping"),
+    (18, "Pong"),
+    (19, "PlayerSpectate"),
+    (20, "PlayerList"),
+];
+
+pub fn packet_name(id: u16) -> Option<&'static str> {
+    PACKET_REGISTRY
+        .iter()
+        .find(|(registered, _)| *registered == id)
+        .map(|(_, name)| *name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    UnknownPacket(u16),
+    Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownPacket(id) => write!(f, "unknown packet id {}", id),
+            DecodeError::Malformed(err) => write!(f, "malformed packet: {}", err),
+        }
+    }
 }
 
-fn get_type_id<Type: 'static>() -> u64 {
-    let mut hasher = FnvHasher::default();
-    //let type_id = TypeId::of::<Type>();
-    let type_name = std::any::type_name::<Type>();
-    //println!("Type name: {:?}",type_name);
-    type_name.hash(&mut hasher);
-    //type_id.hash(&mut hasher);
-    hasher.finish()
+impl From<bincode::Error> for DecodeError {
+    fn from(err: bincode::Error) -> Self {
+        DecodeError::Malformed(err.to_string())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PacketInternal {
-    pub type_id: u64,
+    pub id: u16,
     pub data: Vec<u8>,
 }
+
 impl PacketInternal {
-    pub fn new<T: serde::Serialize + 'static>(data: T) -> Result<Self, Box<dyn Error>> {
-        let type_id = get_type_id::<T>();
-        let data = bincode::serialize(&data)?;
-        Ok(Self { type_id, data })
-    }
-    #[must_use]
-    pub fn try_deserialize<T: serde::de::DeserializeOwned + 'static>(&self) -> Option<T> {
-        if self.type_id == get_type_id::<T>() {
-            //println!("Type match");
-            bincode::deserialize(&self.data).map_or_else(|_| None, |data| Some(data))
-        } else {
-            //println!("Type mismatch");
-            None
+    pub fn encode(packet: &Packet) -> PacketInternal {
+        let (id, data) = match packet {
+            Packet::ClientIDPacket(inner) => (0, bincode::serialize(inner)),
+            Packet::ConnectionPacket(ConnectionPacket::HelloPacket(inner)) => {
+                (1, bincode::serialize(inner))
+            }
+            Packet::ConnectionPacket(ConnectionPacket::RejectPacket(inner)) => {
+                (2, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(inner)) => {
+                (3, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(inner)) => {
+                (4, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(inner)) => {
+                (5, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(inner)) => {
+                (6, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(inner)) => {
+                (7, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerInputPacket(inner)) => {
+                (8, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerRespawnPacket(inner)) => {
+                (9, bincode::serialize(inner))
+            }
+            Packet::WorldPacket(WorldPacket::WorldStatePacket(inner)) => {
+                (10, bincode::serialize(inner))
+            }
+            Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(inner)) => {
+                (11, bincode::serialize(inner))
+            }
+            Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(inner)) => {
+                (12, bincode::serialize(inner))
+            }
+            Packet::ConnectionPacket(ConnectionPacket::ResumePacket(inner)) => {
+                (13, bincode::serialize(inner))
+            }
+            Packet::WorldPacket(WorldPacket::LevelRequestPacket(inner)) => {
+                (14, bincode::serialize(inner))
+            }
+            Packet::WorldPacket(WorldPacket::LevelChunkPacket(inner)) => {
+                (15, bincode::serialize(inner))
+            }
+            Packet::ChatPacket(inner) => (16, bincode::serialize(inner)),
+            Packet::ConnectionPacket(ConnectionPacket::PingPacket(inner)) => {
+                (17, bincode::serialize(inner))
+            }
+            Packet::ConnectionPacket(ConnectionPacket::PongPacket(inner)) => {
+                (18, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerSpectatePacket(inner)) => {
+                (19, bincode::serialize(inner))
+            }
+            Packet::PlayerPacket(PlayerPacket::PlayerListPacket(inner)) => {
+                (20, bincode::serialize(inner))
+            }
+        };
+        PacketInternal {
+            id,
+            data: data.expect("Failed to serialize packet"),
         }
     }
+
+    pub fn decode(&self) -> Result<Packet, DecodeError> {
+        let name = packet_name(self.id).ok_or(DecodeError::UnknownPacket(self.id))?;
+        self.decode_data()
+            .map_err(|err| DecodeError::Malformed(format!("{}: {}", name, err)))
+    }
+
+    fn decode_data(&self) -> bincode::Result<Packet> {
+        let data = &self.data;
+        Ok(match self.id {
+            0 => Packet::ClientIDPacket(bincode::deserialize(data)?),
+            1 => {
+                Packet::ConnectionPacket(ConnectionPacket::HelloPacket(bincode::deserialize(data)?))
+            }
+            2 => Packet::ConnectionPacket(ConnectionPacket::RejectPacket(bincode::deserialize(
+                data,
+            )?)),
+            3 => Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(bincode::deserialize(
+                data,
+            )?)),
+            4 => Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(bincode::deserialize(
+                data,
+            )?)),
+            5 => Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(bincode::deserialize(
+                data,
+            )?)),
+            6 => Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(bincode::deserialize(
+                data,
+            )?)),
+            7 => Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(bincode::deserialize(data)?)),
+            8 => Packet::PlayerPacket(PlayerPacket::PlayerInputPacket(bincode::deserialize(data)?)),
+            9 => Packet::PlayerPacket(PlayerPacket::PlayerRespawnPacket(bincode::deserialize(
+                data,
+            )?)),
+            10 => Packet::WorldPacket(WorldPacket::WorldStatePacket(bincode::deserialize(data)?)),
+            11 => Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(
+                bincode::deserialize(data)?,
+            )),
+            12 => Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(bincode::deserialize(
+                data,
+            )?)),
+            13 => Packet::ConnectionPacket(ConnectionPacket::ResumePacket(bincode::deserialize(
+                data,
+            )?)),
+            14 => Packet::WorldPacket(WorldPacket::LevelRequestPacket(bincode::deserialize(data)?)),
+            15 => Packet::WorldPacket(WorldPacket::LevelChunkPacket(bincode::deserialize(data)?)),
+            16 => Packet::ChatPacket(bincode::deserialize(data)?),
+            17 => {
+                Packet::ConnectionPacket(ConnectionPacket::PingPacket(bincode::deserialize(data)?))
+            }
+            18 => {
+                Packet::ConnectionPacket(ConnectionPacket::PongPacket(bincode::deserialize(data)?))
+            }
+            19 => Packet::PlayerPacket(PlayerPacket::PlayerSpectatePacket(bincode::deserialize(
+                data,
+            )?)),
+            20 => Packet::PlayerPacket(PlayerPacket::PlayerListPacket(bincode::deserialize(data)?)),
+            id => {
+                let err = format!("packet {} is registered but can't be decoded", id);
+                return Err(Box::new(bincode::ErrorKind::Custom(err)));
+            }
+        })
+    }
 }
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
-use crate::entities::animation_data::AnimationData;
+use crate::entities::animation_data::{AnimationState, Facing};
+use crate::simulation::input::PlayerInput;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum PlayerPacket {
@@ -9,6 +10,10 @@ pub enum PlayerPacket {
     PlayerDisconnectPacket(PlayerDisconnect),
     PlayerAnimationPacket(PlayerAnimation),
     PlayerLevelPacket(PlayerLevel),
+    PlayerInputPacket(PlayerInputCommand),
+    PlayerRespawnPacket(PlayerRespawn),
+    PlayerSpectatePacket(PlayerSpectate),
+    PlayerListPacket(PlayerList),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -24,6 +29,8 @@ pub struct PlayerPosition {
     pub player_id: u64,
     pub x: f64,
     pub y: f64,
+    pub time: f64,     // server time of the level the player is on
+    pub sequence: u32, // last input of this player the server simulated
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -38,14 +45,62 @@ pub struct PlayerDisconnect {
     pub id: u64,
 }
 
+// which of the already loaded animations to show, textures are never sent
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlayerAnimation {
-    pub id: u64,
-    pub animation_data: AnimationData,
+    pub player_id: u64,
+    pub state: AnimationState,
+    pub frame: u16,
+    pub facing: Facing,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlayerLevel {
     pub player_id: u64,
     pub level: String,
+    pub checksum: u64, // of the level's contents, filled in by the server
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerInputCommand {
+    pub player_id: u64,
+    pub sequence: u32, // counts simulation steps, starts at 1
+    pub input: PlayerInput,
+    // the unacknowledged inputs before this one, oldest first, in case their packets got lost
+    pub earlier: Vec<PlayerInput>,
+}
+
+impl PlayerInputCommand {
+    // every input in the command with its sequence number, oldest first
+    pub fn inputs(&self) -> impl Iterator<Item = (u32, PlayerInput)> + '_ {
+        let first = self.sequence.checked_sub(self.earlier.len() as u32);
+        self.earlier
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, input)| Some((first? + i as u32, *input)))
+            .chain(std::iter::once((self.sequence, self.input)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerRespawn {
+    pub player_id: u64,
+}
+
+// sent instead of a level packet to watch a level without a player of our own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerSpectate {
+    pub level: String,
+}
+
+// everyone on the server, sent to all clients whenever someone joins, leaves or starts watching
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerList {
+    pub players: Vec<PlayerListEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerListEntry {
+    pub player_id: u64,
+    pub spectator: bool,
 }
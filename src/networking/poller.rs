@@ -0,0 +1,56 @@
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use mio::{Events, Poll, Registry, Token, Waker};
+
+const WAKER: Token = Token(usize::MAX);
+
+// lets a thread sleep until one of its sockets has something for it,
+// the waker ends the sleep early from another thread
+pub struct Poller {
+    poll: Poll,
+    events: Events,
+    waker: Arc<Waker>,
+}
+
+impl Poller {
+    pub fn new() -> std::io::Result<Poller> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        Ok(Poller {
+            poll,
+            events: Events::with_capacity(256),
+            waker,
+        })
+    }
+
+    pub fn registry(&self) -> &Registry {
+        self.poll.registry()
+    }
+
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
+
+    // the sockets that became ready, none after a timeout or a wake up,
+    // readiness is only reported once so the sockets have to be read until they would block
+    pub fn wait(&mut self, timeout: Duration) -> Vec<Token> {
+        match self.poll.poll(&mut self.events, Some(timeout)) {
+            Ok(()) => self
+                .events
+                .iter()
+                .map(|event| event.token())
+                .filter(|token| *token != WAKER)
+                .collect(),
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => Vec::new(),
+            Err(err) => {
+                // don't spin if polling keeps failing
+                println!("Polling failed: {}", err);
+                thread::sleep(timeout);
+                Vec::new()
+            }
+        }
+    }
+}
@@ -1,17 +1,36 @@
 use crate::entities::player::Player;
-use crate::networking::helpers::{
-    NetworkResult, deserialize_to_packet, serialize_and_send, try_read_tcp,
+use crate::environment::level::is_known_level;
+use crate::networking::connection_packets::{
+    ConnectionPacket, Goodbye, GoodbyeReason, Hello, Ping, Pong, Reject, RejectReason, ServerInfo,
 };
-use crate::networking::packet::{ClientID, Packet, ServerInternal, ServerPacket};
-use crate::networking::{player_packets::*, shared::LOCAL};
-use rand::Rng;
-use std::collections::{HashMap, HashSet};
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc as mspc;
-use std::thread;
-
-fn new_client_id(set: &HashSet<u64>) -> u64 {
-    let mut rng = rand::rng();
+use crate::networking::discovery::{DiscoveryResponder, ServerAnnouncement};
+use crate::networking::level_transfer::{LevelCache, checksum};
+use crate::networking::packet::{ChatMessage, ClientID, PROTOCOL_VERSION, Packet, ServerPacket};
+use crate::networking::player_packets::*;
+use crate::networking::server_config::ServerConfig;
+use crate::networking::shared::{DISCOVERY_PORT, MAX_CHAT_LENGTH, player_name};
+use crate::networking::tcp::TcpServerTransport;
+use crate::networking::transport::{ServerTransport, TransportKind};
+use crate::networking::udp::UdpServerTransport;
+use crate::networking::world_packets::{LevelChunk, LevelRequest, WorldPacket};
+use crate::simulation::{
+    TIME_STEP,
+    input::{InputBuffer, PlayerInput},
+    world::{World, WorldEvent},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// how long a stopping server waits for its goodbyes to be acknowledged
+const SHUTDOWN_FLUSH: Duration = Duration::from_secs(1);
+// level downloads go out a bit every step instead of all at once,
+// a big level would fill a tcp client's send buffer past its limit otherwise
+const CHUNKS_PER_STEP: usize = 2;
+
+fn new_client_id(set: &HashSet<u64>, rng: &mut StdRng) -> u64 {
     let mut random_u64: u64 = rng.random();
     while set.get(&random_u64).is_some() {
         random_u64 = rng.random();
@@ -19,162 +38,934 @@ fn new_client_id(set: &HashSet<u64>) -> u64 {
     random_u64
 }
 
+// session tokens have to be hard to guess, they are all it takes to take over a player
+fn new_session_token(sessions: &HashMap<u64, u64>, suspended: &HashMap<u64, Suspended>) -> u64 {
+    let mut rng = rand::rng();
+    let mut token: u64 = rng.random();
+    while sessions.contains_key(&token) || suspended.contains_key(&token) {
+        token = rng.random();
+    }
+    token
+}
+
+// what's left of a chat message after removing anything that could mess up the chat log
+fn clean_chat(text: &str) -> Option<String> {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CHAT_LENGTH)
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// whether a client may join, before it gets an id
+pub fn check_hello(hello: &Hello, joined: usize, max_players: usize) -> Result<(), RejectReason> {
+    if hello.protocol_version != PROTOCOL_VERSION {
+        return Err(RejectReason::IncompatibleVersion {
+            server_version: PROTOCOL_VERSION,
+        });
+    }
+    if joined >= max_players {
+        return Err(RejectReason::ServerFull {
+            max_players: max_players as u32,
+        });
+    }
+    Ok(())
+}
+
+// one line per event with key=value fields, so the logs can be grepped and parsed
+fn log_event(event: &str, fields: &[(&str, String)]) {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let mut line = format!("time={} event={}", time, event);
+    for (key, value) in fields {
+        if value.contains(char::is_whitespace) || value.is_empty() {
+            line += &format!(" {}={:?}", key, value);
+        } else {
+            line += &format!(" {}={}", key, value);
+        }
+    }
+    println!("{}", line);
+}
+
 fn handle_player_send(
     packet: PlayerPacket,
-    _player_id: u64,
-    players: &mut HashMap<u64, Player>,
-) -> Packet {
-    match packet {
-        PlayerPacket::PlayerPositionPacket(PlayerPosition { x, y, player_id }) => {
-            players.get_mut(&player_id).unwrap().x = x;
-            players.get_mut(&player_id).unwrap().y = y;
-            Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(PlayerPosition {
-                player_id,
-                x,
-                y,
-            }))
+    sender_id: u64,
+    worlds: &mut HashMap<String, World>,
+    inputs: &mut HashMap<u64, InputBuffer>,
+    levels: &mut LevelCache,
+    seed: u64,
+    tick_rate: u32,
+) -> Result<Option<Packet>, RejectReason> {
+    let reply = match packet {
+        // the server moves players itself, reported positions are ignored
+        PlayerPacket::PlayerPositionPacket(_) => None,
+        // the server announces disconnects itself, clients say goodbye instead
+        PlayerPacket::PlayerDisconnectPacket(_) => None,
+        // players are animated by the server's simulation, like they're moved
+        PlayerPacket::PlayerAnimationPacket(_) => None,
+        // the server tells players about each other once they can see each other
+        PlayerPacket::PlayerWelcomePacket(_) => None,
+        PlayerPacket::PlayerLevelPacket(PlayerLevel { level, .. }) => {
+            // clients only pick the level they join on, after that exits decide,
+            // a resumed client is told where it really is
+            if let Some(world) = worlds.values().find(|w| w.player(sender_id).is_some()) {
+                return Ok(Some(level_packet(world, sender_id, levels)));
+            }
+            let mut player = Player::new(sender_id);
+            player.load_player_texture(); // frames only, so the server can count them
+            add_to_world(worlds, player, &level, seed).map_err(|_| RejectReason::UnknownLevel {
+                level: level.clone(),
+            })?;
+            Some(level_packet(&worlds[&level], sender_id, levels))
         }
-        PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect { id }) => {
-            Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect {
-                id,
-            }))
-        }
-        PlayerPacket::PlayerAnimationPacket(PlayerAnimation { id, animation_data }) => {
-            if let Some(player) = players.get_mut(&id) {
-                player.animation_data = animation_data.clone();
-            }
-            Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(PlayerAnimation {
-                id,
-                animation_data,
-            }))
-        }
-        //PlayerPacket::PlayerAnimationPacket(PlayerAnimation { id, animation_data }) => {
-        //    players.get_mut(&player_id).unwrap().animation_data = Some(animation_data.clone());
-        //    return Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(PlayerAnimation {
-        //        id,
-        //        animation_data,
-        //    }));
-        //}
-        PlayerPacket::PlayerWelcomePacket(PlayerWelcome { player_id, x, y }) => {
-            Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(PlayerWelcome {
-                player_id,
-                x,
-                y,
-            }))
+        PlayerPacket::PlayerInputPacket(command) => {
+            // inputs the buffer already has are skipped
+            let buffer = inputs
+                .entry(sender_id)
+                .or_insert_with(|| InputBuffer::for_tick_rate(tick_rate));
+            for (sequence, input) in command.inputs() {
+                buffer.push(sequence, input);
+            }
+            None
         }
-        PlayerPacket::PlayerLevelPacket(PlayerLevel { player_id, level }) => {
-            players.get_mut(&player_id).unwrap().current_level = level.clone();
-            Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(PlayerLevel {
-                player_id,
-                level,
-            }))
+        // spectating is up to the server, the list only goes to clients
+        PlayerPacket::PlayerSpectatePacket(_) | PlayerPacket::PlayerListPacket(_) => None,
+        PlayerPacket::PlayerRespawnPacket(_) => {
+            for world in worlds.values_mut() {
+                let spawn = world.level.spawn_point(sender_id);
+                if let Some(player) = world.player_mut(sender_id)
+                    && player.health <= 0
+                {
+                    player.health = 100;
+                    player.reset_velocity();
+                    player.spawn_at(spawn.0, spawn.1);
+                }
+            }
+            None
         }
+    };
+    Ok(reply)
+}
+
+// which level a player is on, the checksum lets clients skip downloading it
+fn level_packet(world: &World, player_id: u64, levels: &mut LevelCache) -> Packet {
+    Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(PlayerLevel {
+        player_id,
+        level: world.level_path.clone(),
+        checksum: levels.checksum(&world.level_path, &world.level),
+    }))
+}
+
+// whether something at x, y is close enough to the viewer to be sent to it,
+// without a radius the whole level is
+fn in_view(viewer: &Player, x: f64, y: f64, radius: Option<f64>) -> bool {
+    radius.is_none_or(|radius| (x - viewer.x).hypot(y - viewer.y) <= radius)
+}
+
+fn find_player_mut(worlds: &mut HashMap<String, World>, id: u64) -> Option<&mut Player> {
+    worlds.values_mut().find_map(|world| world.player_mut(id))
+}
+
+// every level gets its own seed, so it plays the same no matter when it's loaded
+fn level_seed(seed: u64, level: &str) -> u64 {
+    seed ^ checksum(level.as_bytes())
+}
+
+// level names come from clients, so only known levels are loaded
+fn load_world<'a>(
+    worlds: &'a mut HashMap<String, World>,
+    level: &str,
+    seed: u64,
+) -> Result<&'a mut World, String> {
+    if !worlds.contains_key(level) {
+        if !is_known_level(level) {
+            return Err(format!("{} isn't a level of this server", level));
+        }
+        log_event("level_load", &[("level", level.to_string())]);
+        let world = World::new(level.to_string(), level_seed(seed, level)).inspect_err(|err| {
+            log_event(
+                "level_error",
+                &[("level", level.to_string()), ("error", err.clone())],
+            )
+        })?;
+        worlds.insert(level.to_string(), world);
     }
+    Ok(worlds.get_mut(level).unwrap())
+}
+
+fn add_to_world(
+    worlds: &mut HashMap<String, World>,
+    player: Player,
+    level: &str,
+    seed: u64,
+) -> Result<(), String> {
+    load_world(worlds, level, seed)?.add_player(player);
+    Ok(())
 }
 
-fn send_to_clients(packet: Packet, clients: &mut HashMap<u64, TcpStream>) {
-    if let Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect { id })) = packet {
-        clients.remove(&id);
+// the connected clients and the transport that reaches them
+struct Clients {
+    transport: Box<dyn ServerTransport>,
+    addresses: HashMap<u64, SocketAddr>, // uuid to address
+}
+
+impl Clients {
+    fn send_to_players(&mut self, packet: Packet, ids: &[u64]) {
+        for id in ids {
+            if let Some(addr) = self.addresses.get(id) {
+                self.transport.send(*addr, packet.clone());
+            }
+        }
     }
 
-    for (_, client) in clients.iter_mut() {
-        serialize_and_send(client, packet.clone());
+    fn send_to_all(&mut self, packet: Packet) {
+        for addr in self.addresses.values() {
+            self.transport.send(*addr, packet.clone());
+        }
     }
 }
 
-pub fn server() {
-    // create a listener
-    let listener = TcpListener::bind(LOCAL).expect("Failed to bind");
-    listener
-        .set_nonblocking(true)
-        .expect("Failed to initialize non-blocking");
+// a player whose connection was lost, waiting for the client to resume
+struct Suspended {
+    id: u64,
+    level: String,
+    player: Player,
+    since: Instant,
+}
 
-    let mut clients: HashMap<u64, TcpStream> = HashMap::new(); // uuid to tcp stream
-    // intermediate step to handle client messages (so we dont need arc(mutex) on everything to pass between threads)
-    let (server_sender, server_receiver) = mspc::channel::<ServerPacket>();
+// everything the server keeps track of, fed with transport events
+pub struct Server {
+    config: ServerConfig,
+    clients: Clients,
+    ip_to_uuid: HashMap<SocketAddr, u64>,
+    uuid_to_ip: HashMap<u64, SocketAddr>,
+    used_uuid: HashSet<u64>,
+    sessions: HashMap<u64, u64>, // session token to the id of a connected player
+    suspended: HashMap<u64, Suspended>, // session token to a player that lost the connection
+    // one simulated world for every level that has players on it
+    worlds: HashMap<String, World>,
+    spectators: HashMap<u64, String>, // clients watching a level without a player, by id
+    inputs: HashMap<u64, InputBuffer>,
+    levels: LevelCache,
+    downloads: HashMap<u64, VecDeque<LevelChunk>>, // chunks still to send, by client id
+    interest: HashMap<u64, HashSet<u64>>,          // players each client has been told about
+    server_id: u64,                                // tells the server apart in the browser
+    seed: u64,
+    rng: StdRng, // player ids, session tokens are too important for it
+}
 
-    let mut ip_to_uuid: HashMap<std::net::SocketAddr, u64> = HashMap::new();
-    let mut uuid_to_ip: HashMap<u64, std::net::SocketAddr> = HashMap::new();
-    let mut used_uuid = HashSet::new();
+impl Server {
+    pub fn new(config: ServerConfig, transport: Box<dyn ServerTransport>) -> Server {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        Server {
+            config,
+            clients: Clients {
+                transport,
+                addresses: HashMap::new(),
+            },
+            ip_to_uuid: HashMap::new(),
+            uuid_to_ip: HashMap::new(),
+            used_uuid: HashSet::new(),
+            sessions: HashMap::new(),
+            suspended: HashMap::new(),
+            worlds: HashMap::new(),
+            spectators: HashMap::new(),
+            inputs: HashMap::new(),
+            levels: LevelCache::default(),
+            downloads: HashMap::new(),
+            interest: HashMap::new(),
+            server_id: rand::random(),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
 
-    let mut players: HashMap<u64, Player> = HashMap::new();
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-    loop {
-        //std::thread::sleep(std::time::Duration::from_secs_f64(1./5.));
-        // socket reading
-        if let Ok((mut socket, addr)) = listener.accept() {
-            println!("Client {} connected", addr);
-            let server_sender = server_sender.clone();
+    pub fn player_count(&self) -> usize {
+        self.clients.addresses.len()
+    }
 
-            // add a new uuid for the client
-            let uuid = new_client_id(&used_uuid);
-            used_uuid.insert(uuid);
-            ip_to_uuid.insert(addr, uuid);
-            uuid_to_ip.insert(uuid, addr);
+    // the answer to clients looking for servers on the local network
+    pub fn announcement(&self) -> ServerAnnouncement {
+        ServerAnnouncement {
+            server_id: self.server_id,
+            name: self.config.name.clone(),
+            port: self
+                .clients
+                .transport
+                .local_addr()
+                .map_or(self.config.port, |addr| addr.port()),
+            transport: self.config.transport,
+            protocol_version: PROTOCOL_VERSION,
+            players: self.player_count() as u32,
+            max_players: self.config.max_players as u32,
+            level: self
+                .worlds
+                .values()
+                .filter(|world| !world.players.is_empty())
+                .max_by_key(|world| world.players.len())
+                .map(|world| world.level_path.clone()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn world(&self, level: &str) -> Option<&World> {
+        self.worlds.get(level)
+    }
 
-            // add player to the player list
-            server_sender
-                .send(ServerPacket::AddPlayer(addr))
-                .expect("Failed adding client to player list");
-            clients.insert(uuid, socket.try_clone().expect("Failed to clone client"));
+    #[cfg(test)]
+    pub fn world_mut(&mut self, level: &str) -> Option<&mut World> {
+        self.worlds.get_mut(level)
+    }
+
+    pub fn poll(&mut self) {
+        for event in self.clients.transport.poll() {
+            self.handle(event);
+        }
+    }
 
-            // send the client id to the client
-            send_to_clients(Packet::ClientIDPacket(ClientID { id: uuid }), &mut clients);
+    // sleeps until a client sends something or the timeout passes
+    pub fn wait(&mut self, timeout: Duration) {
+        self.clients.transport.wait(timeout);
+    }
 
-            // read from the socket, new thread for each client
-            thread::spawn(move || {
-                loop {
-                    match try_read_tcp(&mut socket) {
-                        NetworkResult::Ok(buf) => {
-                            match deserialize_to_packet(buf) {
-                                // handling in the main thread
-                                Some(packet) => server_sender
-                                    .send(ServerPacket::ServerInternalPacket(ServerInternal {
-                                        address: addr,
-                                        packet,
-                                    }))
-                                    .expect("Failed to send internal server packet"),
-                                _ => println!("Unknown packet"),
-                            }
+    pub fn handle(&mut self, event: ServerPacket) {
+        match event {
+            ServerPacket::ServerInternalPacket(packet) => {
+                let (addr, packet) = (packet.address, packet.packet);
+                match packet {
+                    Packet::ConnectionPacket(ConnectionPacket::HelloPacket(hello)) => {
+                        self.handle_join(addr, hello, None);
+                    }
+                    Packet::ConnectionPacket(ConnectionPacket::ResumePacket(resume)) => {
+                        let hello = Hello {
+                            protocol_version: resume.protocol_version,
+                        };
+                        self.handle_join(addr, hello, Some(resume.session));
+                    }
+                    // leaving on purpose, nothing to resume
+                    Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(goodbye)) => {
+                        self.clients.transport.disconnect(addr);
+                        self.remove_client(addr, &goodbye.reason.to_string(), false);
+                    }
+                    Packet::ConnectionPacket(ConnectionPacket::PingPacket(Ping { sequence })) => {
+                        self.clients.transport.send(
+                            addr,
+                            Packet::ConnectionPacket(ConnectionPacket::PongPacket(Pong {
+                                sequence,
+                            })),
+                        );
+                    }
+                    Packet::PlayerPacket(PlayerPacket::PlayerSpectatePacket(spectate)) => {
+                        self.handle_spectate(addr, spectate.level);
+                    }
+                    Packet::PlayerPacket(packet) => {
+                        // nothing counts before the hello
+                        let Some(&sender_uuid) = self.ip_to_uuid.get(&addr) else {
+                            return;
+                        };
+                        // spectators have no player to move, respawn or put on a level
+                        if self.spectators.contains_key(&sender_uuid) {
+                            return;
                         }
-                        NetworkResult::WouldBlock => (),
-                        NetworkResult::ConnectionLost => {
-                            println!("Closing connection with: {}", addr);
-                            // send packet that signals client disconnect
-                            server_sender
-                                .send(ServerPacket::RemovePlayer(addr))
-                                .expect("Failed to send disconnect packet");
-                            break;
+                        match handle_player_send(
+                            packet,
+                            sender_uuid,
+                            &mut self.worlds,
+                            &mut self.inputs,
+                            &mut self.levels,
+                            self.seed,
+                            self.config.tick_rate,
+                        ) {
+                            Ok(Some(packet)) => self.clients.transport.send(addr, packet),
+                            Ok(None) => (),
+                            Err(reason) => self.drop_rejected(addr, reason),
                         }
                     }
+                    Packet::ChatPacket(ChatMessage { text, .. }) => {
+                        let Some(&sender) = self.ip_to_uuid.get(&addr) else {
+                            return;
+                        };
+                        let Some(text) = clean_chat(&text) else {
+                            return;
+                        };
+                        log_event(
+                            "chat",
+                            &[("id", sender.to_string()), ("text", text.clone())],
+                        );
+                        self.clients.send_to_all(Packet::ChatPacket(ChatMessage {
+                            sender: Some(sender),
+                            text,
+                        }));
+                    }
+                    Packet::WorldPacket(WorldPacket::LevelRequestPacket(LevelRequest {
+                        checksum,
+                    })) => {
+                        let Some(&uuid) = self.ip_to_uuid.get(&addr) else {
+                            return;
+                        };
+                        let chunks = self.levels.chunks(checksum);
+                        log_event(
+                            "level_request",
+                            &[
+                                ("addr", addr.to_string()),
+                                ("checksum", format!("{:016x}", checksum)),
+                                ("chunks", chunks.len().to_string()),
+                            ],
+                        );
+                        // replaces a download of a level the client doesn't need anymore
+                        self.downloads.insert(uuid, chunks.into());
+                    }
+                    _ => (),
+                }
+            }
+            // the client joins once it said hello
+            ServerPacket::AddPlayer(addr) => {
+                log_event("connect", &[("addr", addr.to_string())]);
+            }
+            ServerPacket::RemovePlayer(addr) => {
+                self.remove_client(addr, "connection lost", true);
+            }
+        }
+    }
+
+    fn handle_join(&mut self, addr: SocketAddr, hello: Hello, session: Option<u64>) {
+        if self.ip_to_uuid.contains_key(&addr) {
+            return; // already joined
+        }
+        // the old connection might not have timed out yet, its slot is taken over
+        let old = session.and_then(|token| {
+            let id = self.sessions.get(&token)?;
+            self.uuid_to_ip.get(id).copied()
+        });
+        let joined = self.player_count() - usize::from(old.is_some());
+        if let Err(reason) = check_hello(&hello, joined, self.config.max_players) {
+            log_event(
+                "reject",
+                &[("addr", addr.to_string()), ("reason", reason.to_string())],
+            );
+            self.clients.transport.send(
+                addr,
+                Packet::ConnectionPacket(ConnectionPacket::RejectPacket(Reject { reason })),
+            );
+            return;
+        }
+        // only once the new connection is accepted
+        if let Some(old) = old {
+            self.clients.transport.disconnect(old);
+            self.remove_client(old, "resumed from another connection", true);
+        }
+
+        // an unknown or expired token is the same as a hello
+        let resumed = session.and_then(|token| Some((token, self.suspended.remove(&token)?)));
+        let event = if resumed.is_some() { "resume" } else { "join" };
+        let (uuid, token) = match resumed {
+            Some((token, suspended)) => {
+                let mut player = suspended.player;
+                player.reset_velocity();
+                let (x, y) = (player.x, player.y);
+                // the level was loaded before, but its file might be gone by now
+                if let Err(err) =
+                    add_to_world(&mut self.worlds, player, &suspended.level, self.seed)
+                {
+                    log_event("level_error", &[("level", suspended.level), ("error", err)]);
+                    self.used_uuid.remove(&suspended.id);
+                    return;
                 }
-            });
-        }
-
-        // handle incoming packets
-        if let Ok(packet) = server_receiver.try_recv() {
-            match packet {
-                ServerPacket::ServerInternalPacket(packet) => {
-                    let (addr, packet) = (packet.address, packet.packet);
-                    if let Packet::PlayerPacket(packet) = packet {
-                        let sender_uuid = *ip_to_uuid.get(&addr).unwrap();
-                        let packet = handle_player_send(packet, sender_uuid, &mut players);
-                        send_to_clients(packet, &mut clients);
+                // adding puts players on the spawn
+                if let Some(player) = find_player_mut(&mut self.worlds, suspended.id) {
+                    player.set_position(x, y);
+                }
+                (suspended.id, token)
+            }
+            None => {
+                // add a new uuid for the client
+                let uuid = new_client_id(&self.used_uuid, &mut self.rng);
+                self.used_uuid.insert(uuid);
+                (uuid, new_session_token(&self.sessions, &self.suspended))
+            }
+        };
+        self.sessions.insert(token, uuid);
+        self.ip_to_uuid.insert(addr, uuid);
+        self.uuid_to_ip.insert(uuid, addr);
+        self.clients.addresses.insert(uuid, addr);
+        log_event(
+            event,
+            &[
+                ("id", uuid.to_string()),
+                ("addr", addr.to_string()),
+                (
+                    "players",
+                    format!("{}/{}", joined + 1, self.config.max_players),
+                ),
+            ],
+        );
+
+        self.clients.transport.send(
+            addr,
+            Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(ServerInfo {
+                motd: self.config.motd.clone(),
+                players: joined as u32 + 1,
+                max_players: self.config.max_players as u32,
+                seed: self.seed,
+            })),
+        );
+        // the others hear about the player once it's on their level
+        self.clients.transport.send(
+            addr,
+            Packet::ClientIDPacket(ClientID {
+                id: uuid,
+                session: Some(token),
+            }),
+        );
+        match event {
+            "resume" => self.announce(format!("{} is back", player_name(uuid))),
+            _ => self.announce(format!("{} joined", player_name(uuid))),
+        }
+        self.send_player_list();
+    }
+
+    // the client can't stay, it's told why and dropped like it said goodbye
+    fn drop_rejected(&mut self, addr: SocketAddr, reason: RejectReason) {
+        log_event(
+            "reject",
+            &[("addr", addr.to_string()), ("reason", reason.to_string())],
+        );
+        self.clients.transport.send(
+            addr,
+            Packet::ConnectionPacket(ConnectionPacket::RejectPacket(Reject { reason })),
+        );
+        self.clients.transport.disconnect(addr);
+        self.remove_client(addr, "rejected", false);
+    }
+
+    // watching a level instead of playing on it, enemies can't see spectators since they have no player
+    fn handle_spectate(&mut self, addr: SocketAddr, level: String) {
+        let Some(&uuid) = self.ip_to_uuid.get(&addr) else {
+            return;
+        };
+        // a player would be left behind
+        if self
+            .worlds
+            .values()
+            .any(|world| world.player(uuid).is_some())
+        {
+            return;
+        }
+        let world = match load_world(&mut self.worlds, &level, self.seed) {
+            Ok(world) => world,
+            Err(_) => return self.drop_rejected(addr, RejectReason::UnknownLevel { level }),
+        };
+        let packet = level_packet(world, uuid, &mut self.levels);
+        self.spectators.insert(uuid, level.clone());
+        self.clients.transport.send(addr, packet);
+        log_event("spectate", &[("id", uuid.to_string()), ("level", level)]);
+        // the level it watched before might be empty now
+        self.unload_empty_worlds();
+        self.send_player_list();
+    }
+
+    // who is on the server and who is only watching
+    fn send_player_list(&mut self) {
+        let mut players: Vec<PlayerListEntry> = self
+            .clients
+            .addresses
+            .keys()
+            .map(|&player_id| PlayerListEntry {
+                player_id,
+                spectator: self.spectators.contains_key(&player_id),
+            })
+            .collect();
+        players.sort_by_key(|entry| entry.player_id);
+        self.clients
+            .send_to_all(Packet::PlayerPacket(PlayerPacket::PlayerListPacket(
+                PlayerList { players },
+            )));
+    }
+
+    // forget everything about a client and tell the others it's gone,
+    // with suspend its player is kept for a while in case it comes back
+    fn remove_client(&mut self, addr: SocketAddr, reason: &str, suspend: bool) {
+        let Some(uuid) = self.ip_to_uuid.remove(&addr) else {
+            return; // never joined or already removed
+        };
+        self.uuid_to_ip.remove(&uuid);
+        self.clients.addresses.remove(&uuid);
+        self.inputs.remove(&uuid);
+        self.downloads.remove(&uuid);
+        self.spectators.remove(&uuid);
+        let token = self
+            .sessions
+            .iter()
+            .find_map(|(token, id)| (*id == uuid).then_some(*token));
+        if let Some(token) = token {
+            self.sessions.remove(&token);
+        }
+        let mut removed = None;
+        for world in self.worlds.values_mut() {
+            if let Some(player) = world.remove_player(uuid) {
+                removed = Some((world.level_path.clone(), player));
+            }
+        }
+        self.unload_empty_worlds();
+
+        // the id stays taken while the player can still come back
+        let suspended = match (token, removed) {
+            (Some(token), Some((level, player))) if suspend && self.config.grace_period > 0 => {
+                self.suspended.insert(
+                    token,
+                    Suspended {
+                        id: uuid,
+                        level,
+                        player,
+                        since: Instant::now(),
+                    },
+                );
+                true
+            }
+            _ => {
+                self.used_uuid.remove(&uuid);
+                false
+            }
+        };
+
+        // only the clients that could see the player know about it
+        self.interest.remove(&uuid);
+        let viewers: Vec<u64> = self
+            .interest
+            .iter_mut()
+            .filter_map(|(viewer, known)| known.remove(&uuid).then_some(*viewer))
+            .collect();
+        self.clients.send_to_players(
+            Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect {
+                id: uuid,
+            })),
+            &viewers,
+        );
+        self.announce(format!("{} left ({})", player_name(uuid), reason));
+        self.send_player_list();
+        log_event(
+            "leave",
+            &[
+                ("id", uuid.to_string()),
+                ("addr", addr.to_string()),
+                ("reason", reason.to_string()),
+                ("resumable", suspended.to_string()),
+                (
+                    "players",
+                    format!("{}/{}", self.player_count(), self.config.max_players),
+                ),
+            ],
+        );
+    }
+
+    // drops players that didn't come back within the grace period
+    pub fn expire_sessions(&mut self, now: Instant) {
+        let grace_period = Duration::from_secs(self.config.grace_period);
+        let expired: Vec<u64> = self
+            .suspended
+            .iter()
+            .filter(|(_, suspended)| now.saturating_duration_since(suspended.since) >= grace_period)
+            .map(|(token, _)| *token)
+            .collect();
+        for token in expired {
+            if let Some(suspended) = self.suspended.remove(&token) {
+                self.used_uuid.remove(&suspended.id);
+                log_event("expire", &[("id", suspended.id.to_string())]);
+            }
+        }
+    }
+
+    // advance every loaded level by one step
+    pub fn tick(&mut self) {
+        // one queued input per player and step, players without one keep moving like before
+        // until their client has been quiet for too long
+        let step_inputs: HashMap<u64, PlayerInput> = self
+            .inputs
+            .iter_mut()
+            .filter_map(|(id, buffer)| Some((*id, buffer.next()?)))
+            .collect();
+
+        let mut transfers = Vec::new();
+        let mut deaths = Vec::new();
+        for world in self.worlds.values_mut() {
+            for event in world.step(TIME_STEP, &step_inputs) {
+                match event {
+                    WorldEvent::ExitReached { player_id, exit } => {
+                        transfers.push((player_id, world.level_path.clone(), exit.next_level));
                     }
+                    WorldEvent::PlayerDied(player_id) => deaths.push(player_id),
                 }
-                ServerPacket::AddPlayer(addr) => {
-                    let uuid = *ip_to_uuid.get(&addr).unwrap();
-                    players.insert(uuid, Player::new(uuid));
+            }
+        }
+
+        for (player_id, from, to) in transfers {
+            // an exit to a level that doesn't load sends the player back to the start
+            let to = match load_world(&mut self.worlds, &to, self.seed) {
+                Ok(_) => to,
+                Err(_) => from.clone(),
+            };
+            let Some(player) = self
+                .worlds
+                .get_mut(&from)
+                .and_then(|w| w.remove_player(player_id))
+            else {
+                continue;
+            };
+            self.worlds.get_mut(&to).unwrap().add_player(player);
+            // the players around it notice on the next update
+            let packet = level_packet(&self.worlds[&to], player_id, &mut self.levels);
+            self.clients.send_to_players(packet, &[player_id]);
+        }
+
+        for player_id in deaths {
+            self.announce(format!("{} died", player_name(player_id)));
+        }
+
+        self.unload_empty_worlds();
+        self.send_downloads();
+    }
+
+    fn send_downloads(&mut self) {
+        for (uuid, chunks) in self.downloads.iter_mut() {
+            let Some(&addr) = self.uuid_to_ip.get(uuid) else {
+                continue;
+            };
+            for chunk in chunks.drain(..CHUNKS_PER_STEP.min(chunks.len())) {
+                self.clients.transport.send(
+                    addr,
+                    Packet::WorldPacket(WorldPacket::LevelChunkPacket(chunk)),
+                );
+            }
+        }
+        self.downloads.retain(|_, chunks| !chunks.is_empty());
+    }
+
+    // a chat message from the server itself
+    fn announce(&mut self, text: String) {
+        self.clients
+            .send_to_all(Packet::ChatPacket(ChatMessage { sender: None, text }));
+    }
+
+    // levels without players or spectators don't need to run
+    fn unload_empty_worlds(&mut self) {
+        let spectators = &self.spectators;
+        self.worlds.retain(|level, world| {
+            !world.players.is_empty() || spectators.values().any(|watched| watched == level)
+        });
+        let worlds = &self.worlds;
+        self.levels.retain(|level| worlds.contains_key(level));
+    }
+
+    // tell every player what happened around it on its level,
+    // players coming into view are welcomed and the ones leaving it are dropped,
+    // spectators have no position so they see the whole level
+    pub fn send_updates(&mut self) {
+        let radius = self.config.view_radius;
+        for world in self.worlds.values() {
+            let snapshot = world.snapshot();
+            let spectators = self
+                .spectators
+                .iter()
+                .filter(|(_, level)| **level == world.level_path)
+                .map(|(id, _)| (*id, None));
+            let viewers: Vec<(u64, Option<&Player>)> = world
+                .players
+                .iter()
+                .map(|player| (player.id, Some(player)))
+                .chain(spectators)
+                .collect();
+            for (viewer_id, viewer) in viewers {
+                let Some(&addr) = self.clients.addresses.get(&viewer_id) else {
+                    continue;
+                };
+                let visible =
+                    |x: f64, y: f64| viewer.is_none_or(|viewer| in_view(viewer, x, y, radius));
+                let seen: HashSet<u64> = world
+                    .players
+                    .iter()
+                    .filter(|other| other.id != viewer_id && visible(other.x, other.y))
+                    .map(|other| other.id)
+                    .collect();
+                let known = self.interest.entry(viewer_id).or_default();
+                for &id in known.difference(&seen) {
+                    self.clients.transport.send(
+                        addr,
+                        Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(
+                            PlayerDisconnect { id },
+                        )),
+                    );
                 }
-                ServerPacket::RemovePlayer(addr) => {
-                    let uuid = *ip_to_uuid.get(&addr).unwrap();
-                    //players.remove(&uuid);
-                    let disconnect_packet = Packet::PlayerPacket(
-                        PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect { id: uuid }),
+                for other in &world.players {
+                    if !seen.contains(&other.id) || known.contains(&other.id) {
+                        continue;
+                    }
+                    self.clients.transport.send(
+                        addr,
+                        Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(PlayerWelcome {
+                            player_id: other.id,
+                            x: other.x,
+                            y: other.y,
+                        })),
                     );
-                    send_to_clients(disconnect_packet, &mut clients);
+                    let level = level_packet(world, other.id, &mut self.levels);
+                    self.clients.transport.send(addr, level);
                 }
+                *known = seen;
+
+                for player in &world.players {
+                    if player.id != viewer_id && !known.contains(&player.id) {
+                        continue;
+                    }
+                    let position =
+                        Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(PlayerPosition {
+                            player_id: player.id,
+                            x: player.x,
+                            y: player.y,
+                            time: world.time,
+                            sequence: self.inputs.get(&player.id).map_or(0, |b| b.last_sequence()),
+                        }));
+                    self.clients.transport.send(addr, position);
+                    let animation = Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(
+                        PlayerAnimation {
+                            player_id: player.id,
+                            state: player.animation_data.current_animation.clone(),
+                            frame: player.animation_data.frame(),
+                            facing: player.animation_data.facing.clone(),
+                        },
+                    ));
+                    self.clients.transport.send(addr, animation);
+                }
+
+                let mut state = snapshot.clone();
+                state
+                    .players
+                    .retain(|p| p.player_id == viewer_id || known.contains(&p.player_id));
+                state.enemies.retain(|e| visible(e.x, e.y));
+                state.projectiles.retain(|p| visible(p.x, p.y));
+                self.clients.transport.send(
+                    addr,
+                    Packet::WorldPacket(WorldPacket::WorldStatePacket(state)),
+                );
             }
         }
     }
+
+    // says goodbye to everyone, so clients don't wait for a timeout
+    pub fn shutdown(&mut self) {
+        let addresses: Vec<SocketAddr> = self.clients.addresses.values().copied().collect();
+        for addr in addresses {
+            self.clients.transport.send(
+                addr,
+                Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(Goodbye {
+                    reason: GoodbyeReason::ServerShutdown,
+                })),
+            );
+            self.clients.transport.disconnect(addr);
+            self.remove_client(addr, "server shutdown", false);
+        }
+        // the goodbyes should get there before we're gone
+        let deadline = Instant::now() + SHUTDOWN_FLUSH;
+        while self.clients.transport.is_closing() && Instant::now() < deadline {
+            self.clients.transport.wait(deadline - Instant::now());
+            self.clients.transport.poll();
+        }
+        log_event("stop", &[]);
+    }
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+// makes the server loop stop after the current tick, safe to call from a signal handler
+pub fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+pub fn server(config: ServerConfig) {
+    let address = config.address();
+    let transport: Box<dyn ServerTransport> = match config.transport {
+        TransportKind::Tcp => Box::new(TcpServerTransport::bind(&address).expect("Failed to bind")),
+        TransportKind::Udp => Box::new(UdpServerTransport::bind(&address).expect("Failed to bind")),
+    };
+    log_event(
+        "start",
+        &[
+            (
+                "address",
+                transport
+                    .local_addr()
+                    .map_or(address, |addr| addr.to_string()),
+            ),
+            (
+                "transport",
+                format!("{:?}", config.transport).to_lowercase(),
+            ),
+            ("tick_rate", config.tick_rate.to_string()),
+            ("max_players", config.max_players.to_string()),
+        ],
+    );
+
+    // another server on this machine might have the port already
+    let discovery = match DiscoveryResponder::bind(&format!("{}:{}", config.bind, DISCOVERY_PORT)) {
+        Ok(discovery) => {
+            let address = discovery.local_addr().map(|addr| addr.to_string());
+            log_event("discovery", &[("address", address.unwrap_or_default())]);
+            Some(discovery)
+        }
+        Err(err) => {
+            log_event("discovery_off", &[("reason", err.to_string())]);
+            None
+        }
+    };
+
+    let tick_period = Duration::from_secs_f64(1.0 / config.tick_rate as f64);
+    let mut server = Server::new(config, transport);
+    // with this seed the server plays out the same again
+    log_event("seed", &[("seed", server.seed().to_string())]);
+    let mut next_tick = Instant::now();
+    let mut last_tick = Instant::now();
+    let mut accumulator = 0.0;
+
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        // handle incoming packets as soon as they arrive
+        server.poll();
+        server.expire_sessions(Instant::now());
+        if let Some(discovery) = &discovery {
+            discovery.answer(|| server.announcement());
+        }
+
+        // sleep until the next tick or until someone sends something, instead of spinning
+        let now = Instant::now();
+        if next_tick > now {
+            server.wait(next_tick - now);
+            continue;
+        }
+
+        // simulation, always in fixed steps so clients can predict it
+        accumulator += (now - last_tick).as_secs_f64();
+        last_tick = now;
+        let mut stepped = false;
+        while accumulator >= TIME_STEP {
+            accumulator -= TIME_STEP;
+            server.tick();
+            stepped = true;
+        }
+        if stepped {
+            server.send_updates();
+        }
+
+        next_tick += tick_period;
+        let now = Instant::now();
+        if next_tick < now {
+            // we fell behind, don't try to catch up with a burst of ticks
+            next_tick = now;
+        }
+    }
+    server.shutdown();
 }
@@ -0,0 +1,93 @@
+use crate::networking::discovery::MAX_NAME_LENGTH;
+use crate::networking::shared::{DEFAULT_BIND, DEFAULT_PORT};
+use crate::networking::transport::TransportKind;
+
+pub const USAGE: &str = "usage: server [--bind ADDRESS] [--port PORT] [--max-players N] \
+[--tick-rate HZ] [--name NAME] [--motd MESSAGE] [--grace-period SECONDS] [--view-radius PIXELS] \
+[--seed N] [--udp]";
+
+// how a dedicated server runs, filled from the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    pub max_players: usize,
+    pub tick_rate: u32, // how often per second the server wakes up and sends updates
+    pub name: String,   // shown in the server browser
+    pub motd: String,
+    pub grace_period: u64, // seconds a player who lost the connection is kept for, 0 turns resuming off
+    pub view_radius: Option<f64>, // how far around them players get updates, the whole level without
+    pub seed: Option<u64>,        // for enemies and player ids, a random one without
+    pub transport: TransportKind,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: DEFAULT_BIND.to_string(),
+            port: DEFAULT_PORT,
+            max_players: 8,
+            tick_rate: 60,
+            name: "sea2d server".to_string(),
+            motd: "Welcome to sea2d!".to_string(),
+            grace_period: 30,
+            view_radius: None,
+            seed: None,
+            transport: TransportKind::Tcp,
+        }
+    }
+}
+
+impl ServerConfig {
+    // args are everything after "server"
+    pub fn from_args(args: &[String]) -> Result<ServerConfig, String> {
+        let mut config = ServerConfig::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--bind" => config.bind = value()?.clone(),
+                "--port" => config.port = parse(arg, value()?)?,
+                "--max-players" => config.max_players = parse(arg, value()?)?,
+                "--tick-rate" => config.tick_rate = parse(arg, value()?)?,
+                "--name" => config.name = value()?.clone(),
+                "--motd" => config.motd = value()?.clone(),
+                "--grace-period" => config.grace_period = parse(arg, value()?)?,
+                "--view-radius" => config.view_radius = Some(parse(arg, value()?)?),
+                "--seed" => config.seed = Some(parse(arg, value()?)?),
+                "--udp" => config.transport = TransportKind::Udp,
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if config.max_players == 0 {
+            return Err("--max-players has to be at least 1".to_string());
+        }
+        if config.tick_rate == 0 {
+            return Err("--tick-rate has to be at least 1".to_string());
+        }
+        // the name has to fit into the answers to the server browser
+        if config.name.len() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "--name can be at most {} bytes long",
+                MAX_NAME_LENGTH
+            ));
+        }
+        if config
+            .view_radius
+            .is_some_and(|radius| radius.is_nan() || radius <= 0.0)
+        {
+            return Err("--view-radius has to be positive".to_string());
+        }
+        Ok(config)
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
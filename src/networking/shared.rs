@@ -1,5 +1,14 @@
-pub const LOCAL: &str = "0.0.0.0:6000";
+pub const DEFAULT_BIND: &str = "0.0.0.0";
+pub const DEFAULT_PORT: u16 = 6000;
+pub const DISCOVERY_PORT: u16 = 6001; // servers on the local network answer queries here
 pub const CLIENT_LOCAL: &str = "127.0.0.1:6000"; // 0.0.0.0 doesn't work on windows lol
 pub const MAX_PACKET_SIZE: usize = 65536;
+pub const MAX_FRAME_SIZE: usize = u16::MAX as usize; // the most a tcp frame's size prefix can say
+pub const MAX_CHAT_LENGTH: usize = 200; // characters
 pub const SCREEN_WIDTH: u32 = 800;
 pub const SCREEN_HEIGHT: u32 = 600;
+
+// players don't have names yet, this is how they show up in chat
+pub fn player_name(id: u64) -> String {
+    format!("Player {:04}", id % 10000)
+}
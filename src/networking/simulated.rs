@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mio::Waker;
+use rand::Rng;
+
+use crate::networking::packet::Packet;
+use crate::networking::transport::{Channel, ClientTransport, TransportError};
+
+// a bad connection to test with on localhost, set with --latency, --jitter and --loss
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkConditions {
+    pub latency: Duration, // one way, added in both directions
+    pub jitter: Duration,  // up to this much more, random for every packet
+    pub loss: f64,         // chance an unreliable packet is dropped, 0 to 1
+}
+
+impl NetworkConditions {
+    // nothing if none of the flags are there
+    pub fn from_args(args: &[String]) -> Result<Option<NetworkConditions>, String> {
+        let mut conditions = NetworkConditions {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+        };
+        let mut found = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite() && *value >= 0.0)
+                    .ok_or_else(|| format!("invalid value for {}: {}", arg, value))
+            };
+            match arg.as_str() {
+                "--latency" => conditions.latency = Duration::from_secs_f64(value()? / 1000.0),
+                "--jitter" => conditions.jitter = Duration::from_secs_f64(value()? / 1000.0),
+                "--loss" => conditions.loss = value()? / 100.0,
+                _ => continue,
+            }
+            found = true;
+        }
+        if conditions.loss > 1.0 {
+            return Err("--loss is a percentage, at most 100".to_string());
+        }
+        Ok(found.then_some(conditions))
+    }
+
+    // when a packet sent now arrives, nothing if it gets lost
+    fn delay(&self, packet: &Packet, now: Instant) -> Option<Instant> {
+        let mut rng = rand::rng();
+        let reliable = packet.channel() == Channel::Reliable;
+        if !reliable && rng.random_bool(self.loss) {
+            return None;
+        }
+        let jitter = self.jitter.mul_f64(rng.random::<f64>());
+        Some(now + self.latency + jitter)
+    }
+}
+
+// packets waiting to be let through, in one direction
+#[derive(Default)]
+struct DelayQueue {
+    packets: Vec<(Instant, Packet)>,
+    last_reliable: Option<Instant>,
+}
+
+impl DelayQueue {
+    fn push(&mut self, conditions: &NetworkConditions, packet: Packet, now: Instant) {
+        let Some(mut due) = conditions.delay(&packet, now) else {
+            return;
+        };
+        // reliable packets can be late, but never overtake each other
+        if packet.channel() == Channel::Reliable {
+            due = due.max(self.last_reliable.unwrap_or(due));
+            self.last_reliable = Some(due);
+        }
+        self.packets.push((due, packet));
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.packets.iter().map(|(due, _)| *due).min()
+    }
+
+    fn due(&mut self, now: Instant) -> Vec<Packet> {
+        self.packets.sort_by_key(|(due, _)| *due);
+        let count = self.packets.partition_point(|(due, _)| *due <= now);
+        self.packets
+            .drain(..count)
+            .map(|(_, packet)| packet)
+            .collect()
+    }
+}
+
+// wraps a real transport and makes it behave like a worse network,
+// reliable packets are only delayed since the transport would resend them anyway
+pub struct SimulatedClientTransport {
+    inner: Box<dyn ClientTransport>,
+    conditions: NetworkConditions,
+    outgoing: DelayQueue,
+    incoming: DelayQueue,
+}
+
+impl SimulatedClientTransport {
+    pub fn new(
+        inner: Box<dyn ClientTransport>,
+        conditions: NetworkConditions,
+    ) -> SimulatedClientTransport {
+        SimulatedClientTransport {
+            inner,
+            conditions,
+            outgoing: DelayQueue::default(),
+            incoming: DelayQueue::default(),
+        }
+    }
+
+    fn flush(&mut self, now: Instant) -> Result<(), TransportError> {
+        for packet in self.outgoing.due(now) {
+            self.inner.send(packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl ClientTransport for SimulatedClientTransport {
+    fn send(&mut self, packet: Packet) -> Result<(), TransportError> {
+        let now = Instant::now();
+        self.outgoing.push(&self.conditions, packet, now);
+        self.flush(now)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Packet>, TransportError> {
+        let now = Instant::now();
+        self.flush(now)?;
+        for packet in self.inner.poll()? {
+            self.incoming.push(&self.conditions, packet, now);
+        }
+        Ok(self.incoming.due(now))
+    }
+
+    // held back packets have to be let through on time even if nothing arrives
+    fn wait(&mut self, timeout: Duration) {
+        let now = Instant::now();
+        let timeout = [self.outgoing.next_due(), self.incoming.next_due()]
+            .into_iter()
+            .flatten()
+            .map(|due| due.saturating_duration_since(now))
+            .fold(timeout, Duration::min);
+        self.inner.wait(timeout);
+    }
+
+    fn waker(&self) -> Option<Arc<Waker>> {
+        self.inner.waker()
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::networking::connection_packets::{ConnectionPacket, Ping, Pong};
+use crate::networking::packet::{Packet, PacketInternal};
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+const PINGS_KEPT: usize = 8; // answers to older pings don't count anymore
+
+// what the network overlay shows, kept up to date by the network thread
+#[derive(Debug, Clone, Default)]
+pub struct NetworkStats {
+    pub rtt: Option<Duration>,
+    pub sent: Traffic,
+    pub received: Traffic,
+}
+
+impl NetworkStats {
+    // also makes the rates drop to zero when nothing is going on
+    pub fn update(&mut self, now: Instant) {
+        self.sent.update(now);
+        self.received.update(now);
+    }
+}
+
+// packets and bytes in one direction, averaged over about a second
+#[derive(Debug, Clone, Default)]
+pub struct Traffic {
+    pub packets_per_second: u32,
+    pub bytes_per_second: u32,
+    packets: u32,
+    bytes: u32,
+    since: Option<Instant>,
+}
+
+impl Traffic {
+    pub fn count(&mut self, packet: &Packet, now: Instant) {
+        self.update(now);
+        self.packets += 1;
+        self.bytes += packet_size(packet) as u32;
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        let since = *self.since.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let seconds = elapsed.as_secs_f64();
+        self.packets_per_second = (self.packets as f64 / seconds).round() as u32;
+        self.bytes_per_second = (self.bytes as f64 / seconds).round() as u32;
+        self.packets = 0;
+        self.bytes = 0;
+        self.since = Some(now);
+    }
+}
+
+// bytes of the packet itself, headers the transport adds aren't counted
+pub fn packet_size(packet: &Packet) -> usize {
+    bincode::serialized_size(&PacketInternal::encode(packet)).unwrap_or(0) as usize
+}
+
+// sends a ping every PING_INTERVAL and matches the pongs to them
+#[derive(Default)]
+pub struct Pinger {
+    sequence: u32,
+    sent: VecDeque<(u32, Instant)>,
+}
+
+impl Pinger {
+    // a ping if it's time for the next one
+    pub fn ping(&mut self, now: Instant) -> Option<Packet> {
+        if let Some((_, last)) = self.sent.back()
+            && now.saturating_duration_since(*last) < PING_INTERVAL
+        {
+            return None;
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sent.push_back((self.sequence, now));
+        if self.sent.len() > PINGS_KEPT {
+            self.sent.pop_front();
+        }
+        Some(Packet::ConnectionPacket(ConnectionPacket::PingPacket(
+            Ping {
+                sequence: self.sequence,
+            },
+        )))
+    }
+
+    // how long the network thread can sleep before the next ping is due
+    pub fn until_next(&self, now: Instant) -> Duration {
+        match self.sent.back() {
+            Some((_, last)) => PING_INTERVAL.saturating_sub(now.saturating_duration_since(*last)),
+            None => Duration::ZERO,
+        }
+    }
+
+    // the round trip time, nothing for unknown or repeated pongs
+    pub fn pong(&mut self, pong: &Pong, now: Instant) -> Option<Duration> {
+        let index = self
+            .sent
+            .iter()
+            .position(|(sequence, _)| *sequence == pong.sequence)?;
+        let (_, sent) = self.sent.remove(index)?;
+        Some(now.saturating_duration_since(sent))
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Interest, Token, Waker};
+
+use crate::networking::helpers::{FrameDecoder, deserialize_to_packet, frame_packet};
+use crate::networking::packet::{Packet, ServerInternal, ServerPacket};
+use crate::networking::poller::Poller;
+use crate::networking::shared::MAX_FRAME_SIZE;
+use crate::networking::transport::{ClientTransport, ServerTransport, TransportError};
+
+const LISTENER: Token = Token(0);
+const STREAM: Token = Token(1); // the client only has the one
+// clients only send inputs, chat and such, anything bigger is someone messing with us
+const MAX_CLIENT_FRAME_SIZE: usize = 4096;
+// a peer that stopped reading is dropped once this much is waiting for it
+const MAX_OUTGOING: usize = 512 * 1024;
+
+// a stream with the frames that didn't fully make it through yet, in both directions
+struct TcpConnection {
+    stream: TcpStream,
+    token: Token,
+    decoder: FrameDecoder,
+    outgoing: Vec<u8>,
+}
+
+impl TcpConnection {
+    fn new(
+        mut stream: TcpStream,
+        poller: &Poller,
+        token: Token,
+        frame_limit: usize,
+    ) -> std::io::Result<TcpConnection> {
+        poller
+            .registry()
+            .register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(TcpConnection {
+            stream,
+            token,
+            decoder: FrameDecoder::new(frame_limit),
+            outgoing: Vec::new(),
+        })
+    }
+
+    // queues the packet and writes as much as the socket takes
+    fn send(&mut self, packet: Packet) -> std::io::Result<()> {
+        match frame_packet(packet) {
+            Ok(frame) => self.outgoing.extend(frame),
+            // only this packet is lost, the connection is fine
+            Err(err) => println!("Not sending packet: {}", err),
+        }
+        self.flush()?;
+        if self.outgoing.len() > MAX_OUTGOING {
+            return Err(std::io::Error::other(format!(
+                "{} bytes waiting, the other side isn't reading",
+                self.outgoing.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                // the rest goes once the socket is writable again
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    // reads until the socket would block, packets that arrived before an error are kept
+    fn receive(&mut self, packets: &mut Vec<Packet>) -> std::io::Result<()> {
+        let mut buf = [0; 4096];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Err(ErrorKind::UnexpectedEof.into()),
+                Ok(size) => self.decoder.push(&buf[..size]),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => break Err(err),
+            }
+        };
+        loop {
+            match self.decoder.next_frame() {
+                Ok(Some(frame)) => match deserialize_to_packet(frame) {
+                    Ok(packet) => packets.push(packet),
+                    // the frame was fine, so the next one can still be read
+                    Err(err) => println!("Dropping packet: {}", err),
+                },
+                Ok(None) => return result,
+                Err(err) => {
+                    return Err(std::io::Error::new(ErrorKind::InvalidData, err.to_string()));
+                }
+            }
+        }
+    }
+}
+
+// everything over one stream, every packet is reliable
+pub struct TcpClientTransport {
+    connection: TcpConnection,
+    poller: Poller,
+    lost: bool, // reported after the packets that arrived with it
+}
+
+impl TcpClientTransport {
+    pub fn connect(address: &str) -> std::io::Result<TcpClientTransport> {
+        let stream = std::net::TcpStream::connect(address)?;
+        stream.set_nonblocking(true)?;
+        let poller = Poller::new()?;
+        let connection =
+            TcpConnection::new(TcpStream::from_std(stream), &poller, STREAM, MAX_FRAME_SIZE)?;
+        Ok(TcpClientTransport {
+            connection,
+            poller,
+            lost: false,
+        })
+    }
+}
+
+impl ClientTransport for TcpClientTransport {
+    fn send(&mut self, packet: Packet) -> Result<(), TransportError> {
+        if self.lost {
+            return Err(TransportError::ConnectionLost);
+        }
+        self.connection
+            .send(packet)
+            .map_err(|_| TransportError::ConnectionLost)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Packet>, TransportError> {
+        if self.lost {
+            return Err(TransportError::ConnectionLost);
+        }
+        let mut packets = Vec::new();
+        let received = self.connection.receive(&mut packets);
+        if received.and_then(|()| self.connection.flush()).is_err() {
+            self.lost = true;
+            if packets.is_empty() {
+                return Err(TransportError::ConnectionLost);
+            }
+        }
+        Ok(packets)
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        self.poller.wait(timeout);
+    }
+
+    fn waker(&self) -> Option<Arc<Waker>> {
+        Some(self.poller.waker())
+    }
+}
+
+// every client on one thread, the sockets are only read when the poller says so
+pub struct TcpServerTransport {
+    listener: TcpListener,
+    poller: Poller,
+    connections: HashMap<SocketAddr, TcpConnection>,
+    addresses: HashMap<Token, SocketAddr>,
+    next_token: usize,
+    ready: HashSet<Token>,   // found while waiting, handled on the next poll
+    lost: Vec<ServerPacket>, // clients we failed to send to
+}
+
+impl TcpServerTransport {
+    pub fn bind(address: &str) -> std::io::Result<TcpServerTransport> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let mut listener = TcpListener::from_std(listener);
+        let poller = Poller::new()?;
+        poller
+            .registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+        Ok(TcpServerTransport {
+            listener,
+            poller,
+            connections: HashMap::new(),
+            addresses: HashMap::new(),
+            next_token: LISTENER.0 + 1,
+            ready: HashSet::new(),
+            lost: Vec::new(),
+        })
+    }
+
+    fn accept(&mut self, events: &mut Vec<ServerPacket>) {
+        loop {
+            let (stream, addr) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    // out of file descriptors and such, the client can try again later
+                    println!("Failed to accept a client: {}", err);
+                    return;
+                }
+            };
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            let connection =
+                match TcpConnection::new(stream, &self.poller, token, MAX_CLIENT_FRAME_SIZE) {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        println!("Failed to accept {}: {}", addr, err);
+                        continue;
+                    }
+                };
+            self.connections.insert(addr, connection);
+            self.addresses.insert(token, addr);
+            events.push(ServerPacket::AddPlayer(addr));
+            // it might have sent something already
+            self.service(addr, events);
+        }
+    }
+
+    // reads what arrived and writes what's still waiting
+    fn service(&mut self, addr: SocketAddr, events: &mut Vec<ServerPacket>) {
+        let Some(connection) = self.connections.get_mut(&addr) else {
+            return;
+        };
+        let mut packets = Vec::new();
+        let received = connection.receive(&mut packets);
+        let result = received.and_then(|()| connection.flush());
+        events.extend(packets.into_iter().map(|packet| {
+            // handling in the main loop
+            ServerPacket::ServerInternalPacket(ServerInternal {
+                address: addr,
+                packet,
+            })
+        }));
+        if let Err(err) = result {
+            println!("Closing connection with {}: {}", addr, err);
+            self.remove(addr);
+            // send packet that signals client disconnect
+            events.push(ServerPacket::RemovePlayer(addr));
+        }
+    }
+
+    fn remove(&mut self, addr: SocketAddr) -> Option<TcpConnection> {
+        let mut connection = self.connections.remove(&addr)?;
+        self.addresses.remove(&connection.token);
+        let _ = self.poller.registry().deregister(&mut connection.stream);
+        Some(connection)
+    }
+}
+
+impl ServerTransport for TcpServerTransport {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn poll(&mut self) -> Vec<ServerPacket> {
+        let mut events = Vec::new();
+        self.ready.extend(self.poller.wait(Duration::ZERO));
+        for token in std::mem::take(&mut self.ready) {
+            if token == LISTENER {
+                self.accept(&mut events);
+            } else if let Some(&addr) = self.addresses.get(&token) {
+                self.service(addr, &mut events);
+            }
+        }
+        events.append(&mut self.lost);
+        events
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        let ready = self.poller.wait(timeout);
+        self.ready.extend(ready);
+    }
+
+    fn send(&mut self, address: SocketAddr, packet: Packet) {
+        if let Some(connection) = self.connections.get_mut(&address)
+            && let Err(err) = connection.send(packet)
+        {
+            // reported on the next poll, the caller might be iterating over its clients
+            println!("Closing connection with {}: {}", address, err);
+            self.remove(address);
+            self.lost.push(ServerPacket::RemovePlayer(address));
+        }
+    }
+
+    fn disconnect(&mut self, address: SocketAddr) {
+        if let Some(mut connection) = self.remove(address) {
+            // a goodbye might still be waiting
+            let _ = connection.flush();
+            let _ = connection.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use mio::Waker;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::networking::connection_packets::ConnectionPacket;
+use crate::networking::packet::{Packet, ServerPacket};
+use crate::networking::player_packets::PlayerPacket;
+use crate::networking::world_packets::WorldPacket;
+
+// which socket type carries the game traffic, picked with --udp on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TransportKind {
+    Tcp,
+    Udp,
+}
+
+impl TransportKind {
+    pub fn from_args(args: &[String]) -> TransportKind {
+        if args.iter().any(|arg| arg == "--udp") {
+            TransportKind::Udp
+        } else {
+            TransportKind::Tcp
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportError {
+    ConnectionLost,
+    TimedOut, // no answer from the other side for too long
+}
+
+// how a packet has to be delivered, only matters for transports that can lose packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    // every packet arrives exactly once, in the order it was sent
+    Reliable,
+    // packets can get lost, older ones arriving after newer ones on the same stream are dropped
+    Unreliable(u8),
+}
+
+impl Packet {
+    pub fn channel(&self) -> Channel {
+        match self {
+            Packet::ClientIDPacket(_) | Packet::ChatPacket(_) => Channel::Reliable,
+            Packet::ConnectionPacket(packet) => match packet {
+                // a resent ping would only measure the resending
+                ConnectionPacket::PingPacket(_) | ConnectionPacket::PongPacket(_) => {
+                    Channel::Unreliable(4)
+                }
+                ConnectionPacket::HelloPacket(_)
+                | ConnectionPacket::RejectPacket(_)
+                | ConnectionPacket::ServerInfoPacket(_)
+                | ConnectionPacket::GoodbyePacket(_)
+                | ConnectionPacket::ResumePacket(_) => Channel::Reliable,
+            },
+            Packet::PlayerPacket(packet) => match packet {
+                PlayerPacket::PlayerPositionPacket(_) => Channel::Unreliable(0),
+                PlayerPacket::PlayerAnimationPacket(_) => Channel::Unreliable(1),
+                // inputs come with the unacknowledged ones before them, a lost one is sent again
+                PlayerPacket::PlayerInputPacket(_) => Channel::Unreliable(2),
+                PlayerPacket::PlayerWelcomePacket(_)
+                | PlayerPacket::PlayerDisconnectPacket(_)
+                | PlayerPacket::PlayerLevelPacket(_)
+                | PlayerPacket::PlayerRespawnPacket(_)
+                | PlayerPacket::PlayerSpectatePacket(_)
+                | PlayerPacket::PlayerListPacket(_) => Channel::Reliable,
+            },
+            Packet::WorldPacket(packet) => match packet {
+                WorldPacket::WorldStatePacket(_) => Channel::Unreliable(3),
+                WorldPacket::LevelRequestPacket(_) | WorldPacket::LevelChunkPacket(_) => {
+                    Channel::Reliable
+                }
+            },
+        }
+    }
+}
+
+// the client's end of a connection
+pub trait ClientTransport: Send {
+    fn send(&mut self, packet: Packet) -> Result<(), TransportError>;
+    // everything that arrived since the last call, also keeps the connection alive
+    fn poll(&mut self) -> Result<Vec<Packet>, TransportError>;
+    // sleeps until something might have arrived, the timeout passed or the waker was used,
+    // transports without sockets can only nap
+    fn wait(&mut self, timeout: Duration) {
+        thread::sleep(timeout.min(Duration::from_millis(1)));
+    }
+    // for the thread that hands the transport packets to send
+    fn waker(&self) -> Option<Arc<Waker>> {
+        None
+    }
+}
+
+// the server's end, talks to every client at once
+pub trait ServerTransport {
+    fn local_addr(&self) -> std::io::Result<SocketAddr>;
+    // new clients, their packets and clients that went away, in the order it happened
+    fn poll(&mut self) -> Vec<ServerPacket>;
+    // sleeps until a client might have sent something or the timeout passed
+    fn wait(&mut self, timeout: Duration) {
+        thread::sleep(timeout);
+    }
+    fn send(&mut self, address: SocketAddr, packet: Packet);
+    // closes the connection from our side, no RemovePlayer is reported for it,
+    // what was sent before still gets through
+    fn disconnect(&mut self, address: SocketAddr);
+    // disconnected clients are still being sent their last packets
+    fn is_closing(&self) -> bool {
+        false
+    }
+}
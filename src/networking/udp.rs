@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, hash_map::Entry};
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mio::net::UdpSocket;
+use mio::{Interest, Token, Waker};
+
+use crate::networking::helpers::{deserialize_to_packet, serialize_packet};
+use crate::networking::packet::{Packet, ServerInternal, ServerPacket};
+use crate::networking::poller::Poller;
+use crate::networking::shared::MAX_PACKET_SIZE;
+use crate::networking::transport::{Channel, ClientTransport, ServerTransport, TransportError};
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+// reliable packets further ahead than this are dropped unacked, the peer sends them again later,
+// and a peer with this many of ours unacked is gone
+pub const RELIABLE_WINDOW: u32 = 1024;
+const SOCKET: Token = Token(0);
+
+// what actually goes over the socket, payloads are serialized packets
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Datagram {
+    Connect,
+    Accept,
+    Heartbeat,
+    Unreliable {
+        stream: u8,
+        sequence: u32,
+        payload: Vec<u8>,
+    },
+    Reliable {
+        sequence: u32,
+        payload: Vec<u8>,
+    },
+    Ack {
+        sequence: u32,
+    },
+    Disconnect,
+}
+
+impl Datagram {
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Datagram> {
+        bincode::deserialize(buf).ok()
+    }
+}
+
+// channel state for one peer, the same on both ends
+// time is passed in so it can be tested without waiting
+pub struct Connection {
+    unreliable_sent: HashMap<u8, u32>,
+    unreliable_received: HashMap<u8, u32>,
+    next_reliable: u32,
+    unacked: BTreeMap<u32, (Vec<u8>, Instant, Instant)>, // payload, first and last time it was sent
+    next_expected: u32,
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    last_received: Instant,
+    last_sent: Instant,
+}
+
+impl Connection {
+    pub fn new(now: Instant) -> Connection {
+        Connection {
+            unreliable_sent: HashMap::new(),
+            unreliable_received: HashMap::new(),
+            next_reliable: 0,
+            unacked: BTreeMap::new(),
+            next_expected: 0,
+            out_of_order: BTreeMap::new(),
+            last_received: now,
+            last_sent: now,
+        }
+    }
+
+    pub fn send(&mut self, packet: Packet, now: Instant) -> Datagram {
+        let channel = packet.channel();
+        let payload = serialize_packet(packet);
+        self.last_sent = now;
+        match channel {
+            Channel::Reliable => {
+                let sequence = self.next_reliable;
+                self.next_reliable += 1;
+                self.unacked.insert(sequence, (payload.clone(), now, now));
+                Datagram::Reliable { sequence, payload }
+            }
+            Channel::Unreliable(stream) => {
+                let sequence = self.unreliable_sent.entry(stream).or_insert(0);
+                *sequence += 1;
+                Datagram::Unreliable {
+                    stream,
+                    sequence: *sequence,
+                    payload,
+                }
+            }
+        }
+    }
+
+    // returns the packets that can be handed to the game and what to send back
+    pub fn receive(&mut self, datagram: Datagram, now: Instant) -> (Vec<Packet>, Vec<Datagram>) {
+        self.last_received = now;
+        let mut packets = Vec::new();
+        let mut replies = Vec::new();
+        match datagram {
+            Datagram::Unreliable {
+                stream,
+                sequence,
+                payload,
+            } => {
+                let newest = self.unreliable_received.entry(stream).or_insert(0);
+                if sequence > *newest {
+                    *newest = sequence;
+                    packets.extend(decode(payload));
+                }
+            }
+            Datagram::Reliable { sequence, payload } => {
+                if sequence >= self.next_expected
+                    && sequence - self.next_expected >= RELIABLE_WINDOW
+                {
+                    return (packets, replies);
+                }
+                // always ack, the first ack might have been lost
+                replies.push(Datagram::Ack { sequence });
+                if sequence >= self.next_expected {
+                    self.out_of_order.insert(sequence, payload);
+                }
+                while let Some(payload) = self.out_of_order.remove(&self.next_expected) {
+                    self.next_expected += 1;
+                    packets.extend(decode(payload));
+                }
+            }
+            Datagram::Ack { sequence } => {
+                self.unacked.remove(&sequence);
+            }
+            // handled by the socket owners
+            Datagram::Connect | Datagram::Accept | Datagram::Heartbeat | Datagram::Disconnect => {}
+        }
+        if !replies.is_empty() {
+            self.last_sent = now;
+        }
+        (packets, replies)
+    }
+
+    // resends reliable packets that weren't acked and keeps a quiet connection alive
+    pub fn update(&mut self, now: Instant) -> Vec<Datagram> {
+        let mut datagrams = Vec::new();
+        for (sequence, (payload, _, sent)) in self.unacked.iter_mut() {
+            if now - *sent >= RESEND_INTERVAL {
+                *sent = now;
+                datagrams.push(Datagram::Reliable {
+                    sequence: *sequence,
+                    payload: payload.clone(),
+                });
+            }
+        }
+        if datagrams.is_empty() && now - self.last_sent >= HEARTBEAT_INTERVAL {
+            datagrams.push(Datagram::Heartbeat);
+        }
+        if !datagrams.is_empty() {
+            self.last_sent = now;
+        }
+        datagrams
+    }
+
+    pub fn timed_out(&self, now: Instant) -> bool {
+        now - self.last_received >= TIMEOUT
+    }
+
+    // the peer still sends, but doesn't ack what we send it
+    pub fn stalled(&self, now: Instant) -> bool {
+        self.unacked.len() >= RELIABLE_WINDOW as usize
+            || self
+                .unacked
+                .values()
+                .next()
+                .is_some_and(|(_, first_sent, _)| now - *first_sent >= TIMEOUT)
+    }
+
+    // every reliable packet got through
+    pub fn flushed(&self) -> bool {
+        self.unacked.is_empty()
+    }
+}
+
+fn decode(payload: Vec<u8>) -> Option<Packet> {
+    deserialize_to_packet(payload)
+        .map_err(|err| println!("Dropping packet: {}", err))
+        .ok()
+}
+
+fn send_datagram(socket: &UdpSocket, datagram: &Datagram, address: Option<SocketAddr>) {
+    let buf = datagram.encode();
+    // a full socket buffer is the same as a lost packet
+    let _ = match address {
+        Some(address) => socket.send_to(&buf, address),
+        None => socket.send(&buf),
+    };
+}
+
+enum ClientState {
+    Connecting {
+        started: Instant,
+        last_attempt: Instant,
+        queued: Vec<Packet>,
+    },
+    Connected,
+}
+
+pub struct UdpClientTransport {
+    socket: UdpSocket,
+    poller: Poller,
+    connection: Connection,
+    state: ClientState,
+    lost: bool, // reported after the packets that arrived with it
+}
+
+// a socket the poller watches, nothing arriving still has to wake us for resends and heartbeats
+fn watched_socket(socket: std::net::UdpSocket) -> std::io::Result<(UdpSocket, Poller)> {
+    socket.set_nonblocking(true)?;
+    let mut socket = UdpSocket::from_std(socket);
+    let poller = Poller::new()?;
+    poller
+        .registry()
+        .register(&mut socket, SOCKET, Interest::READABLE)?;
+    Ok((socket, poller))
+}
+
+impl UdpClientTransport {
+    // doesn't wait for the server, the handshake finishes while polling
+    pub fn connect(address: &str) -> std::io::Result<UdpClientTransport> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        let (socket, poller) = watched_socket(socket)?;
+        let now = Instant::now();
+        send_datagram(&socket, &Datagram::Connect, None);
+        Ok(UdpClientTransport {
+            socket,
+            poller,
+            connection: Connection::new(now),
+            state: ClientState::Connecting {
+                started: now,
+                last_attempt: now,
+                queued: Vec::new(),
+            },
+            lost: false,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, ClientState::Connected)
+    }
+}
+
+impl ClientTransport for UdpClientTransport {
+    fn send(&mut self, packet: Packet) -> Result<(), TransportError> {
+        match self.state {
+            ClientState::Connecting { ref mut queued, .. } => queued.push(packet),
+            ClientState::Connected => {
+                let datagram = self.connection.send(packet, Instant::now());
+                send_datagram(&self.socket, &datagram, None);
+            }
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<Packet>, TransportError> {
+        if self.lost {
+            return Err(TransportError::ConnectionLost);
+        }
+        let mut packets = Vec::new();
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let size = match self.socket.recv(&mut buf) {
+                Ok(size) => size,
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                // the server port is closed
+                Err(ref err) if err.kind() == ErrorKind::ConnectionRefused => {
+                    if self.is_connected() {
+                        return Err(TransportError::ConnectionLost);
+                    }
+                    break;
+                }
+                Err(_) => return Err(TransportError::ConnectionLost),
+            };
+            let Some(datagram) = Datagram::decode(&buf[..size]) else {
+                continue;
+            };
+            let now = Instant::now();
+            match datagram {
+                Datagram::Accept => {
+                    if let ClientState::Connecting { ref mut queued, .. } = self.state {
+                        let queued = std::mem::take(queued);
+                        self.state = ClientState::Connected;
+                        for packet in queued {
+                            self.send(packet)?;
+                        }
+                    }
+                    self.connection.receive(Datagram::Accept, now);
+                }
+                // a goodbye or reject might have come with it
+                Datagram::Disconnect => {
+                    self.lost = true;
+                    if packets.is_empty() {
+                        return Err(TransportError::ConnectionLost);
+                    }
+                    return Ok(packets);
+                }
+                datagram => {
+                    let (received, replies) = self.connection.receive(datagram, now);
+                    packets.extend(received);
+                    for reply in replies {
+                        send_datagram(&self.socket, &reply, None);
+                    }
+                }
+            }
+        }
+
+        let now = Instant::now();
+        match self.state {
+            ClientState::Connecting {
+                started,
+                ref mut last_attempt,
+                ..
+            } => {
+                if now - started >= TIMEOUT {
+                    return Err(TransportError::TimedOut);
+                }
+                if now - *last_attempt >= CONNECT_RETRY_INTERVAL {
+                    *last_attempt = now;
+                    send_datagram(&self.socket, &Datagram::Connect, None);
+                }
+            }
+            ClientState::Connected => {
+                if self.connection.timed_out(now) {
+                    return Err(TransportError::TimedOut);
+                }
+                if self.connection.stalled(now) {
+                    return Err(TransportError::ConnectionLost);
+                }
+                for datagram in self.connection.update(now) {
+                    send_datagram(&self.socket, &datagram, None);
+                }
+            }
+        }
+        Ok(packets)
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        self.poller.wait(timeout.min(RESEND_INTERVAL));
+    }
+
+    fn waker(&self) -> Option<Arc<Waker>> {
+        Some(self.poller.waker())
+    }
+}
+
+impl Drop for UdpClientTransport {
+    // lets the server know right away instead of waiting for the timeout
+    fn drop(&mut self) {
+        if self.is_connected() && !self.lost {
+            send_datagram(&self.socket, &Datagram::Disconnect, None);
+        }
+    }
+}
+
+// one socket for every client, connections are told apart by their address
+pub struct UdpServerTransport {
+    socket: UdpSocket,
+    poller: Poller,
+    connections: HashMap<SocketAddr, Connection>,
+    // disconnected, but kept until the last reliable packets (a goodbye, a reject) are acked
+    closing: HashMap<SocketAddr, Connection>,
+}
+
+impl UdpServerTransport {
+    pub fn bind(address: &str) -> std::io::Result<UdpServerTransport> {
+        let (socket, poller) = watched_socket(std::net::UdpSocket::bind(address)?)?;
+        Ok(UdpServerTransport {
+            socket,
+            poller,
+            connections: HashMap::new(),
+            closing: HashMap::new(),
+        })
+    }
+}
+
+impl ServerTransport for UdpServerTransport {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn poll(&mut self) -> Vec<ServerPacket> {
+        let mut events = Vec::new();
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let (size, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                // a client went away, it will time out
+                Err(_) => continue,
+            };
+            let Some(datagram) = Datagram::decode(&buf[..size]) else {
+                continue;
+            };
+            let now = Instant::now();
+            // only acks matter from a connection we're closing
+            if let Some(connection) = self.closing.get_mut(&addr) {
+                match datagram {
+                    Datagram::Connect | Datagram::Disconnect => {
+                        self.closing.remove(&addr);
+                    }
+                    datagram => {
+                        let (_, replies) = connection.receive(datagram, now);
+                        for reply in replies {
+                            send_datagram(&self.socket, &reply, Some(addr));
+                        }
+                        continue;
+                    }
+                }
+            }
+            match datagram {
+                Datagram::Connect => {
+                    // a repeated connect means our accept got lost
+                    if let Entry::Vacant(entry) = self.connections.entry(addr) {
+                        entry.insert(Connection::new(now));
+                        events.push(ServerPacket::AddPlayer(addr));
+                    }
+                    send_datagram(&self.socket, &Datagram::Accept, Some(addr));
+                }
+                Datagram::Disconnect => {
+                    if self.connections.remove(&addr).is_some() {
+                        println!("Closing connection with: {}", addr);
+                        events.push(ServerPacket::RemovePlayer(addr));
+                    }
+                }
+                datagram => {
+                    let Some(connection) = self.connections.get_mut(&addr) else {
+                        continue; // not connected
+                    };
+                    let (packets, replies) = connection.receive(datagram, now);
+                    for reply in replies {
+                        send_datagram(&self.socket, &reply, Some(addr));
+                    }
+                    events.extend(packets.into_iter().map(|packet| {
+                        ServerPacket::ServerInternalPacket(ServerInternal {
+                            address: addr,
+                            packet,
+                        })
+                    }));
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let mut lost = Vec::new();
+        for (addr, connection) in self.connections.iter_mut() {
+            if connection.timed_out(now) {
+                lost.push((*addr, "timed out"));
+                continue;
+            }
+            if connection.stalled(now) {
+                lost.push((*addr, "stopped acknowledging"));
+                continue;
+            }
+            for datagram in connection.update(now) {
+                send_datagram(&self.socket, &datagram, Some(*addr));
+            }
+        }
+        for (addr, reason) in lost {
+            println!("Connection with {} {}", addr, reason);
+            self.connections.remove(&addr);
+            events.push(ServerPacket::RemovePlayer(addr));
+        }
+
+        self.closing.retain(|addr, connection| {
+            if connection.flushed() || connection.timed_out(now) || connection.stalled(now) {
+                send_datagram(&self.socket, &Datagram::Disconnect, Some(*addr));
+                return false;
+            }
+            for datagram in connection.update(now) {
+                send_datagram(&self.socket, &datagram, Some(*addr));
+            }
+            true
+        });
+        events
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        // everything is read on the next poll anyway
+        self.poller.wait(timeout.min(RESEND_INTERVAL));
+    }
+
+    fn send(&mut self, address: SocketAddr, packet: Packet) {
+        if let Some(connection) = self.connections.get_mut(&address) {
+            let datagram = connection.send(packet, Instant::now());
+            send_datagram(&self.socket, &datagram, Some(address));
+        }
+    }
+
+    fn disconnect(&mut self, address: SocketAddr) {
+        let Some(connection) = self.connections.remove(&address) else {
+            return;
+        };
+        if connection.flushed() {
+            send_datagram(&self.socket, &Datagram::Disconnect, Some(address));
+        } else {
+            self.closing.insert(address, connection);
+        }
+    }
+
+    fn is_closing(&self) -> bool {
+        !self.closing.is_empty()
+    }
+}
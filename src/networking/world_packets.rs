@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{animation_data::AnimationState, enemy::EnemyType};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+// named like the variants of the other packet enums
+#[allow(clippy::enum_variant_names)]
+pub enum WorldPacket {
+    WorldStatePacket(WorldState),
+    LevelRequestPacket(LevelRequest),
+    LevelChunkPacket(LevelChunk),
+}
+
+// a client that doesn't have a level asks the server for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LevelRequest {
+    pub checksum: u64,
+}
+
+// one piece of a serialized LevelData, levels are too big for a single packet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LevelChunk {
+    pub checksum: u64,
+    pub index: u32,
+    pub count: u32,
+    pub data: Vec<u8>,
+}
+
+// everything the server simulates in one level, sent to the clients on that level
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorldState {
+    pub level: String,
+    pub time: f64,
+    pub players: Vec<PlayerState>,
+    pub enemies: Vec<EnemyState>,
+    pub projectiles: Vec<ProjectileState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerState {
+    pub player_id: u64,
+    pub health: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnemyState {
+    pub id: u64,
+    pub kind: EnemyType,
+    pub x: f64,
+    pub y: f64,
+    pub health: i32,
+    pub animation: AnimationState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectileState {
+    pub id: u64,
+    pub x: f64,
+    pub y: f64,
+    pub direction: f64,
+    pub fired_by_player: bool,
+}
@@ -0,0 +1,175 @@
+use sdl2::{event::Event, keyboard::Keycode};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use super::TIME_STEP;
+
+// a client that sends faster than the server simulates drops inputs instead of lagging behind
+const MAX_QUEUED_INPUTS: usize = 8;
+// a late input keeps the player moving, a client that stopped sending (paused, chatting,
+// gone) gets its player stopped after a quarter of a second
+const STOP_AFTER_MISSED: usize = 15;
+
+// what a player wants to do during one simulation step
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerInput {
+    pub move_x: i8,         // -1 left, 1 right
+    pub move_y: i8,         // -1 up, 1 down
+    pub shoot: Option<f64>, // direction in radians
+}
+
+// turns keyboard events into player inputs, the last pressed key on an axis wins
+#[derive(Debug, Default)]
+pub struct InputState {
+    pressed_up: bool,
+    pressed_down: bool,
+    pressed_left: bool,
+    pressed_right: bool,
+    move_x: i8,
+    move_y: i8,
+    pending_shot: Option<f64>,
+}
+
+impl InputState {
+    pub fn new() -> InputState {
+        InputState::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = InputState::default();
+    }
+
+    pub fn shoot(&mut self, direction: f64) {
+        self.pending_shot = Some(direction);
+    }
+
+    // a shot is only fired once, even if the game runs several steps per frame
+    pub fn take_input(&mut self) -> PlayerInput {
+        PlayerInput {
+            move_x: self.move_x,
+            move_y: self.move_y,
+            shoot: self.pending_shot.take(),
+        }
+    }
+
+    pub fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => match *keycode {
+                Keycode::Up | Keycode::W => {
+                    self.move_y = -1;
+                    self.pressed_up = true;
+                }
+                Keycode::Down | Keycode::S => {
+                    self.move_y = 1;
+                    self.pressed_down = true;
+                }
+                Keycode::Left | Keycode::A => {
+                    self.move_x = -1;
+                    self.pressed_left = true;
+                }
+                Keycode::Right | Keycode::D => {
+                    self.move_x = 1;
+                    self.pressed_right = true;
+                }
+                _ => (),
+            },
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => match *keycode {
+                Keycode::Up | Keycode::W => {
+                    self.pressed_up = false;
+                    self.move_y = if self.pressed_down { 1 } else { 0 };
+                }
+                Keycode::Down | Keycode::S => {
+                    self.pressed_down = false;
+                    self.move_y = if self.pressed_up { -1 } else { 0 };
+                }
+                Keycode::Left | Keycode::A => {
+                    self.pressed_left = false;
+                    self.move_x = if self.pressed_right { 1 } else { 0 };
+                }
+                Keycode::Right | Keycode::D => {
+                    self.pressed_right = false;
+                    self.move_x = if self.pressed_left { -1 } else { 0 };
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+// the inputs a client sent that the server hasn't simulated yet, one is used every step
+#[derive(Debug)]
+pub struct InputBuffer {
+    queued: VecDeque<(u32, PlayerInput)>,
+    last_sequence: u32,
+    capacity: usize,
+    missed: usize, // steps in a row without an input
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        InputBuffer {
+            queued: VecDeque::new(),
+            last_sequence: 0,
+            capacity: MAX_QUEUED_INPUTS,
+            missed: 0,
+        }
+    }
+}
+
+impl InputBuffer {
+    // a server that wakes up less often simulates more steps at once,
+    // so the buffer holds the inputs of two wakes
+    pub fn for_tick_rate(tick_rate: u32) -> InputBuffer {
+        let steps_per_wake = (1.0 / (tick_rate as f64 * TIME_STEP)).ceil() as usize;
+        InputBuffer {
+            capacity: MAX_QUEUED_INPUTS.max(2 * steps_per_wake),
+            ..InputBuffer::default()
+        }
+    }
+
+    pub fn push(&mut self, sequence: u32, input: PlayerInput) {
+        let newest = self
+            .queued
+            .back()
+            .map_or(self.last_sequence, |(seq, _)| *seq);
+        if sequence <= newest {
+            return; // duplicate or out of order
+        }
+        self.queued.push_back((sequence, input));
+
+        while self.queued.len() > self.capacity {
+            let (sequence, dropped) = self.queued.pop_front().unwrap();
+            self.last_sequence = sequence;
+            // movement can be corrected later, a lost shot can't
+            if let Some(shot) = dropped.shoot
+                && let Some((_, next)) = self.queued.front_mut()
+            {
+                next.shoot = next.shoot.or(Some(shot));
+            }
+        }
+    }
+
+    // the input for the next step, None if the client hasn't sent one in time,
+    // once it has missed too many the player is told to stand still
+    pub fn next(&mut self) -> Option<PlayerInput> {
+        let Some((sequence, input)) = self.queued.pop_front() else {
+            self.missed += 1;
+            return (self.missed == STOP_AFTER_MISSED).then(PlayerInput::default);
+        };
+        self.missed = 0;
+        self.last_sequence = sequence;
+        Some(input)
+    }
+
+    // the sequence number the client gets back as an acknowledgement
+    pub fn last_sequence(&self) -> u32 {
+        self.last_sequence
+    }
+}
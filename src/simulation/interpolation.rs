@@ -0,0 +1,112 @@
+use std::collections::{HashMap, VecDeque};
+
+// remote entities are drawn this far in the past, so there is usually a newer snapshot to move towards
+pub const RENDER_DELAY: f64 = 0.1;
+// how long an entity keeps moving on its own after snapshots stop arriving
+const MAX_EXTRAPOLATION: f64 = 0.25;
+const MAX_SNAPSHOTS: usize = 32;
+// how quickly the clock estimate follows packets that arrived late
+const CLOCK_SMOOTHING: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Snapshot {
+    time: f64, // server time
+    x: f64,
+    y: f64,
+}
+
+// the last few positions the server reported for one entity
+#[derive(Debug, Default)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotBuffer {
+    pub fn push(&mut self, time: f64, x: f64, y: f64) {
+        if self.snapshots.back().is_some_and(|last| last.time >= time) {
+            return; // arrived out of order
+        }
+        self.snapshots.push_back(Snapshot { time, x, y });
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    // position at the given server time, between the two snapshots around it
+    // or a bit past the newest one if packets went missing
+    pub fn sample(&self, time: f64) -> Option<(f64, f64)> {
+        let first = self.snapshots.front()?;
+        if time <= first.time {
+            return Some((first.x, first.y));
+        }
+
+        let newer = self.snapshots.iter().position(|s| s.time >= time);
+        let (from, to, t) = match newer {
+            Some(index) => {
+                let (from, to) = (self.snapshots[index - 1], self.snapshots[index]);
+                (from, to, (time - from.time) / (to.time - from.time))
+            }
+            None if self.snapshots.len() >= 2 => {
+                let to = self.snapshots[self.snapshots.len() - 1];
+                let from = self.snapshots[self.snapshots.len() - 2];
+                let ahead = f64::min(time - to.time, MAX_EXTRAPOLATION);
+                (from, to, 1.0 + ahead / (to.time - from.time))
+            }
+            None => return Some((first.x, first.y)),
+        };
+        Some((from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t))
+    }
+}
+
+// the snapshot buffers of every entity of one kind, by id,
+// entities missing from a snapshot are gone and forgotten
+#[derive(Debug, Default)]
+pub struct EntitySnapshots {
+    buffers: HashMap<u64, SnapshotBuffer>,
+}
+
+impl EntitySnapshots {
+    pub fn push(&mut self, time: f64, positions: impl IntoIterator<Item = (u64, f64, f64)>) {
+        let mut buffers = HashMap::new();
+        for (id, x, y) in positions {
+            let mut buffer = self.buffers.remove(&id).unwrap_or_default();
+            buffer.push(time, x, y);
+            buffers.insert(id, buffer);
+        }
+        self.buffers = buffers;
+    }
+
+    pub fn sample(&self, id: u64, time: f64) -> Option<(f64, f64)> {
+        self.buffers.get(&id)?.sample(time)
+    }
+
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+}
+
+// estimates the server's clock from the timestamps of its snapshots
+#[derive(Debug, Default)]
+pub struct RenderClock {
+    offset: Option<f64>, // server time - local time
+}
+
+impl RenderClock {
+    pub fn observe(&mut self, server_time: f64, local_time: f64) {
+        let offset = server_time - local_time;
+        self.offset = Some(match self.offset {
+            // a packet that came quicker than the others is the best guess we have
+            Some(current) if offset < current => current + (offset - current) * CLOCK_SMOOTHING,
+            _ => offset,
+        });
+    }
+
+    // the server's clock starts over on another level
+    pub fn reset(&mut self) {
+        self.offset = None;
+    }
+
+    pub fn render_time(&self, local_time: f64) -> Option<f64> {
+        Some(local_time + self.offset? - RENDER_DELAY)
+    }
+}
@@ -0,0 +1,6 @@
+pub mod input;
+pub mod interpolation;
+pub mod prediction;
+pub mod world;
+
+pub const TIME_STEP: f64 = 1.0 / 60.0;
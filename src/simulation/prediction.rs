@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+use crate::networking::player_packets::{PlayerInputCommand, PlayerPosition};
+use crate::simulation::{TIME_STEP, input::PlayerInput, world::World};
+
+// how many unacknowledged inputs go along with every new one, a lost packet loses nothing
+// as long as one of the next few arrives
+const RESENT_INPUTS: usize = 8;
+
+// moves the local player right away and fixes it up once the server answers
+#[derive(Debug, Default)]
+pub struct Prediction {
+    next_sequence: u32,
+    pending: VecDeque<PlayerInputCommand>, // sent, but not acknowledged yet
+}
+
+impl Prediction {
+    pub fn new() -> Prediction {
+        Prediction {
+            next_sequence: 1,
+            pending: VecDeque::new(),
+        }
+    }
+
+    // numbers an input and remembers it, the result has to be sent to the server
+    pub fn record(&mut self, player_id: u64, input: PlayerInput) -> PlayerInputCommand {
+        let earlier = self
+            .pending
+            .iter()
+            .skip(self.pending.len().saturating_sub(RESENT_INPUTS))
+            .map(|command| command.input)
+            .collect();
+        let command = PlayerInputCommand {
+            player_id,
+            sequence: self.next_sequence,
+            input,
+            earlier: Vec::new(),
+        };
+        self.next_sequence += 1;
+        self.pending.push_back(command.clone());
+        PlayerInputCommand { earlier, ..command }
+    }
+
+    // start from the server's position and replay everything it hasn't seen yet
+    pub fn reconcile(&mut self, world: &mut World, position: &PlayerPosition) {
+        while self
+            .pending
+            .front()
+            .is_some_and(|command| command.sequence <= position.sequence)
+        {
+            self.pending.pop_front();
+        }
+
+        let World {
+            players,
+            level,
+            time,
+            ..
+        } = world;
+        let Some(player) = players.iter_mut().find(|p| p.id == position.player_id) else {
+            return;
+        };
+        player.set_position(position.x, position.y);
+        for command in &self.pending {
+            player.apply_input(&command.input);
+            player.update(TIME_STEP, level, &[], *time);
+        }
+        // exits are handled by the server
+        player.reached_end = None;
+    }
+}
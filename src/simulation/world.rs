@@ -0,0 +1,277 @@
+use rand::{SeedableRng, rngs::StdRng};
+use std::collections::HashMap;
+
+use crate::entities::{
+    animation_data::AnimationState,
+    enemy::{Enemy, EnemyType},
+    player::Player,
+    projectile::Projectile,
+};
+use crate::environment::{level::Level, tile_type::ExitTile};
+use crate::networking::world_packets::{EnemyState, PlayerState, ProjectileState, WorldState};
+use crate::simulation::input::PlayerInput;
+
+const SHOT_COOLDOWN: f64 = 0.25;
+
+// things the owner of the world has to react to (load textures, switch levels, ...)
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldEvent {
+    ExitReached { player_id: u64, exit: ExitTile },
+    PlayerDied(u64),
+}
+
+// the whole game state of one level, without anything tied to a window
+pub struct World {
+    pub level: Level,
+    pub level_path: String,
+    pub players: Vec<Player>,
+    pub enemies: Vec<Enemy>,
+    pub projectiles: Vec<Projectile>,
+    pub time: f64, // seconds of simulated time
+    // only the authoritative world (server) runs enemy ai, shooting and damage,
+    // the others just move players and replay what the server sends
+    pub authoritative: bool,
+    next_entity_id: u64, // 0 is left for projectiles that aren't numbered yet
+    // everything random in the simulation comes from here, so a seed replays the same game
+    rng: StdRng,
+}
+
+impl World {
+    pub fn new(level_path: String, seed: u64) -> Result<World, String> {
+        let mut world = World::new_client(level_path)?;
+        world.authoritative = true;
+        world.rng = StdRng::seed_from_u64(seed);
+        for (kind, x, y) in world.level.enemy_spawns.clone() {
+            world.spawn_enemy(kind, x, y);
+        }
+        Ok(world)
+    }
+
+    pub fn new_client(level_path: String) -> Result<World, String> {
+        Ok(World {
+            level: Level::load(&level_path)?,
+            level_path,
+            players: Vec::new(),
+            enemies: Vec::new(),
+            projectiles: Vec::new(),
+            time: 0.0,
+            authoritative: false,
+            next_entity_id: 1,
+            // clients don't run anything random, the server does
+            rng: StdRng::seed_from_u64(0),
+        })
+    }
+
+    pub fn spawn_enemy(&mut self, kind: EnemyType, x: f64, y: f64) -> u64 {
+        let mut enemy = Enemy::new(kind);
+        enemy.set_position(x, y);
+        enemy.id = self.next_entity_id;
+        self.next_entity_id += 1;
+        self.enemies.push(enemy);
+        self.next_entity_id - 1
+    }
+
+    pub fn add_player(&mut self, mut player: Player) {
+        let spawn = self.level.spawn_point(player.id);
+        player.spawn_at(spawn.0, spawn.1);
+        player.current_level = self.level_path.clone();
+        self.players.push(player);
+    }
+
+    pub fn remove_player(&mut self, id: u64) -> Option<Player> {
+        let index = self.players.iter().position(|player| player.id == id)?;
+        Some(self.players.remove(index))
+    }
+
+    pub fn player(&self, id: u64) -> Option<&Player> {
+        self.players.iter().find(|player| player.id == id)
+    }
+
+    pub fn player_mut(&mut self, id: u64) -> Option<&mut Player> {
+        self.players.iter_mut().find(|player| player.id == id)
+    }
+
+    // switches to an already loaded level under its path, players start over on its spawn
+    pub fn set_level(&mut self, level_path: String, level: Level) {
+        self.level = level;
+        self.level_path = level_path;
+        self.projectiles.clear();
+        for player in &mut self.players {
+            let spawn = self.level.spawn_point(player.id);
+            player.spawn_at(spawn.0, spawn.1);
+            player.current_level = self.level_path.clone();
+        }
+    }
+
+    // advance the simulation by dt seconds, players without an input keep their last one
+    pub fn step(&mut self, dt: f64, inputs: &HashMap<u64, PlayerInput>) -> Vec<WorldEvent> {
+        let mut events = Vec::new();
+        let alive: Vec<bool> = self.players.iter().map(|p| p.health > 0).collect();
+
+        for enemy in &mut self.enemies {
+            if self.authoritative {
+                enemy.update(
+                    dt,
+                    &self.level,
+                    &self.players,
+                    self.time,
+                    &mut self.projectiles,
+                    &mut self.rng,
+                );
+            } else {
+                enemy.animate(dt);
+            }
+        }
+
+        // contact damage is decided by the server
+        let enemies: &[Enemy] = if self.authoritative {
+            &self.enemies
+        } else {
+            &[]
+        };
+        for player in &mut self.players {
+            if player.health <= 0 {
+                continue;
+            }
+            if let Some(input) = inputs.get(&player.id) {
+                player.apply_input(input);
+                if let Some(direction) = input.shoot
+                    && self.authoritative
+                    && self.time - player.last_shot_time > SHOT_COOLDOWN
+                {
+                    player.last_shot_time = self.time;
+                    self.projectiles.push(Projectile::new(
+                        player.x + player.size_x as f64 / 2.0,
+                        player.y + player.size_y as f64 / 2.0,
+                        15,
+                        direction,
+                        true,
+                    ));
+                }
+            }
+            player.update(dt, &self.level, enemies, self.time);
+            if let Some(exit) = player.reached_end.take() {
+                events.push(WorldEvent::ExitReached {
+                    player_id: player.id,
+                    exit,
+                });
+            }
+        }
+
+        if self.authoritative {
+            // enemies and players shot, clients tell the new projectiles apart by their ids
+            for projectile in &mut self.projectiles {
+                if projectile.id == 0 {
+                    projectile.id = self.next_entity_id;
+                    self.next_entity_id += 1;
+                }
+            }
+            self.projectiles.retain_mut(|projectile| {
+                projectile.update(dt);
+                // remove projectile if it collides with something
+                !projectile.resolve_collision(&self.level, &mut self.enemies, &mut self.players)
+            });
+
+            // remove dead enemies
+            self.enemies.retain(|enemy| enemy.health > 0);
+        } else {
+            for projectile in &mut self.projectiles {
+                projectile.update(dt);
+            }
+        }
+
+        for (player, was_alive) in self.players.iter().zip(alive) {
+            if was_alive && player.health <= 0 {
+                events.push(WorldEvent::PlayerDied(player.id));
+            }
+        }
+
+        self.time += dt;
+        events
+    }
+
+    pub fn snapshot(&self) -> WorldState {
+        WorldState {
+            level: self.level_path.clone(),
+            time: self.time,
+            players: self
+                .players
+                .iter()
+                .map(|player| PlayerState {
+                    player_id: player.id,
+                    health: player.health,
+                })
+                .collect(),
+            enemies: self
+                .enemies
+                .iter()
+                .map(|enemy| EnemyState {
+                    id: enemy.id,
+                    kind: enemy.kind,
+                    x: enemy.x,
+                    y: enemy.y,
+                    health: enemy.health,
+                    animation: enemy
+                        .animation_data
+                        .as_ref()
+                        .map_or(AnimationState::Default, |data| {
+                            data.current_animation.clone()
+                        }),
+                })
+                .collect(),
+            projectiles: self
+                .projectiles
+                .iter()
+                .map(|projectile| ProjectileState {
+                    id: projectile.id,
+                    x: projectile.x,
+                    y: projectile.y,
+                    direction: projectile.direction,
+                    fired_by_player: projectile.fired_by_player,
+                })
+                .collect(),
+        }
+    }
+
+    // replace enemies, projectiles and health with the server's version,
+    // returns true if new enemies appeared (their textures still need loading)
+    pub fn apply_snapshot(&mut self, state: &WorldState) -> bool {
+        let mut spawned = false;
+        let mut enemies = Vec::with_capacity(state.enemies.len());
+        for enemy_state in &state.enemies {
+            let mut enemy = match self.enemies.iter().position(|e| e.id == enemy_state.id) {
+                Some(index) => self.enemies.swap_remove(index),
+                None => {
+                    spawned = true;
+                    let mut enemy = Enemy::new(enemy_state.kind);
+                    enemy.id = enemy_state.id;
+                    enemy
+                }
+            };
+            enemy.set_position(enemy_state.x, enemy_state.y);
+            enemy.health = enemy_state.health;
+            if let Some(ref mut animation_data) = enemy.animation_data {
+                animation_data.current_animation = enemy_state.animation.clone();
+            }
+            enemies.push(enemy);
+        }
+        self.enemies = enemies;
+
+        self.projectiles = state
+            .projectiles
+            .iter()
+            .map(|p| Projectile {
+                id: p.id,
+                ..Projectile::new(p.x, p.y, 15, p.direction, p.fired_by_player)
+            })
+            .collect();
+
+        for player_state in &state.players {
+            let time = self.time;
+            if let Some(player) = self.player_mut(player_state.player_id) {
+                player.set_health(player_state.health, time);
+            }
+        }
+        spawned
+    }
+}
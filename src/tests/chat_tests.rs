@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use crate::display::chat::{CHAT_LINES, ChatLog};
+use crate::networking::shared::MAX_CHAT_LENGTH;
+
+fn texts(chat: &ChatLog, now: Instant) -> Vec<String> {
+    chat.visible(now)
+        .into_iter()
+        .map(|(line, _)| line.text.clone())
+        .collect()
+}
+
+fn alpha(chat: &ChatLog, now: Instant) -> Vec<u8> {
+    chat.visible(now).iter().map(|(_, alpha)| *alpha).collect()
+}
+
+#[test]
+fn old_lines_fade_out() {
+    let start = Instant::now();
+    let mut chat = ChatLog::default();
+    chat.push("first".to_string(), true, start);
+    chat.push("second".to_string(), false, start + Duration::from_secs(5));
+
+    assert_eq!(alpha(&chat, start + Duration::from_secs(1)), vec![255, 255]);
+    let fading = alpha(&chat, start + Duration::from_secs(9));
+    assert!(fading[0] > 0 && fading[0] < 255);
+    assert_eq!(
+        texts(&chat, start + Duration::from_secs(11)),
+        vec!["second"]
+    );
+    assert!(chat.visible(start + Duration::from_secs(20)).is_empty());
+
+    // everything comes back while typing
+    chat.open();
+    assert_eq!(
+        alpha(&chat, start + Duration::from_secs(20)),
+        vec![255, 255]
+    );
+}
+
+#[test]
+fn typing_and_sending() {
+    let mut chat = ChatLog::default();
+    chat.type_text("ignored");
+    assert_eq!(chat.input(), None);
+
+    chat.open();
+    chat.type_text("  hi\tthere!");
+    chat.backspace();
+    assert_eq!(chat.input(), Some("  hithere"));
+    assert_eq!(chat.submit(), Some("hithere".to_string()));
+    assert!(!chat.is_open());
+
+    chat.open();
+    chat.type_text("   ");
+    assert_eq!(chat.submit(), None);
+
+    chat.open();
+    chat.type_text(&"x".repeat(MAX_CHAT_LENGTH + 10));
+    assert_eq!(chat.input().unwrap().len(), MAX_CHAT_LENGTH);
+}
+
+#[test]
+fn scrolling_back() {
+    let now = Instant::now();
+    let mut chat = ChatLog::default();
+    for i in 0..20 {
+        chat.push(i.to_string(), false, now);
+    }
+    assert_eq!(chat.visible(now).len(), CHAT_LINES);
+    assert_eq!(texts(&chat, now).last().unwrap(), "19");
+
+    chat.scroll(5); // only while typing
+    assert_eq!(texts(&chat, now).last().unwrap(), "19");
+    chat.open();
+    chat.scroll(5);
+    assert_eq!(texts(&chat, now).last().unwrap(), "14");
+    chat.scroll(100);
+    assert_eq!(texts(&chat, now)[0], "0");
+    chat.scroll(-100);
+    assert_eq!(texts(&chat, now).last().unwrap(), "19");
+}
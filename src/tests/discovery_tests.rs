@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::networking::{
+    discovery::{DiscoveryResponder, ServerAnnouncement, discover},
+    packet::PROTOCOL_VERSION,
+    transport::TransportKind,
+};
+
+fn announcement(server_id: u64) -> ServerAnnouncement {
+    ServerAnnouncement {
+        server_id,
+        name: "test".to_string(),
+        port: 7000,
+        transport: TransportKind::Udp,
+        protocol_version: PROTOCOL_VERSION,
+        players: 1,
+        max_players: 8,
+        level: Some("resources/levels/level1.json".to_string()),
+    }
+}
+
+// answers queries in the background until dropped
+struct Answering {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for Answering {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn answering(responder: DiscoveryResponder, server_id: u64) -> Answering {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    thread::spawn(move || {
+        while !stopped.load(Ordering::Relaxed) {
+            responder.answer(|| announcement(server_id));
+            thread::sleep(Duration::from_millis(5));
+        }
+    });
+    Answering { stop }
+}
+
+#[test]
+fn servers_answer_queries() {
+    let responder = DiscoveryResponder::bind("127.0.0.1:0").unwrap();
+    let address = responder.local_addr().unwrap();
+    let _answering = answering(responder, 42);
+
+    let servers = discover(&[address], Duration::from_millis(300)).unwrap();
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].announcement, announcement(42));
+    // the game port, not the discovery one
+    assert_eq!(servers[0].address, "127.0.0.1:7000".parse().unwrap());
+}
+
+#[test]
+fn servers_are_listed_once() {
+    let responder = DiscoveryResponder::bind("127.0.0.1:0").unwrap();
+    let address = responder.local_addr().unwrap();
+    let _answering = answering(responder, 42);
+
+    // asked twice, as with the broadcast and localhost
+    let servers = discover(&[address, address], Duration::from_millis(300)).unwrap();
+    assert_eq!(servers.len(), 1);
+}
+
+#[test]
+fn nobody_answers() {
+    let responder = DiscoveryResponder::bind("127.0.0.1:0").unwrap();
+    let address = responder.local_addr().unwrap();
+    // bound but never answering
+    let servers = discover(&[address], Duration::from_millis(100)).unwrap();
+    assert!(servers.is_empty());
+    drop(responder);
+}
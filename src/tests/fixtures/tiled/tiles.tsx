@@ -0,0 +1,22 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.10.2" name="sea2d" tilewidth="60" tileheight="60" tilecount="5" columns="5">
+ <image source="tiles.png" width="300" height="60"/>
+ <tile id="0" class="wall">
+  <properties>
+   <property name="solid" type="bool" value="true"/>
+  </properties>
+ </tile>
+ <tile id="1" type="grass"/>
+ <tile id="2" type="water">
+  <properties>
+   <property name="solid" type="bool" value="true"/>
+  </properties>
+ </tile>
+ <tile id="3" type="exit"/>
+ <tile id="4">
+  <properties>
+   <property name="solid" type="bool" value="true"/>
+   <property name="tile" value="rock"/>
+  </properties>
+ </tile>
+</tileset>
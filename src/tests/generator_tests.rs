@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use super::map_tests::scratch_dir;
+use crate::environment::{
+    generator::{
+        GenerationParams, LearnParams, generate_map, generated_level_name, learn_level,
+        parse_generated_name,
+    },
+    level::Level,
+    map::MapFile,
+    tile_type::TileType,
+};
+
+const SEED: u64 = 1234;
+
+fn small(floor: u32) -> GenerationParams {
+    GenerationParams {
+        width: 16,
+        height: 12,
+        floor,
+    }
+}
+
+// the tiles a player can walk on, going through the exit too
+fn walkable_tiles(map: &MapFile) -> Vec<Vec<bool>> {
+    let ground = map.grid(&map.layers[0]).unwrap();
+    let exits = map.grid(&map.layers[1]).unwrap();
+    ground
+        .iter()
+        .zip(&exits)
+        .map(|(ground_row, exit_row)| {
+            ground_row
+                .iter()
+                .zip(exit_row)
+                .map(|(ground, exit)| {
+                    let (_, solid) = map.tile_type(*ground).unwrap().unwrap();
+                    !solid || *exit != 0
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn reachable(walkable: &[Vec<bool>], from: (usize, usize), to: (usize, usize)) -> bool {
+    let mut seen = vec![vec![false; walkable[0].len()]; walkable.len()];
+    let mut queue = VecDeque::from([from]);
+    seen[from.1][from.0] = true;
+    while let Some((x, y)) = queue.pop_front() {
+        if (x, y) == to {
+            return true;
+        }
+        for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+            if walkable[ny][nx] && !seen[ny][nx] {
+                seen[ny][nx] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    false
+}
+
+#[test]
+fn generated_names_round_trip() {
+    let name = generated_level_name(SEED, 3);
+    assert_eq!(name, "generated:1234:3");
+    assert_eq!(parse_generated_name(&name), Some((SEED, 3)));
+    assert_eq!(parse_generated_name("resources/levels/level1.json"), None);
+    assert_eq!(parse_generated_name("generated:abc:0"), None);
+    assert_eq!(parse_generated_name("generated:1234"), None);
+}
+
+#[test]
+fn same_seed_same_level() {
+    assert_eq!(
+        generate_map(SEED, small(0)).unwrap(),
+        generate_map(SEED, small(0)).unwrap()
+    );
+    assert_ne!(
+        generate_map(SEED, small(0)).unwrap().layers,
+        generate_map(SEED, small(1)).unwrap().layers
+    );
+}
+
+#[test]
+fn exits_are_reachable_and_lead_deeper() {
+    for seed in 0..5 {
+        let map = generate_map(seed, small(seed as u32)).unwrap();
+        assert!(map.validate().is_ok());
+        assert_eq!((map.width, map.height), (18, 14));
+        assert_eq!(map.spawns.len(), 1);
+        assert_eq!(map.exits.len(), 1);
+        let exit = &map.exits[0];
+        assert_eq!(exit.target, generated_level_name(seed, seed as u32 + 1));
+
+        let walkable = walkable_tiles(&map);
+        let spawn = (map.spawns[0].x as usize, map.spawns[0].y as usize);
+        assert!(walkable[spawn.1][spawn.0]);
+        assert!(reachable(
+            &walkable,
+            spawn,
+            (exit.x as usize, exit.y as usize)
+        ));
+        for enemy in &map.enemies {
+            assert!(walkable[enemy.y as usize][enemy.x as usize]);
+        }
+    }
+
+    // there's nothing below the deepest floor
+    let deepest = generate_map(SEED, small(u32::MAX)).unwrap();
+    assert_eq!(deepest.info.name, "Floor 4294967296");
+    assert_eq!(
+        deepest.exits[0].target,
+        generated_level_name(SEED, u32::MAX)
+    );
+}
+
+#[test]
+fn generated_levels_load_by_name() {
+    let name = generated_level_name(SEED, 0);
+    let level = Level::load(&name).unwrap();
+    assert_eq!(level.name, "Floor 1");
+    assert_eq!(
+        level.to_data(),
+        Level::generate(SEED, GenerationParams::default())
+            .unwrap()
+            .to_data()
+    );
+
+    // a wall all around with collision, and the exit to the next floor
+    let (width, height) = (34, 26);
+    let size = level.tile_size;
+    for tile in level.tiles[0].values() {
+        let (x, y) = (tile.x / size, tile.y / size);
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            assert_eq!(tile.tile_type, TileType::Wall);
+            assert!(tile.bounding_box.is_some());
+        }
+    }
+    let exits: Vec<&TileType> = level.tiles[1]
+        .values()
+        .map(|tile| &tile.tile_type)
+        .collect();
+    match exits[..] {
+        [TileType::Exit(exit)] => assert_eq!(exit.next_level, generated_level_name(SEED, 1)),
+        _ => panic!("expected one exit, got {:?}", exits),
+    }
+}
+
+#[test]
+fn learned_levels_load_as_png_levels() {
+    let example = ::image::open("resources/examples/level1.png")
+        .unwrap()
+        .to_rgba8();
+    let params = LearnParams {
+        width: 16,
+        height: 12,
+        ..LearnParams::default()
+    };
+    let learned = learn_level(&example, SEED, &params).unwrap();
+    assert_eq!(learned.ground.dimensions(), (18, 14));
+    let base = scratch_dir("learned").join("learned");
+    let path = learned.save(base.to_str().unwrap()).unwrap();
+
+    let map = MapFile::from_png(&path).unwrap();
+    assert_eq!(map.spawns.len(), 1);
+    assert_eq!(map.exits.len(), 1);
+    assert_eq!(map.exits[0].target, params.next);
+    let walkable = walkable_tiles(&map);
+    let spawn = (map.spawns[0].x as usize, map.spawns[0].y as usize);
+    let exit = (map.exits[0].x as usize, map.exits[0].y as usize);
+    assert!(walkable[spawn.1][spawn.0]);
+    assert!(reachable(&walkable, spawn, exit));
+    assert!(Level::load(&path).is_ok());
+}
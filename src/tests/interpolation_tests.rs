@@ -0,0 +1,71 @@
+use crate::simulation::interpolation::{
+    EntitySnapshots, RENDER_DELAY, RenderClock, SnapshotBuffer,
+};
+
+fn buffer(snapshots: &[(f64, f64, f64)]) -> SnapshotBuffer {
+    let mut buffer = SnapshotBuffer::default();
+    for (time, x, y) in snapshots {
+        buffer.push(*time, *x, *y);
+    }
+    buffer
+}
+
+#[test]
+fn empty_buffer_has_no_position() {
+    assert_eq!(SnapshotBuffer::default().sample(1.0), None);
+}
+
+#[test]
+fn interpolates_between_snapshots() {
+    let buffer = buffer(&[(0.0, 0.0, 0.0), (0.1, 10.0, 20.0), (0.2, 20.0, 20.0)]);
+    assert_eq!(buffer.sample(0.05), Some((5.0, 10.0)));
+    assert_eq!(buffer.sample(0.1), Some((10.0, 20.0)));
+    assert_eq!(buffer.sample(0.15), Some((15.0, 20.0)));
+    // older than anything we have
+    assert_eq!(buffer.sample(-1.0), Some((0.0, 0.0)));
+}
+
+#[test]
+fn extrapolates_a_limited_time() {
+    let buffer = buffer(&[(0.0, 0.0, 0.0), (0.1, 10.0, 0.0)]);
+    let (x, _) = buffer.sample(0.2).unwrap();
+    assert!((x - 20.0).abs() < 1e-9);
+    // after a while the entity stops instead of running off
+    assert_eq!(buffer.sample(10.0), buffer.sample(0.5));
+}
+
+#[test]
+fn ignores_old_snapshots() {
+    let buffer = buffer(&[(0.0, 0.0, 0.0), (0.2, 20.0, 0.0), (0.1, 100.0, 0.0)]);
+    assert_eq!(buffer.sample(0.1), Some((10.0, 0.0)));
+}
+
+#[test]
+fn entities_are_interpolated_by_id() {
+    let mut entities = EntitySnapshots::default();
+    entities.push(0.0, [(1, 0.0, 0.0), (2, 50.0, 50.0)]);
+    entities.push(0.1, [(2, 60.0, 50.0), (1, 10.0, 0.0)]);
+    assert_eq!(entities.sample(1, 0.05), Some((5.0, 0.0)));
+    assert_eq!(entities.sample(2, 0.05), Some((55.0, 50.0)));
+
+    // the second one was destroyed, a new one shows up
+    entities.push(0.2, [(1, 20.0, 0.0), (3, 0.0, 0.0)]);
+    assert_eq!(entities.sample(1, 0.15), Some((15.0, 0.0)));
+    assert_eq!(entities.sample(2, 0.15), None);
+    assert_eq!(entities.sample(3, 0.15), Some((0.0, 0.0)));
+}
+
+#[test]
+fn render_clock_follows_server() {
+    let mut clock = RenderClock::default();
+    assert_eq!(clock.render_time(0.0), None);
+
+    clock.observe(10.0, 2.0);
+    assert_eq!(clock.render_time(3.0), Some(11.0 - RENDER_DELAY));
+    // a packet that was held up doesn't throw the clock back
+    clock.observe(10.5, 3.0);
+    assert!(clock.render_time(3.0).unwrap() > 10.9 - RENDER_DELAY);
+
+    clock.reset();
+    assert_eq!(clock.render_time(3.0), None);
+}
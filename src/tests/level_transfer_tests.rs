@@ -0,0 +1,100 @@
+use crate::environment::{generator::generated_level_name, level::Level, tile_type::TileType};
+use crate::networking::level_transfer::{LevelCache, LevelDownload, level_checksum, load_local};
+
+const LEVEL: &str = "resources/levels/level1.json";
+
+fn load(path: &str) -> Level {
+    Level::load(path).unwrap()
+}
+
+fn exits(level: &Level) -> Vec<String> {
+    let mut exits: Vec<String> = level
+        .tiles
+        .iter()
+        .flat_map(|layer| layer.values())
+        .filter_map(|tile| match &tile.tile_type {
+            TileType::Exit(exit) => Some(exit.next_level.clone()),
+            _ => None,
+        })
+        .collect();
+    exits.sort();
+    exits
+}
+
+#[test]
+fn level_data_round_trips() {
+    let level = load(LEVEL);
+    let data = level.to_data();
+    let copy = Level::from_data(&data);
+    assert_eq!(copy.to_data(), data);
+    assert_eq!(copy.player_spawn, level.player_spawn);
+    assert!(!exits(&copy).is_empty());
+    assert_eq!(exits(&copy), exits(&level));
+
+    let solid = |level: &Level| {
+        level
+            .tiles
+            .iter()
+            .flat_map(|layer| layer.values())
+            .filter(|tile| tile.bounding_box.is_some())
+            .count()
+    };
+    assert_eq!(solid(&copy), solid(&level));
+}
+
+#[test]
+fn checksums_tell_levels_apart() {
+    let first = level_checksum(&load(LEVEL).to_data());
+    assert_eq!(first, level_checksum(&load(LEVEL).to_data()));
+    assert_ne!(
+        first,
+        level_checksum(&load("resources/levels/level2.json").to_data())
+    );
+
+    assert!(load_local(LEVEL, first).is_some());
+    assert!(load_local(LEVEL, first + 1).is_none());
+    assert!(load_local("resources/levels/generated.json", first).is_none());
+
+    // generated levels are made again instead of downloaded
+    let generated = generated_level_name(1234, 2);
+    let checksum = level_checksum(&load(&generated).to_data());
+    assert!(load_local(&generated, checksum).is_some());
+    assert!(load_local(&generated, first).is_none());
+}
+
+#[test]
+fn downloads_survive_reordering() {
+    // big enough to need several chunks
+    let level = load("resources/levels/stress_test1.json");
+    let mut cache = LevelCache::default();
+    let checksum = cache.checksum("stress", &level);
+    let mut chunks = cache.chunks(checksum);
+    assert!(chunks.len() > 2);
+    chunks.reverse();
+
+    let mut download = LevelDownload::new("stress".to_string(), checksum);
+    let last = chunks.pop().unwrap();
+    for chunk in chunks {
+        assert_eq!(download.push(chunk), Ok(None));
+    }
+    assert_eq!(download.push(last), Ok(Some(level.to_data())));
+
+    assert!(cache.chunks(checksum + 1).is_empty());
+    cache.retain(|_| false);
+    assert!(cache.chunks(checksum).is_empty());
+}
+
+#[test]
+fn corrupted_downloads_are_rejected() {
+    let mut cache = LevelCache::default();
+    let checksum = cache.checksum(LEVEL, &load(LEVEL));
+    let mut download = LevelDownload::new(LEVEL.to_string(), checksum);
+    let mut result = Ok(None);
+    for mut chunk in cache.chunks(checksum) {
+        if chunk.index == 0 {
+            chunk.data[0] ^= 1;
+        }
+        result = download.push(chunk);
+    }
+    assert!(result.is_err());
+}
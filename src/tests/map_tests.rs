@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use ::image::{Rgba, RgbaImage};
+
+use crate::entities::enemy::EnemyType;
+use crate::environment::{
+    level::Level,
+    map::{
+        EnemySpawn, MapExit, MapFile, MapInfo, MapLayer, MapPoint, MapTile, convert_levels,
+        legacy_levels,
+    },
+    tile_type::TileType,
+};
+use crate::simulation::world::World;
+
+// a room with a wall on top, an exit in it and grass on the floor
+fn small_map() -> MapFile {
+    let tile = |tile: &str, solid| MapTile {
+        tile: tile.to_string(),
+        solid,
+    };
+    MapFile {
+        info: MapInfo {
+            name: "small".to_string(),
+            properties: BTreeMap::from([("author".to_string(), "tests".to_string())]),
+        },
+        width: 4,
+        height: 3,
+        tile_size: 10,
+        tileset: BTreeMap::from([
+            (1, tile("wall", true)),
+            (2, tile("grass", false)),
+            (3, tile("exit", false)),
+        ]),
+        layers: vec![
+            MapLayer {
+                name: "ground".to_string(),
+                rows: vec![
+                    "1,1,3,1".to_string(),
+                    "2,2,2,2".to_string(),
+                    "2,2,2,2".to_string(),
+                ],
+            },
+            MapLayer {
+                name: "decoration".to_string(),
+                rows: vec![
+                    "0,0,0,0".to_string(),
+                    "0,2,0,0".to_string(),
+                    "0,0,0,0".to_string(),
+                ],
+            },
+        ],
+        spawns: vec![MapPoint { x: 1, y: 1 }, MapPoint { x: 2, y: 2 }],
+        enemies: vec![EnemySpawn {
+            kind: EnemyType::Slime,
+            x: 3,
+            y: 2,
+        }],
+        exits: vec![MapExit {
+            x: 2,
+            y: 0,
+            target: "resources/levels/level2.json".to_string(),
+        }],
+    }
+}
+
+// a fresh directory for files a test writes
+pub(super) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sea2d_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn maps_load_into_levels() {
+    let level = Level::from_map(&small_map()).unwrap();
+    assert_eq!(level.name, "small");
+    assert_eq!(level.tiles.len(), 2);
+    assert_eq!(level.tiles[0].len(), 12);
+    assert_eq!(level.tiles[1].len(), 1);
+
+    let tile = |x: i32, y: i32| {
+        level.tiles[0]
+            .values()
+            .find(|tile| tile.x == x * 10 && tile.y == y * 10)
+            .unwrap()
+    };
+    assert!(tile(0, 0).bounding_box.is_some());
+    assert!(tile(0, 1).bounding_box.is_none());
+    match &tile(2, 0).tile_type {
+        TileType::Exit(exit) => assert_eq!(exit.next_level, "resources/levels/level2.json"),
+        other => panic!("expected an exit, got {:?}", other),
+    }
+
+    assert_eq!(level.player_spawn, (10, 10));
+    assert_eq!(level.spawn_point(0), (10, 10));
+    assert_eq!(level.spawn_point(1), (20, 20));
+    assert_eq!(level.enemy_spawns, vec![(EnemyType::Slime, 30.0, 20.0)]);
+}
+
+#[test]
+fn maps_survive_saving() {
+    let path = scratch_dir("save").join("small.json");
+    let path = path.to_str().unwrap();
+    let map = small_map();
+    map.save(path).unwrap();
+    assert_eq!(MapFile::load(path).unwrap(), map);
+}
+
+#[test]
+fn broken_maps_are_rejected() {
+    let broken = |change: fn(&mut MapFile)| {
+        let mut map = small_map();
+        change(&mut map);
+        map.validate().is_err() && Level::from_map(&map).is_err()
+    };
+    assert!(small_map().validate().is_ok());
+    assert!(broken(|map| {
+        map.layers[0].rows.pop();
+    }));
+    assert!(broken(|map| map.layers[0].rows[1] = "2,2,2".to_string()));
+    assert!(broken(|map| map.layers[0].rows[1] = "2,x,2,2".to_string()));
+    assert!(broken(|map| map.layers[0].rows[1] = "2,9,2,2".to_string()));
+    assert!(broken(
+        |map| map.tileset.get_mut(&2).unwrap().tile = "lava".to_string()
+    ));
+    assert!(broken(|map| map.exits.clear()));
+    assert!(broken(|map| map.exits[0].x = 0));
+    assert!(broken(|map| map.spawns[0].x = 4));
+    assert!(broken(|map| map.tile_size = 0));
+}
+
+#[test]
+fn png_levels_are_converted() {
+    let dir = scratch_dir("convert");
+    let (wall, grass, spawn, exit) = (
+        Rgba([50, 47, 77, 255]),
+        Rgba([0, 255, 0, 0]),
+        Rgba([255, 0, 0, 255]),
+        Rgba([64, 58, 171, 255]),
+    );
+    let mut first = RgbaImage::from_pixel(3, 2, grass);
+    first.put_pixel(0, 0, wall);
+    first.put_pixel(1, 0, exit);
+    first.put_pixel(2, 1, spawn);
+    first.save(dir.join("test_1.png")).unwrap();
+    let mut second = RgbaImage::new(2, 1);
+    second.put_pixel(0, 0, exit);
+    second.save(dir.join("test_2.png")).unwrap();
+    std::fs::write(
+        dir.join("test_exits.txt"),
+        "levels/next_1.png\nlevels/other.json\n",
+    )
+    .unwrap();
+
+    let png = dir.join("test_1.png").to_str().unwrap().to_string();
+    let written = convert_levels(std::slice::from_ref(&png)).unwrap();
+    let json = dir.join("test.json").to_str().unwrap().to_string();
+    assert_eq!(written, vec![json.clone()]);
+
+    let map = MapFile::load(&json).unwrap();
+    assert_eq!(map.info.name, "test");
+    assert_eq!((map.width, map.height), (3, 2));
+    assert_eq!(map.layers.len(), 2);
+    assert_eq!(map.layers[1].rows, vec!["2,0,0", "0,0,0"]);
+    assert_eq!(map.spawns, vec![MapPoint { x: 2, y: 1 }]);
+    // exits take their targets in scan order, png targets point to the converted maps
+    assert_eq!(map.exits.len(), 2);
+    assert_eq!(map.exits[0].target, "levels/next.json");
+    assert_eq!(map.exits[1].target, "levels/other.json");
+    let solid: Vec<bool> = map.tileset.values().map(|tile| tile.solid).collect();
+    assert_eq!(solid, vec![true, true, false]);
+
+    // the png level still loads, exactly like its conversion
+    assert_eq!(
+        Level::load(&png).unwrap().to_data(),
+        Level::load(&json).unwrap().to_data()
+    );
+    assert!(Level::load(dir.join("missing_1.png").to_str().unwrap()).is_err());
+}
+
+#[test]
+fn shipped_png_levels_match_their_maps() {
+    // the sources the json maps were converted from
+    let pngs = legacy_levels("resources/levels");
+    assert!(!pngs.is_empty());
+    for png in pngs {
+        let json = png.replace("_1.png", ".json");
+        assert_eq!(
+            MapFile::from_png(&png).unwrap(),
+            MapFile::load(&json).unwrap(),
+            "{}",
+            png
+        );
+    }
+}
+
+#[test]
+fn worlds_spawn_the_map_enemies() {
+    let world = World::new("resources/levels/level1.json".to_string(), 0).unwrap();
+    assert_eq!(world.enemies.len(), 1);
+    assert_eq!(world.enemies[0].kind, EnemyType::Wizard);
+    assert_eq!((world.enemies[0].x, world.enemies[0].y), (960.0, 960.0));
+    assert!(
+        World::new_client("resources/levels/level1.json".to_string())
+            .unwrap()
+            .enemies
+            .is_empty()
+    );
+}
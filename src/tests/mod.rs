@@ -1 +1,15 @@
+mod chat_tests;
+mod discovery_tests;
+mod generator_tests;
+mod interpolation_tests;
+mod level_transfer_tests;
+mod map_tests;
 mod network_tests;
+mod overlapping_tests;
+mod prediction_tests;
+mod server_tests;
+mod simulation_tests;
+mod stats_tests;
+mod tiled_tests;
+mod transport_tests;
+mod wfc_tests;
@@ -1,51 +1,238 @@
+use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use crate::{
-    entities::animation_data::AnimationData,
+    entities::{
+        animation_data::{AnimationState, Facing},
+        enemy::EnemyType,
+    },
     networking::{
+        connection_packets::{
+            ConnectionPacket, Goodbye, GoodbyeReason, Hello, Ping, Pong, Reject, RejectReason,
+            Resume, ServerInfo,
+        },
         helpers::{
-            NetworkResult, deserialize_to_packet, prepend_size, serialize_and_send, try_read_tcp,
+            FrameDecoder, FrameError, deserialize_to_packet, frame_packet, prepend_size,
+            serialize_packet,
+        },
+        packet::{
+            ChatMessage, ClientID, DecodeError, PACKET_REGISTRY, PROTOCOL_VERSION, Packet,
+            PacketInternal, ServerPacket,
         },
-        packet::{ClientID, Packet},
         player_packets::{
-            PlayerAnimation, PlayerDisconnect, PlayerPacket, PlayerPosition, PlayerWelcome,
+            PlayerAnimation, PlayerDisconnect, PlayerInputCommand, PlayerLevel, PlayerList,
+            PlayerListEntry, PlayerPacket, PlayerPosition, PlayerRespawn, PlayerSpectate,
+            PlayerWelcome,
+        },
+        server::check_hello,
+        shared::MAX_FRAME_SIZE,
+        tcp::TcpServerTransport,
+        transport::ServerTransport,
+        world_packets::{
+            EnemyState, LevelChunk, LevelRequest, PlayerState, ProjectileState, WorldPacket,
+            WorldState,
         },
     },
+    simulation::input::PlayerInput,
 };
 
 #[test]
 fn prepend_size_test() {
     let mut v = vec![1, 2, 3, 4, 5];
-    prepend_size(&mut v);
+    prepend_size(&mut v).unwrap();
     assert_eq!(v, vec![5, 0, 1, 2, 3, 4, 5]);
 }
 
 #[test]
 fn prepend_size_test_large() {
     let mut v = vec![1; 65500];
-    prepend_size(&mut v);
+    prepend_size(&mut v).unwrap();
     assert_eq!(v.len(), 65500 + 2);
     assert_eq!(v[0], 220);
     assert_eq!(v[1], 255);
 }
 
+#[test]
+fn oversized_packets_are_not_framed() {
+    let mut v = vec![1; MAX_FRAME_SIZE];
+    prepend_size(&mut v).unwrap();
+
+    let mut v = vec![1; MAX_FRAME_SIZE + 1];
+    assert_eq!(
+        prepend_size(&mut v),
+        Err(FrameError::TooLarge {
+            size: MAX_FRAME_SIZE + 1,
+            limit: MAX_FRAME_SIZE
+        })
+    );
+    let huge = Packet::WorldPacket(WorldPacket::LevelChunkPacket(LevelChunk {
+        checksum: 1,
+        index: 0,
+        count: 1,
+        data: vec![0; 70000],
+    }));
+    assert!(frame_packet(huge).is_err());
+}
+
+// every frame that came out of the decoder until it needs more bytes
+fn decoded(decoder: &mut FrameDecoder) -> Result<Vec<Packet>, FrameError> {
+    let mut packets = Vec::new();
+    while let Some(frame) = decoder.next_frame()? {
+        packets.push(deserialize_to_packet(frame).unwrap());
+    }
+    Ok(packets)
+}
+
+#[test]
+fn frames_survive_any_split() {
+    let packets = sample_packets();
+    let bytes: Vec<u8> = packets
+        .iter()
+        .flat_map(|packet| frame_packet(packet.clone()).unwrap())
+        .collect();
+
+    // a byte at a time, also splitting the sizes
+    let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE);
+    let mut received = Vec::new();
+    for byte in &bytes {
+        decoder.push(&[*byte]);
+        received.extend(decoded(&mut decoder).unwrap());
+    }
+    assert_eq!(received, packets);
+
+    let mut rng = StdRng::seed_from_u64(17);
+    for _ in 0..200 {
+        let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE);
+        let mut received = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let (read, left) = rest.split_at(rng.random_range(1..=rest.len().min(64)));
+            decoder.push(read);
+            received.extend(decoded(&mut decoder).unwrap());
+            rest = left;
+        }
+        assert_eq!(received, packets);
+        assert_eq!(decoder.next_frame(), Ok(None));
+    }
+}
+
+#[test]
+fn oversized_frames_end_the_stream() {
+    let mut decoder = FrameDecoder::new(100);
+    decoder.push(
+        &frame_packet(Packet::ClientIDPacket(ClientID {
+            id: 1,
+            session: None,
+        }))
+        .unwrap(),
+    );
+    decoder.push(&[101, 0]);
+    let err = FrameError::TooLarge {
+        size: 101,
+        limit: 100,
+    };
+    // what came before is still fine
+    assert!(decoder.next_frame().unwrap().is_some());
+    assert_eq!(decoder.next_frame(), Err(err.clone()));
+    // there's no telling where the next frame would start
+    decoder.push(
+        &frame_packet(Packet::ClientIDPacket(ClientID {
+            id: 2,
+            session: None,
+        }))
+        .unwrap(),
+    );
+    assert_eq!(decoder.next_frame(), Err(err));
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..500 {
+        let mut decoder = FrameDecoder::new(4096);
+        let len = rng.random_range(0..2048);
+        let bytes: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+        decoder.push(&bytes);
+        while let Ok(Some(frame)) = decoder.next_frame() {
+            assert!(frame.len() <= 4096);
+            let _ = deserialize_to_packet(frame);
+        }
+    }
+}
+
+#[test]
+fn damaged_packets_never_panic() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for packet in sample_packets() {
+        let buf = serialize_packet(packet);
+        for _ in 0..200 {
+            let mut damaged = buf.clone();
+            for _ in 0..rng.random_range(1..4) {
+                let index = rng.random_range(0..damaged.len());
+                damaged[index] = rng.random();
+            }
+            // anything is fine, as long as it's an answer
+            let _ = deserialize_to_packet(damaged);
+        }
+    }
+}
+
+#[test]
+fn tcp_server_drops_clients_sending_huge_frames() {
+    let mut server = TcpServerTransport::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    client.write_all(&[255, 255, 1, 2, 3]).unwrap();
+
+    let mut events = Vec::new();
+    for _ in 0..100 {
+        server.wait(Duration::from_millis(20));
+        events.extend(server.poll());
+        if events
+            .iter()
+            .any(|event| matches!(event, ServerPacket::RemovePlayer(_)))
+        {
+            break;
+        }
+    }
+    assert!(matches!(
+        events[..],
+        [ServerPacket::AddPlayer(_), ServerPacket::RemovePlayer(_)]
+    ));
+    // and the connection is closed
+    let mut buf = [0; 8];
+    client
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    assert!(matches!(client.read(&mut buf), Ok(0) | Err(_)));
+}
+
 #[test]
 fn serialize_deserialize_test() {
     let server = TcpListener::bind("127.0.0.1:6000").unwrap();
     let mut client = TcpStream::connect("127.0.0.1:6000").unwrap();
 
     if let Ok((mut socket, _)) = server.accept() {
+        let mut reader = FrameDecoder::new(MAX_FRAME_SIZE);
         let mut test_packet = |packet: Packet| {
             // Send the packet
-            serialize_and_send(&mut client, packet.clone());
+            client
+                .write_all(&frame_packet(packet.clone()).unwrap())
+                .unwrap();
             // Read and deserialize the packet
-            match try_read_tcp(&mut socket) {
-                NetworkResult::Ok(buf) => {
-                    let deserialized_packet = deserialize_to_packet(buf);
-                    assert_eq!(deserialized_packet, Some(packet));
+            let buf = loop {
+                if let Some(frame) = reader.next_frame().unwrap() {
+                    break frame;
                 }
-                _ => panic!("Failed to read packet"),
-            }
+                let mut data = [0; 256];
+                let size = socket.read(&mut data).expect("Failed to read packet");
+                reader.push(&data[..size]);
+            };
+            let deserialized_packet = deserialize_to_packet(buf);
+            assert_eq!(deserialized_packet, Ok(packet));
         };
 
         test_packet(Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(
@@ -53,6 +240,8 @@ fn serialize_deserialize_test() {
                 x: 1.0,
                 y: 2.,
                 player_id: 0,
+                time: 0.0,
+                sequence: 0,
             },
         )));
         test_packet(Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(
@@ -60,6 +249,8 @@ fn serialize_deserialize_test() {
                 x: 3.,
                 y: 4.,
                 player_id: 1,
+                time: 0.0,
+                sequence: 0,
             },
         )));
         test_packet(Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(
@@ -67,18 +258,25 @@ fn serialize_deserialize_test() {
                 x: 5.,
                 y: 6.,
                 player_id: 2,
+                time: 0.0,
+                sequence: 0,
             },
         )));
         test_packet(Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(
             PlayerAnimation {
-                id: 0,
-                animation_data: AnimationData::new(),
+                player_id: 0,
+                state: AnimationState::Left,
+                frame: 3,
+                facing: Facing::Left,
             },
         )));
         test_packet(Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(
             PlayerDisconnect { id: 0 },
         )));
-        test_packet(Packet::ClientIDPacket(ClientID { id: 0 }));
+        test_packet(Packet::ClientIDPacket(ClientID {
+            id: 0,
+            session: None,
+        }));
         test_packet(Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(
             PlayerWelcome {
                 player_id: 0,
@@ -88,3 +286,227 @@ fn serialize_deserialize_test() {
         )));
     }
 }
+
+// one of every registered packet
+fn sample_packets() -> Vec<Packet> {
+    vec![
+        Packet::ClientIDPacket(ClientID {
+            id: 42,
+            session: Some(7),
+        }),
+        Packet::ConnectionPacket(ConnectionPacket::HelloPacket(Hello {
+            protocol_version: PROTOCOL_VERSION,
+        })),
+        Packet::ConnectionPacket(ConnectionPacket::RejectPacket(Reject {
+            reason: RejectReason::IncompatibleVersion { server_version: 7 },
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(PlayerWelcome {
+            player_id: 1,
+            x: 12.5,
+            y: -3.0,
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(PlayerPosition {
+            player_id: 1,
+            x: 1.0,
+            y: 2.0,
+            time: 3.5,
+            sequence: 99,
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect {
+            id: 1,
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerAnimationPacket(PlayerAnimation {
+            player_id: 1,
+            state: AnimationState::Idle,
+            frame: 0,
+            facing: Facing::Front,
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(PlayerLevel {
+            player_id: 1,
+            level: "resources/levels/level1.json".to_string(),
+            checksum: 99,
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerInputPacket(PlayerInputCommand {
+            player_id: 1,
+            sequence: 5,
+            input: PlayerInput {
+                move_x: -1,
+                move_y: 1,
+                shoot: Some(1.5),
+            },
+            earlier: vec![PlayerInput::default()],
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerRespawnPacket(PlayerRespawn {
+            player_id: 1,
+        })),
+        Packet::WorldPacket(WorldPacket::WorldStatePacket(WorldState {
+            level: "resources/levels/level1.json".to_string(),
+            time: 10.0,
+            players: vec![PlayerState {
+                player_id: 1,
+                health: 85,
+            }],
+            enemies: vec![EnemyState {
+                id: 0,
+                kind: EnemyType::Wizard,
+                x: 100.0,
+                y: 200.0,
+                health: 50,
+                animation: AnimationState::Idle,
+            }],
+            projectiles: vec![ProjectileState {
+                id: 3,
+                x: 5.0,
+                y: 6.0,
+                direction: 0.5,
+                fired_by_player: true,
+            }],
+        })),
+        Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(ServerInfo {
+            motd: "hello".to_string(),
+            players: 1,
+            max_players: 8,
+            seed: 42,
+        })),
+        Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(Goodbye {
+            reason: GoodbyeReason::ServerShutdown,
+        })),
+        Packet::ConnectionPacket(ConnectionPacket::ResumePacket(Resume {
+            protocol_version: PROTOCOL_VERSION,
+            session: 7,
+        })),
+        Packet::WorldPacket(WorldPacket::LevelRequestPacket(LevelRequest {
+            checksum: 99,
+        })),
+        Packet::WorldPacket(WorldPacket::LevelChunkPacket(LevelChunk {
+            checksum: 99,
+            index: 1,
+            count: 3,
+            data: vec![1, 2, 3],
+        })),
+        Packet::ChatPacket(ChatMessage {
+            sender: Some(1),
+            text: "hi".to_string(),
+        }),
+        Packet::ConnectionPacket(ConnectionPacket::PingPacket(Ping { sequence: 7 })),
+        Packet::ConnectionPacket(ConnectionPacket::PongPacket(Pong { sequence: 7 })),
+        Packet::PlayerPacket(PlayerPacket::PlayerSpectatePacket(PlayerSpectate {
+            level: "resources/levels/level1.json".to_string(),
+        })),
+        Packet::PlayerPacket(PlayerPacket::PlayerListPacket(PlayerList {
+            players: vec![
+                PlayerListEntry {
+                    player_id: 1,
+                    spectator: false,
+                },
+                PlayerListEntry {
+                    player_id: 2,
+                    spectator: true,
+                },
+            ],
+        })),
+    ]
+}
+
+#[test]
+fn every_registered_packet_round_trips() {
+    let packets = sample_packets();
+    let mut ids: Vec<u16> = packets
+        .iter()
+        .map(|packet| PacketInternal::encode(packet).id)
+        .collect();
+    ids.sort();
+    let registered: Vec<u16> = PACKET_REGISTRY.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, registered, "a registered packet is missing a sample");
+
+    for packet in packets {
+        let buf = serialize_packet(packet.clone());
+        assert_eq!(deserialize_to_packet(buf), Ok(packet));
+    }
+}
+
+#[test]
+fn packet_ids_are_stable() {
+    // changing these breaks every client out there
+    let ids: Vec<u16> = sample_packets()
+        .iter()
+        .map(|packet| PacketInternal::encode(packet).id)
+        .collect();
+    assert_eq!(
+        ids,
+        vec![
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20
+        ]
+    );
+}
+
+#[test]
+fn decode_errors_are_recoverable() {
+    let unknown = bincode::serialize(&PacketInternal {
+        id: 60000,
+        data: vec![1, 2, 3],
+    })
+    .unwrap();
+    assert_eq!(
+        deserialize_to_packet(unknown),
+        Err(DecodeError::UnknownPacket(60000))
+    );
+
+    // right id, wrong contents
+    let wrong = bincode::serialize(&PacketInternal {
+        id: 4,
+        data: vec![1],
+    })
+    .unwrap();
+    assert!(matches!(
+        deserialize_to_packet(wrong),
+        Err(DecodeError::Malformed(_))
+    ));
+
+    for packet in sample_packets() {
+        let buf = serialize_packet(packet);
+        for len in 0..buf.len() {
+            assert!(deserialize_to_packet(buf[..len].to_vec()).is_err());
+        }
+    }
+    assert!(deserialize_to_packet(vec![]).is_err());
+}
+
+#[test]
+fn incompatible_version_is_rejected() {
+    assert_eq!(
+        check_hello(
+            &Hello {
+                protocol_version: PROTOCOL_VERSION
+            },
+            0,
+            8
+        ),
+        Ok(())
+    );
+    let rejected = check_hello(
+        &Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+        },
+        0,
+        8,
+    )
+    .unwrap_err();
+    assert!(
+        rejected
+            .to_string()
+            .starts_with("incompatible server version")
+    );
+}
+
+#[test]
+fn full_server_rejects_players() {
+    let hello = Hello {
+        protocol_version: PROTOCOL_VERSION,
+    };
+    assert_eq!(check_hello(&hello, 7, 8), Ok(()));
+    assert_eq!(
+        check_hello(&hello, 8, 8),
+        Err(RejectReason::ServerFull { max_players: 8 })
+    );
+}
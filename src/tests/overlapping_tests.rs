@@ -0,0 +1,70 @@
+use image::{Rgba, RgbaImage};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::wfc::{OverlappingModel, Symmetry, WfcError};
+
+const EXAMPLE: &str = "resources/examples/level1.png";
+
+const GRASS: Rgba<u8> = Rgba([0, 255, 0, 0]);
+const WATER: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+fn example() -> RgbaImage {
+    ::image::open(EXAMPLE).unwrap().to_rgba8()
+}
+
+// grass with one pond in the corner
+fn pond() -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(4, 4, GRASS);
+    image.put_pixel(0, 0, WATER);
+    image
+}
+
+#[test]
+fn patterns_are_counted() {
+    let model = OverlappingModel::learn(&pond(), 2, Symmetry::None).unwrap();
+    assert_eq!(model.colours(), [WATER, GRASS]);
+    assert_eq!(
+        model.patterns(),
+        vec![vec![WATER, GRASS, GRASS, GRASS], vec![GRASS; 4]]
+    );
+    assert_eq!(model.weights(), [1.0, 8.0]);
+
+    // the pond turned into every corner, mirroring it doesn't add anything new
+    let model = OverlappingModel::learn(&pond(), 2, Symmetry::All).unwrap();
+    assert_eq!(model.patterns().len(), 5);
+    assert_eq!(model.weights(), [2.0, 2.0, 2.0, 2.0, 64.0]);
+    let model = OverlappingModel::learn(&pond(), 2, Symmetry::Mirror).unwrap();
+    assert_eq!(model.patterns().len(), 3);
+
+    assert_eq!(
+        OverlappingModel::learn(&pond(), 5, Symmetry::None).err(),
+        Some(WfcError::BadPatternSize(5))
+    );
+}
+
+#[test]
+fn new_images_are_made_of_the_example() {
+    let model = OverlappingModel::learn(&example(), 3, Symmetry::All).unwrap();
+    let image = model
+        .generate(24, 16, &mut StdRng::seed_from_u64(7))
+        .unwrap();
+    assert_eq!(image.dimensions(), (24, 16));
+    assert!(image.pixels().all(|pixel| model.colours().contains(pixel)));
+    // every 3x3 window of the new image is somewhere in the example
+    let learned = model.patterns();
+    let windows = OverlappingModel::learn(&image, 3, Symmetry::None).unwrap();
+    for window in windows.patterns() {
+        assert!(
+            learned.contains(&window),
+            "{:?} isn't in the example",
+            window
+        );
+    }
+
+    assert_eq!(
+        image,
+        model
+            .generate(24, 16, &mut StdRng::seed_from_u64(7))
+            .unwrap()
+    );
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    entities::player::Player,
+    networking::player_packets::{PlayerInputCommand, PlayerPosition},
+    simulation::{
+        TIME_STEP,
+        input::{InputBuffer, PlayerInput},
+        prediction::Prediction,
+        world::World,
+    },
+};
+
+const TEST_LEVEL: &str = "resources/levels/level1.json";
+const PLAYER_ID: u64 = 1;
+
+const RIGHT: PlayerInput = PlayerInput {
+    move_x: 1,
+    move_y: 0,
+    shoot: None,
+};
+
+// delivers everything a fixed number of steps after it was sent
+struct LatencyLink<T> {
+    delay: usize,
+    in_flight: VecDeque<(usize, T)>,
+}
+
+impl<T> LatencyLink<T> {
+    fn new(delay: usize) -> LatencyLink<T> {
+        LatencyLink {
+            delay,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    fn send(&mut self, now: usize, message: T) {
+        self.in_flight.push_back((now + self.delay, message));
+    }
+
+    fn receive(&mut self, now: usize) -> Vec<T> {
+        let mut arrived = Vec::new();
+        while self.in_flight.front().is_some_and(|(at, _)| *at <= now) {
+            arrived.push(self.in_flight.pop_front().unwrap().1);
+        }
+        arrived
+    }
+}
+
+// a server and a predicting client in one process, connected through a slow link
+struct Loopback {
+    server: World,
+    server_inputs: InputBuffer,
+    client: World,
+    prediction: Prediction,
+    to_server: LatencyLink<PlayerInputCommand>,
+    to_client: LatencyLink<PlayerPosition>,
+    now: usize,
+}
+
+impl Loopback {
+    fn new(delay: usize) -> Loopback {
+        let mut server = World::new(TEST_LEVEL.to_string(), 0).unwrap();
+        server.enemies.clear(); // keep the player alive
+        server.add_player(Player::new(PLAYER_ID));
+        let mut client = World::new_client(TEST_LEVEL.to_string()).unwrap();
+        client.add_player(Player::new(PLAYER_ID));
+
+        Loopback {
+            server,
+            server_inputs: InputBuffer::default(),
+            client,
+            prediction: Prediction::new(),
+            to_server: LatencyLink::new(delay),
+            to_client: LatencyLink::new(delay),
+            now: 0,
+        }
+    }
+
+    fn step(&mut self, input: PlayerInput) {
+        // client, like Game::run
+        let command = self.prediction.record(PLAYER_ID, input);
+        self.to_server.send(self.now, command);
+        self.client
+            .step(TIME_STEP, &HashMap::from([(PLAYER_ID, input)]));
+
+        // server, like server::tick
+        for command in self.to_server.receive(self.now) {
+            for (sequence, input) in command.inputs() {
+                self.server_inputs.push(sequence, input);
+            }
+        }
+        let inputs: HashMap<u64, PlayerInput> = self
+            .server_inputs
+            .next()
+            .map(|input| (PLAYER_ID, input))
+            .into_iter()
+            .collect();
+        self.server.step(TIME_STEP, &inputs);
+        let player = self.server.player(PLAYER_ID).unwrap();
+        self.to_client.send(
+            self.now,
+            PlayerPosition {
+                player_id: PLAYER_ID,
+                x: player.x,
+                y: player.y,
+                time: self.server.time,
+                sequence: self.server_inputs.last_sequence(),
+            },
+        );
+
+        // client receiving, like Game::handle_receive
+        for position in self.to_client.receive(self.now) {
+            self.prediction.reconcile(&mut self.client, &position);
+        }
+        self.now += 1;
+    }
+
+    fn client_position(&self) -> (f64, f64) {
+        let player = self.client.player(PLAYER_ID).unwrap();
+        (player.x, player.y)
+    }
+
+    fn server_position(&self) -> (f64, f64) {
+        let player = self.server.player(PLAYER_ID).unwrap();
+        (player.x, player.y)
+    }
+}
+
+fn assert_close(a: (f64, f64), b: (f64, f64)) {
+    assert!(
+        (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn client_moves_before_server_answers() {
+    let mut loopback = Loopback::new(6); // 100ms each way
+    let spawn = loopback.client_position();
+    loopback.step(RIGHT);
+    assert!(loopback.client_position().0 > spawn.0);
+    assert_close(loopback.server_position(), spawn);
+}
+
+#[test]
+fn prediction_agrees_with_server() {
+    let mut loopback = Loopback::new(6);
+    for _ in 0..30 {
+        loopback.step(RIGHT);
+    }
+    // the server is behind by the round trip, the client isn't
+    assert!(loopback.client_position().0 > loopback.server_position().0);
+
+    for _ in 0..20 {
+        loopback.step(PlayerInput::default());
+    }
+    assert_close(loopback.client_position(), loopback.server_position());
+}
+
+#[test]
+fn misprediction_is_corrected() {
+    let mut loopback = Loopback::new(3);
+    for _ in 0..10 {
+        loopback.step(RIGHT);
+    }
+    // something only the server knows about moves the player
+    let spawn = loopback.server.level.player_spawn;
+    loopback
+        .server
+        .player_mut(PLAYER_ID)
+        .unwrap()
+        .spawn_at(spawn.0, spawn.1 + 40);
+
+    for _ in 0..20 {
+        loopback.step(PlayerInput::default());
+    }
+    assert_close(loopback.client_position(), loopback.server_position());
+}
+
+#[test]
+fn input_buffer_acknowledges_in_order() {
+    let mut buffer = InputBuffer::default();
+    buffer.push(1, RIGHT);
+    buffer.push(2, RIGHT);
+    buffer.push(2, PlayerInput::default()); // duplicate
+    assert_eq!(buffer.next(), Some(RIGHT));
+    assert_eq!(buffer.last_sequence(), 1);
+    buffer.push(1, RIGHT); // already simulated
+    assert_eq!(buffer.next(), Some(RIGHT));
+    assert_eq!(buffer.next(), None);
+    assert_eq!(buffer.last_sequence(), 2);
+}
+
+#[test]
+fn input_buffer_holds_a_wake_at_low_tick_rates() {
+    // at 5 wakes a second a 60 fps client sends 12 inputs in between
+    let mut buffer = InputBuffer::for_tick_rate(5);
+    for sequence in 1..=24 {
+        buffer.push(sequence, RIGHT);
+    }
+    for _ in 0..24 {
+        assert_eq!(buffer.next(), Some(RIGHT));
+    }
+    assert_eq!(buffer.last_sequence(), 24);
+
+    // at the default rate the buffer stays short, old inputs go
+    let mut buffer = InputBuffer::for_tick_rate(60);
+    for sequence in 1..=24 {
+        buffer.push(sequence, RIGHT);
+    }
+    assert_eq!(buffer.next(), Some(RIGHT));
+    assert_eq!(buffer.last_sequence(), 17);
+}
+
+#[test]
+fn lost_inputs_arrive_with_the_next_one() {
+    let shot = PlayerInput {
+        shoot: Some(0.5),
+        ..RIGHT
+    };
+    let mut prediction = Prediction::new();
+    let mut buffer = InputBuffer::default();
+    // the first two packets get lost
+    prediction.record(PLAYER_ID, shot);
+    prediction.record(PLAYER_ID, RIGHT);
+    let command = prediction.record(PLAYER_ID, RIGHT);
+    assert_eq!(command.sequence, 3);
+    assert_eq!(command.earlier, [shot, RIGHT]);
+    for (sequence, input) in command.inputs() {
+        buffer.push(sequence, input);
+    }
+    assert_eq!(buffer.next(), Some(shot));
+    assert_eq!(buffer.next(), Some(RIGHT));
+    assert_eq!(buffer.next(), Some(RIGHT));
+    assert_eq!(buffer.last_sequence(), 3);
+
+    // acknowledged inputs aren't sent again
+    let position = PlayerPosition {
+        player_id: PLAYER_ID,
+        x: 0.0,
+        y: 0.0,
+        time: 0.0,
+        sequence: 3,
+    };
+    let mut world = World::new_client(TEST_LEVEL.to_string()).unwrap();
+    prediction.reconcile(&mut world, &position);
+    assert!(prediction.record(PLAYER_ID, RIGHT).earlier.is_empty());
+
+    // a sequence too small for its earlier inputs only has the newest one
+    let bogus = PlayerInputCommand {
+        player_id: PLAYER_ID,
+        sequence: 1,
+        input: RIGHT,
+        earlier: vec![shot; 3],
+    };
+    assert_eq!(bogus.inputs().collect::<Vec<_>>(), [(1, RIGHT)]);
+}
+
+#[test]
+fn players_stop_once_their_client_goes_quiet() {
+    let mut world = World::new(TEST_LEVEL.to_string(), 0).unwrap();
+    world.enemies.clear();
+    world.add_player(Player::new(PLAYER_ID));
+    let spawn = world.player(PLAYER_ID).unwrap().x;
+    let mut buffer = InputBuffer::default();
+    // the client pauses while holding a key, nothing is sent after that
+    buffer.push(1, RIGHT);
+    let mut step = |world: &mut World| {
+        let inputs: HashMap<u64, PlayerInput> = buffer
+            .next()
+            .map(|input| (PLAYER_ID, input))
+            .into_iter()
+            .collect();
+        world.step(TIME_STEP, &inputs);
+        world.player(PLAYER_ID).unwrap().x
+    };
+    // a few late inputs don't stop the player
+    let mut x = step(&mut world);
+    for _ in 0..5 {
+        let next = step(&mut world);
+        assert!(next > x);
+        x = next;
+    }
+    for _ in 0..30 {
+        x = step(&mut world);
+    }
+    assert!(x > spawn);
+    assert_eq!(step(&mut world), x);
+}
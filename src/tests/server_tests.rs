@@ -0,0 +1,778 @@
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::networking::{
+    connection_packets::{
+        ConnectionPacket, Goodbye, GoodbyeReason, Hello, Ping, Pong, Reject, RejectReason, Resume,
+    },
+    discovery::MAX_NAME_LENGTH,
+    level_transfer::LevelDownload,
+    packet::{ChatMessage, ClientID, PROTOCOL_VERSION, Packet, ServerInternal, ServerPacket},
+    player_packets::{
+        PlayerDisconnect, PlayerLevel, PlayerListEntry, PlayerPacket, PlayerRespawn, PlayerSpectate,
+    },
+    server::Server,
+    server_config::ServerConfig,
+    shared::player_name,
+    transport::{ServerTransport, TransportKind},
+    world_packets::{LevelRequest, WorldPacket},
+};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn server_config_defaults() {
+    let config = ServerConfig::from_args(&[]).unwrap();
+    assert_eq!(config, ServerConfig::default());
+    assert_eq!(config.address(), "0.0.0.0:6000");
+}
+
+#[test]
+fn server_config_from_args() {
+    let config = ServerConfig::from_args(&args(&[
+        "--bind",
+        "127.0.0.1",
+        "--port",
+        "7000",
+        "--max-players",
+        "2",
+        "--tick-rate",
+        "20",
+        "--name",
+        "friday game",
+        "--motd",
+        "hi there",
+        "--grace-period",
+        "10",
+        "--view-radius",
+        "800",
+        "--seed",
+        "42",
+        "--udp",
+    ]))
+    .unwrap();
+    assert_eq!(config.address(), "127.0.0.1:7000");
+    assert_eq!(config.max_players, 2);
+    assert_eq!(config.tick_rate, 20);
+    assert_eq!(config.name, "friday game");
+    assert_eq!(config.motd, "hi there");
+    assert_eq!(config.grace_period, 10);
+    assert_eq!(config.view_radius, Some(800.0));
+    assert_eq!(config.seed, Some(42));
+    assert_eq!(config.transport, TransportKind::Udp);
+}
+
+#[test]
+fn server_config_rejects_bad_args() {
+    assert!(ServerConfig::from_args(&args(&["--port"])).is_err());
+    assert!(ServerConfig::from_args(&args(&["--port", "lots"])).is_err());
+    assert!(ServerConfig::from_args(&args(&["--tick-rate", "0"])).is_err());
+    assert!(ServerConfig::from_args(&args(&["--max-players", "0"])).is_err());
+    assert!(ServerConfig::from_args(&args(&["--view-radius", "0"])).is_err());
+    assert!(ServerConfig::from_args(&args(&["--fast"])).is_err());
+    let long_name = "x".repeat(MAX_NAME_LENGTH + 1);
+    assert!(ServerConfig::from_args(&args(&["--name", &long_name])).is_err());
+    assert!(ServerConfig::from_args(&args(&["--name", &long_name[1..]])).is_ok());
+}
+
+// remembers everything the server did instead of talking to real clients
+#[derive(Default)]
+struct Recorded {
+    sent: Vec<(SocketAddr, Packet)>,
+    disconnected: Vec<SocketAddr>,
+}
+
+struct RecordingTransport(Rc<RefCell<Recorded>>);
+
+impl ServerTransport for RecordingTransport {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        Ok(address(6000))
+    }
+
+    fn poll(&mut self) -> Vec<ServerPacket> {
+        Vec::new()
+    }
+
+    fn send(&mut self, address: SocketAddr, packet: Packet) {
+        self.0.borrow_mut().sent.push((address, packet));
+    }
+
+    fn disconnect(&mut self, address: SocketAddr) {
+        self.0.borrow_mut().disconnected.push(address);
+    }
+}
+
+fn address(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+fn from(address: SocketAddr, packet: Packet) -> ServerPacket {
+    ServerPacket::ServerInternalPacket(ServerInternal { address, packet })
+}
+
+fn test_server(max_players: usize) -> (Server, Rc<RefCell<Recorded>>) {
+    test_server_with(ServerConfig {
+        max_players,
+        ..ServerConfig::default()
+    })
+}
+
+fn test_server_with(config: ServerConfig) -> (Server, Rc<RefCell<Recorded>>) {
+    let recorded = Rc::new(RefCell::new(Recorded::default()));
+    let server = Server::new(config, Box::new(RecordingTransport(recorded.clone())));
+    (server, recorded)
+}
+
+// the id the server sent to this address last
+fn received_id(recorded: &Rc<RefCell<Recorded>>, addr: SocketAddr) -> ClientID {
+    recorded
+        .borrow()
+        .sent
+        .iter()
+        .rev()
+        .find_map(|(to, packet)| match packet {
+            Packet::ClientIDPacket(id) if *to == addr => Some(id.clone()),
+            _ => None,
+        })
+        .expect("no id was sent")
+}
+
+// says hello (or resumes) and joins a level like the game does
+fn join_level(
+    server: &mut Server,
+    recorded: &Rc<RefCell<Recorded>>,
+    addr: SocketAddr,
+    session: Option<u64>,
+    level: &str,
+) -> ClientID {
+    server.handle(ServerPacket::AddPlayer(addr));
+    let hello = match session {
+        Some(session) => ConnectionPacket::ResumePacket(Resume {
+            protocol_version: PROTOCOL_VERSION,
+            session,
+        }),
+        None => ConnectionPacket::HelloPacket(Hello {
+            protocol_version: PROTOCOL_VERSION,
+        }),
+    };
+    server.handle(from(addr, Packet::ConnectionPacket(hello)));
+    let id = received_id(recorded, addr);
+    server.handle(from(
+        addr,
+        Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(PlayerLevel {
+            player_id: id.id,
+            level: level.to_string(),
+            checksum: 0,
+        })),
+    ));
+    id
+}
+
+fn join(server: &mut Server, recorded: &Rc<RefCell<Recorded>>, addr: SocketAddr) -> u64 {
+    join_level(server, recorded, addr, None, LEVEL).id
+}
+
+const LEVEL: &str = "resources/levels/level1.json";
+const OTHER_LEVEL: &str = "resources/levels/level2.json";
+
+#[test]
+fn lost_clients_are_forgotten() {
+    let (mut server, recorded) = test_server(8);
+    let (first, second) = (address(7001), address(7002));
+    let first_id = join(&mut server, &recorded, first);
+    join(&mut server, &recorded, second);
+    server.send_updates();
+    assert_eq!(server.player_count(), 2);
+    assert_eq!(server.world(LEVEL).unwrap().players.len(), 2);
+
+    server.handle(ServerPacket::RemovePlayer(first));
+    assert_eq!(server.player_count(), 1);
+    assert_eq!(server.world(LEVEL).unwrap().players.len(), 1);
+    // the one left behind is told about it
+    assert!(recorded.borrow().sent.contains(&(
+        second,
+        Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect {
+            id: first_id
+        }))
+    )));
+
+    // packets that were still on the way are ignored
+    server.handle(from(
+        first,
+        Packet::PlayerPacket(PlayerPacket::PlayerRespawnPacket(PlayerRespawn {
+            player_id: first_id,
+        })),
+    ));
+    server.handle(ServerPacket::RemovePlayer(second));
+    assert_eq!(server.player_count(), 0);
+    assert!(server.world(LEVEL).is_none(), "empty levels are unloaded");
+}
+
+#[test]
+fn freed_slots_can_be_reused() {
+    let (mut server, recorded) = test_server(1);
+    let (first, second) = (address(7001), address(7002));
+    join(&mut server, &recorded, first);
+
+    server.handle(ServerPacket::AddPlayer(second));
+    server.handle(from(
+        second,
+        Packet::ConnectionPacket(ConnectionPacket::HelloPacket(Hello {
+            protocol_version: PROTOCOL_VERSION,
+        })),
+    ));
+    assert!(matches!(
+        recorded.borrow().sent.last(),
+        Some((
+            _,
+            Packet::ConnectionPacket(ConnectionPacket::RejectPacket(_))
+        ))
+    ));
+
+    server.handle(ServerPacket::RemovePlayer(first));
+    join(&mut server, &recorded, second);
+    assert_eq!(server.player_count(), 1);
+}
+
+// the level name comes from the client, it can be anything
+#[test]
+fn unknown_levels_are_rejected() {
+    let (mut server, recorded) = test_server(8);
+    for (port, level) in [
+        (7001, "resources/levels/missing.json"),
+        (7002, "resources/levels/../../Cargo.toml"),
+        (7003, "/etc/passwd"),
+        (7004, "generated:nope"),
+    ] {
+        let addr = address(port);
+        join_level(&mut server, &recorded, addr, None, level);
+        assert_eq!(
+            recorded.borrow().sent.last(),
+            Some(&(
+                addr,
+                Packet::ConnectionPacket(ConnectionPacket::RejectPacket(Reject {
+                    reason: RejectReason::UnknownLevel {
+                        level: level.to_string()
+                    }
+                }))
+            ))
+        );
+        assert!(recorded.borrow().disconnected.contains(&addr));
+        assert!(server.world(level).is_none());
+    }
+    assert_eq!(server.player_count(), 0);
+    // the server is still fine
+    join(&mut server, &recorded, address(7005));
+    assert_eq!(server.world(LEVEL).unwrap().players.len(), 1);
+}
+
+#[test]
+fn goodbye_closes_the_connection() {
+    let (mut server, recorded) = test_server(8);
+    let addr = address(7001);
+    join(&mut server, &recorded, addr);
+
+    server.handle(from(
+        addr,
+        Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(Goodbye {
+            reason: GoodbyeReason::Quit,
+        })),
+    ));
+    assert_eq!(server.player_count(), 0);
+    assert_eq!(recorded.borrow().disconnected, vec![addr]);
+}
+
+#[test]
+fn shutdown_says_goodbye_to_everyone() {
+    let (mut server, recorded) = test_server(8);
+    let (first, second) = (address(7001), address(7002));
+    join(&mut server, &recorded, first);
+    join(&mut server, &recorded, second);
+
+    server.shutdown();
+    let recorded = recorded.borrow();
+    for addr in [first, second] {
+        assert!(recorded.sent.contains(&(
+            addr,
+            Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(Goodbye {
+                reason: GoodbyeReason::ServerShutdown,
+            }))
+        )));
+        assert!(recorded.disconnected.contains(&addr));
+    }
+    assert_eq!(server.player_count(), 0);
+}
+
+#[test]
+fn lost_players_can_resume() {
+    let (mut server, recorded) = test_server(8);
+    let (first, other) = (address(7001), address(7002));
+    join(&mut server, &recorded, other);
+    let id = join_level(&mut server, &recorded, first, None, OTHER_LEVEL);
+    let session = id.session.expect("no session token");
+    // the token is only for the player it belongs to
+    assert!(!recorded.borrow().sent.iter().any(|(to, packet)| {
+        *to == other && matches!(packet, Packet::ClientIDPacket(sent) if sent.id == id.id)
+    }));
+
+    let player = server
+        .world_mut(OTHER_LEVEL)
+        .unwrap()
+        .player_mut(id.id)
+        .unwrap();
+    player.set_position(300.0, 400.0);
+    player.health = 40;
+    server.handle(ServerPacket::RemovePlayer(first));
+    assert!(server.world(OTHER_LEVEL).is_none());
+
+    // back from another address, the game asks for the first level again
+    let resumed = join_level(&mut server, &recorded, address(7003), Some(session), LEVEL);
+    assert_eq!(resumed, id);
+    let player = server.world(OTHER_LEVEL).unwrap().player(id.id).unwrap();
+    assert_eq!((player.x, player.y, player.health), (300.0, 400.0, 40));
+    assert!(server.world(LEVEL).unwrap().player(id.id).is_none());
+    // and is told where it really is
+    assert!(recorded.borrow().sent.iter().any(|(to, packet)| {
+        *to == address(7003)
+            && matches!(packet, Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(level))
+                if level.player_id == id.id && level.level == OTHER_LEVEL)
+    }));
+}
+
+#[test]
+fn resuming_keeps_the_old_connection_until_accepted() {
+    let (mut server, recorded) = test_server(1);
+    let (first, second) = (address(7001), address(7002));
+    let id = join_level(&mut server, &recorded, first, None, LEVEL);
+
+    // a client that doesn't speak our protocol can't take the session over
+    server.handle(ServerPacket::AddPlayer(second));
+    server.handle(from(
+        second,
+        Packet::ConnectionPacket(ConnectionPacket::ResumePacket(Resume {
+            protocol_version: PROTOCOL_VERSION + 1,
+            session: id.session.unwrap(),
+        })),
+    ));
+    assert!(!recorded.borrow().disconnected.contains(&first));
+    assert!(server.world(LEVEL).unwrap().player(id.id).is_some());
+    assert_eq!(server.player_count(), 1);
+
+    // the old connection's slot goes to the new one, even on a full server
+    let resumed = join_level(&mut server, &recorded, second, id.session, LEVEL);
+    assert_eq!(resumed.id, id.id);
+    assert!(recorded.borrow().disconnected.contains(&first));
+    assert_eq!(server.player_count(), 1);
+}
+
+#[test]
+fn sessions_end_after_the_grace_period_or_a_goodbye() {
+    let (mut server, recorded) = test_server(8);
+    let id = join_level(&mut server, &recorded, address(7001), None, LEVEL);
+    server.handle(ServerPacket::RemovePlayer(address(7001)));
+    server.expire_sessions(Instant::now() + Duration::from_secs(31));
+    let fresh = join_level(&mut server, &recorded, address(7002), id.session, LEVEL);
+    assert_ne!(fresh.id, id.id);
+
+    server.handle(from(
+        address(7002),
+        Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(Goodbye {
+            reason: GoodbyeReason::Quit,
+        })),
+    ));
+    let again = join_level(&mut server, &recorded, address(7003), fresh.session, LEVEL);
+    assert_ne!(again.id, fresh.id);
+    assert_eq!(server.player_count(), 1);
+}
+
+#[test]
+fn levels_are_sent_on_request() {
+    let (mut server, recorded) = test_server(8);
+    let addr = address(7001);
+    let id = join(&mut server, &recorded, addr);
+    let checksum = recorded
+        .borrow()
+        .sent
+        .iter()
+        .find_map(|(_, packet)| match packet {
+            Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(level))
+                if level.player_id == id =>
+            {
+                Some(level.checksum)
+            }
+            _ => None,
+        })
+        .expect("no level was announced");
+
+    server.handle(from(
+        addr,
+        Packet::WorldPacket(WorldPacket::LevelRequestPacket(LevelRequest { checksum })),
+    ));
+    server.tick();
+    let mut download = LevelDownload::new(LEVEL.to_string(), checksum);
+    let mut level = None;
+    for (to, packet) in recorded.borrow().sent.iter() {
+        if let Packet::WorldPacket(WorldPacket::LevelChunkPacket(chunk)) = packet {
+            assert_eq!(*to, addr);
+            level = download.push(chunk.clone()).unwrap();
+        }
+    }
+    assert_eq!(level, Some(server.world(LEVEL).unwrap().level.to_data()));
+}
+
+fn chat_sent_to(recorded: &Rc<RefCell<Recorded>>, addr: SocketAddr) -> Vec<ChatMessage> {
+    recorded
+        .borrow()
+        .sent
+        .iter()
+        .filter_map(|(to, packet)| match packet {
+            Packet::ChatPacket(message) if *to == addr => Some(message.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn chat_is_relayed_with_the_sender() {
+    let (mut server, recorded) = test_server(8);
+    let (first, second) = (address(7001), address(7002));
+    let first_id = join(&mut server, &recorded, first);
+    join(&mut server, &recorded, second);
+    recorded.borrow_mut().sent.clear();
+
+    // clients can't pretend to be someone else
+    server.handle(from(
+        first,
+        Packet::ChatPacket(ChatMessage {
+            sender: None,
+            text: "  hello\n there ".to_string(),
+        }),
+    ));
+    server.handle(from(
+        first,
+        Packet::ChatPacket(ChatMessage {
+            sender: None,
+            text: " \t ".to_string(),
+        }),
+    ));
+    server.handle(from(
+        address(7003),
+        Packet::ChatPacket(ChatMessage {
+            sender: None,
+            text: "not joined".to_string(),
+        }),
+    ));
+    let expected = vec![ChatMessage {
+        sender: Some(first_id),
+        text: "hello there".to_string(),
+    }];
+    assert_eq!(chat_sent_to(&recorded, first), expected);
+    assert_eq!(chat_sent_to(&recorded, second), expected);
+}
+
+#[test]
+fn joins_and_leaves_are_announced() {
+    let (mut server, recorded) = test_server(8);
+    let (first, second) = (address(7001), address(7002));
+    join(&mut server, &recorded, first);
+    let second_id = join(&mut server, &recorded, second);
+    server.handle(ServerPacket::RemovePlayer(second));
+
+    let announced: Vec<String> = chat_sent_to(&recorded, first)
+        .into_iter()
+        .map(|message| {
+            assert_eq!(message.sender, None);
+            message.text
+        })
+        .collect();
+    let name = player_name(second_id);
+    assert_eq!(announced.len(), 3);
+    assert_eq!(announced[1], format!("{} joined", name));
+    assert_eq!(announced[2], format!("{} left (connection lost)", name));
+}
+
+// players this address was sent updates about since the last call
+fn heard_about(recorded: &Rc<RefCell<Recorded>>, addr: SocketAddr) -> Vec<u64> {
+    let mut ids: Vec<u64> = recorded
+        .borrow_mut()
+        .sent
+        .extract_if(.., |(to, _)| *to == addr)
+        .filter_map(|(_, packet)| match packet {
+            Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(position)) => {
+                Some(position.player_id)
+            }
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn welcomed(recorded: &Rc<RefCell<Recorded>>, addr: SocketAddr, id: u64) -> bool {
+    recorded.borrow().sent.iter().any(|(to, packet)| {
+        *to == addr
+            && matches!(packet, Packet::PlayerPacket(PlayerPacket::PlayerWelcomePacket(welcome))
+                if welcome.player_id == id)
+    })
+}
+
+#[test]
+fn announcement_reports_players_and_level() {
+    let (mut server, recorded) = test_server(8);
+    let announcement = server.announcement();
+    assert_eq!(announcement.players, 0);
+    assert_eq!(announcement.max_players, 8);
+    assert_eq!(announcement.level, None);
+
+    join_level(&mut server, &recorded, address(1), None, OTHER_LEVEL);
+    join(&mut server, &recorded, address(2));
+    join(&mut server, &recorded, address(3));
+    let announcement = server.announcement();
+    assert_eq!(announcement.players, 3);
+    assert_eq!(announcement.level.as_deref(), Some(LEVEL));
+    assert_eq!(announcement.protocol_version, PROTOCOL_VERSION);
+}
+
+#[test]
+fn players_only_hear_about_their_level() {
+    let (mut server, recorded) = test_server(8);
+    let (first, second, elsewhere) = (address(7001), address(7002), address(7003));
+    let first_id = join(&mut server, &recorded, first);
+    let second_id = join(&mut server, &recorded, second);
+    let elsewhere_id = join_level(&mut server, &recorded, elsewhere, None, OTHER_LEVEL).id;
+
+    server.send_updates();
+    assert!(welcomed(&recorded, first, second_id));
+    assert!(!welcomed(&recorded, elsewhere, first_id));
+    let mut both = vec![first_id, second_id];
+    both.sort();
+    assert_eq!(heard_about(&recorded, first), both);
+    assert_eq!(heard_about(&recorded, elsewhere), vec![elsewhere_id]);
+
+    // leaving is only news to the ones who could see the player
+    server.handle(ServerPacket::RemovePlayer(second));
+    let told = |addr| {
+        recorded.borrow().sent.iter().any(|(to, packet)| {
+            *to == addr
+                && matches!(
+                    packet,
+                    Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(_))
+                )
+        })
+    };
+    assert!(told(first));
+    assert!(!told(elsewhere));
+}
+
+fn move_to(server: &mut Server, id: u64, x: f64, y: f64) {
+    server
+        .world_mut(LEVEL)
+        .unwrap()
+        .player_mut(id)
+        .unwrap()
+        .set_position(x, y);
+}
+
+#[test]
+fn view_radius_limits_updates() {
+    let (mut server, recorded) = test_server_with(ServerConfig {
+        view_radius: Some(500.0),
+        ..ServerConfig::default()
+    });
+    let (first, second) = (address(7001), address(7002));
+    let first_id = join(&mut server, &recorded, first);
+    let second_id = join(&mut server, &recorded, second);
+    move_to(&mut server, first_id, 100.0, 100.0);
+    move_to(&mut server, second_id, 1000.0, 100.0);
+    server.send_updates();
+    assert_eq!(heard_about(&recorded, first), vec![first_id]);
+
+    move_to(&mut server, second_id, 400.0, 100.0);
+    server.send_updates();
+    assert!(welcomed(&recorded, first, second_id));
+    assert_eq!(heard_about(&recorded, first).len(), 2);
+
+    // walking away again removes the player on the client
+    move_to(&mut server, second_id, 1000.0, 100.0);
+    server.send_updates();
+    assert!(recorded.borrow().sent.contains(&(
+        first,
+        Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect {
+            id: second_id
+        }))
+    )));
+}
+
+#[test]
+fn pings_are_answered() {
+    let (mut server, recorded) = test_server(8);
+    let addr = address(7001);
+    join(&mut server, &recorded, addr);
+    server.handle(from(
+        addr,
+        Packet::ConnectionPacket(ConnectionPacket::PingPacket(Ping { sequence: 3 })),
+    ));
+    assert_eq!(
+        recorded.borrow().sent.last(),
+        Some(&(
+            addr,
+            Packet::ConnectionPacket(ConnectionPacket::PongPacket(Pong { sequence: 3 }))
+        ))
+    );
+}
+
+// says hello and only watches the level
+fn spectate(
+    server: &mut Server,
+    recorded: &Rc<RefCell<Recorded>>,
+    addr: SocketAddr,
+    level: &str,
+) -> u64 {
+    server.handle(ServerPacket::AddPlayer(addr));
+    server.handle(from(
+        addr,
+        Packet::ConnectionPacket(ConnectionPacket::HelloPacket(Hello {
+            protocol_version: PROTOCOL_VERSION,
+        })),
+    ));
+    let id = received_id(recorded, addr).id;
+    server.handle(from(
+        addr,
+        Packet::PlayerPacket(PlayerPacket::PlayerSpectatePacket(PlayerSpectate {
+            level: level.to_string(),
+        })),
+    ));
+    id
+}
+
+fn last_player_list(recorded: &Rc<RefCell<Recorded>>, addr: SocketAddr) -> Vec<PlayerListEntry> {
+    recorded
+        .borrow()
+        .sent
+        .iter()
+        .rev()
+        .find_map(|(to, packet)| match packet {
+            Packet::PlayerPacket(PlayerPacket::PlayerListPacket(list)) if *to == addr => {
+                Some(list.players.clone())
+            }
+            _ => None,
+        })
+        .expect("no player list was sent")
+}
+
+#[test]
+fn spectators_watch_without_a_player() {
+    let (mut server, recorded) = test_server_with(ServerConfig {
+        view_radius: Some(1.0),
+        ..ServerConfig::default()
+    });
+    let (player, watcher) = (address(7001), address(7002));
+    let player_id = join(&mut server, &recorded, player);
+    let watcher_id = spectate(&mut server, &recorded, watcher, LEVEL);
+
+    let world = server.world(LEVEL).unwrap();
+    assert!(world.player(watcher_id).is_none());
+    assert!(recorded.borrow().sent.iter().any(|(to, packet)| {
+        *to == watcher
+            && matches!(packet, Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(level))
+                if level.level == LEVEL && level.player_id == watcher_id)
+    }));
+
+    // the whole level is visible, even with a view radius
+    server.send_updates();
+    assert!(welcomed(&recorded, watcher, player_id));
+    assert_eq!(heard_about(&recorded, watcher), vec![player_id]);
+
+    let list = last_player_list(&recorded, player);
+    assert_eq!(list.len(), 2);
+    assert!(list.contains(&PlayerListEntry {
+        player_id,
+        spectator: false
+    }));
+    assert!(list.contains(&PlayerListEntry {
+        player_id: watcher_id,
+        spectator: true
+    }));
+
+    // the level stays loaded for the spectator alone
+    server.handle(ServerPacket::RemovePlayer(player));
+    server.tick();
+    assert!(server.world(LEVEL).is_some());
+    assert_eq!(last_player_list(&recorded, watcher).len(), 1);
+
+    server.handle(ServerPacket::RemovePlayer(watcher));
+    server.tick();
+    assert!(server.world(LEVEL).is_none());
+}
+
+#[test]
+fn spectating_unknown_levels_is_rejected() {
+    let (mut server, recorded) = test_server(8);
+    let addr = address(7001);
+    let level = "resources/levels/../../Cargo.toml";
+    spectate(&mut server, &recorded, addr, level);
+    assert_eq!(
+        recorded.borrow().sent.last(),
+        Some(&(
+            addr,
+            Packet::ConnectionPacket(ConnectionPacket::RejectPacket(Reject {
+                reason: RejectReason::UnknownLevel {
+                    level: level.to_string()
+                }
+            }))
+        ))
+    );
+    assert!(recorded.borrow().disconnected.contains(&addr));
+    assert!(server.world(level).is_none());
+}
+
+#[test]
+fn spectators_cannot_join_or_move() {
+    let (mut server, recorded) = test_server(8);
+    let watcher = address(7002);
+    let watcher_id = spectate(&mut server, &recorded, watcher, LEVEL);
+    server.handle(from(
+        watcher,
+        Packet::PlayerPacket(PlayerPacket::PlayerLevelPacket(PlayerLevel {
+            player_id: watcher_id,
+            level: OTHER_LEVEL.to_string(),
+            checksum: 0,
+        })),
+    ));
+    assert!(server.world(OTHER_LEVEL).is_none());
+    assert!(server.world(LEVEL).unwrap().player(watcher_id).is_none());
+    assert_eq!(server.player_count(), 1);
+}
+
+#[test]
+fn seeded_servers_hand_out_the_same_ids() {
+    let ids = |seed| {
+        let (mut server, recorded) = test_server_with(ServerConfig {
+            seed: Some(seed),
+            ..ServerConfig::default()
+        });
+        assert_eq!(server.seed(), seed);
+        let ids = [
+            join(&mut server, &recorded, address(5000)),
+            join(&mut server, &recorded, address(5001)),
+        ];
+        // clients are told the seed to show it
+        let told = recorded.borrow().sent.iter().any(|(to, packet)| {
+            *to == address(5000)
+                && matches!(
+                    packet,
+                    Packet::ConnectionPacket(ConnectionPacket::ServerInfoPacket(info))
+                        if info.seed == seed
+                )
+        });
+        assert!(told);
+        ids
+    };
+    assert_eq!(ids(3), ids(3));
+    assert_ne!(ids(3), ids(4));
+}
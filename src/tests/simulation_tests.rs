@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use crate::{
+    entities::{
+        animation_data::{AnimationState, Facing},
+        camera::CameraTarget,
+        enemy::EnemyType,
+        player::Player,
+    },
+    simulation::{TIME_STEP, input::PlayerInput, world::World},
+};
+
+const TEST_LEVEL: &str = "resources/levels/level1.json";
+
+fn world_with_player(id: u64) -> World {
+    let mut world = World::new(TEST_LEVEL.to_string(), 0).unwrap();
+    world.add_player(Player::new(id));
+    world
+}
+
+fn run(world: &mut World, id: u64, input: PlayerInput, steps: usize) {
+    let inputs = HashMap::from([(id, input)]);
+    for _ in 0..steps {
+        world.step(TIME_STEP, &inputs);
+    }
+}
+
+#[test]
+fn headless_world_spawns_player() {
+    let world = world_with_player(1);
+    let player = world.player(1).unwrap();
+    assert_eq!(player.x, world.level.player_spawn.0 as f64);
+    assert_eq!(player.y, world.level.player_spawn.1 as f64);
+    assert_eq!(player.current_level, TEST_LEVEL);
+}
+
+#[test]
+fn headless_world_moves_player() {
+    let mut world = world_with_player(1);
+    let start_y = world.player(1).unwrap().y;
+    let input = PlayerInput {
+        move_y: 1,
+        ..Default::default()
+    };
+    run(&mut world, 1, input, 10);
+    assert!(world.player(1).unwrap().y != start_y);
+    assert!((world.time - 10.0 * TIME_STEP).abs() < 1e-9);
+}
+
+#[test]
+fn headless_world_is_deterministic() {
+    let mut first = world_with_player(1);
+    let mut second = world_with_player(1);
+    let input = PlayerInput {
+        move_x: 1,
+        move_y: -1,
+        shoot: None,
+    };
+    run(&mut first, 1, input, 120);
+    run(&mut second, 1, input, 120);
+    let (a, b) = (first.player(1).unwrap(), second.player(1).unwrap());
+    assert_eq!((a.x, a.y), (b.x, b.y));
+}
+
+#[test]
+fn shooting_respects_cooldown() {
+    let mut world = world_with_player(1);
+    world.enemies.clear();
+    let input = PlayerInput {
+        shoot: Some(0.0),
+        ..Default::default()
+    };
+    run(&mut world, 1, input, 1);
+    assert_eq!(world.projectiles.len(), 1);
+    // still on cooldown, the first projectile may already have hit a wall
+    run(&mut world, 1, input, 1);
+    assert!(world.projectiles.len() <= 1);
+}
+
+#[test]
+fn client_world_follows_server_snapshot() {
+    let mut server = world_with_player(1);
+    let shot = PlayerInput {
+        shoot: Some(0.0),
+        ..PlayerInput::default()
+    };
+    run(&mut server, 1, shot, 1);
+
+    let mut client = World::new_client(TEST_LEVEL.to_string()).unwrap();
+    client.add_player(Player::new(1));
+    assert!(client.enemies.is_empty());
+
+    // the first snapshot creates the enemies, later ones reuse them
+    assert!(client.apply_snapshot(&server.snapshot()));
+    assert!(!client.apply_snapshot(&server.snapshot()));
+    assert_eq!(client.snapshot().enemies, server.snapshot().enemies);
+    assert_eq!(client.snapshot().projectiles, server.snapshot().projectiles);
+    // projectiles are numbered apart from the enemies, so clients can follow them
+    let projectile = server.projectiles[0].id;
+    assert_ne!(projectile, 0);
+    assert!(server.enemies.iter().all(|enemy| enemy.id != projectile));
+
+    // a client world never fires on its own
+    run(&mut client, 1, shot, 1);
+    assert_eq!(client.projectiles.len(), server.projectiles.len());
+}
+
+// a stonewalker too far away to see the player wanders around at random
+fn wandering_enemy(seed: u64) -> Vec<(f64, f64)> {
+    let mut world = World::new(TEST_LEVEL.to_string(), seed).unwrap();
+    world.enemies.clear();
+    world.add_player(Player::new(1));
+    world.spawn_enemy(EnemyType::Stonewalker, 120.0, 120.0);
+    (0..300)
+        .map(|_| {
+            run(&mut world, 1, PlayerInput::default(), 1);
+            (world.enemies[0].x, world.enemies[0].y)
+        })
+        .collect()
+}
+
+#[test]
+fn seeded_worlds_play_out_the_same() {
+    let path = wandering_enemy(7);
+    assert_eq!(path, wandering_enemy(7));
+    assert_ne!(path, wandering_enemy(8));
+    assert!(path.iter().any(|position| *position != (120.0, 120.0)));
+}
+
+#[test]
+fn remote_players_copy_the_server_animation() {
+    let mut world = World::new(TEST_LEVEL.to_string(), 0).unwrap();
+    let mut player = Player::new(1);
+    player.load_player_texture();
+    world.add_player(player);
+    let left = PlayerInput {
+        move_x: -1,
+        ..Default::default()
+    };
+    run(&mut world, 1, left, 20);
+    run(&mut world, 1, PlayerInput::default(), 5);
+
+    // standing still keeps the last direction
+    let animation = &world.player(1).unwrap().animation_data;
+    assert_eq!(animation.current_animation, AnimationState::Left);
+    assert_eq!(animation.facing, Facing::Left);
+    assert!(animation.frame() > 0);
+
+    let mut remote = Player::new(1);
+    remote.load_player_texture();
+    remote.animation_data.apply(
+        animation.current_animation.clone(),
+        animation.frame(),
+        animation.facing.clone(),
+    );
+    assert_eq!(
+        remote.animation_data.current_animation,
+        animation.current_animation
+    );
+    assert_eq!(remote.animation_data.frame(), animation.frame());
+    assert_eq!(remote.animation_data.facing, Facing::Left);
+
+    // a frame the client doesn't have shows the last one
+    remote
+        .animation_data
+        .apply(AnimationState::Idle, 99, Facing::Left);
+    assert_eq!(remote.animation_data.frame(), 5);
+}
+
+#[test]
+fn spectator_camera_cycles_through_players() {
+    let players = [30, 10, 20];
+    let mut target = CameraTarget::Free;
+    let mut followed = Vec::new();
+    for _ in 0..4 {
+        target = target.next(&players);
+        followed.push(target);
+    }
+    assert_eq!(
+        followed,
+        vec![
+            CameraTarget::Player(10),
+            CameraTarget::Player(20),
+            CameraTarget::Player(30),
+            CameraTarget::Free,
+        ]
+    );
+    // nobody to follow
+    assert_eq!(CameraTarget::Player(10).next(&[]), CameraTarget::Free);
+}
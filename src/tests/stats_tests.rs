@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use crate::networking::{
+    connection_packets::{ConnectionPacket, Pong},
+    packet::Packet,
+    stats::{PING_INTERVAL, Pinger, Traffic, packet_size},
+};
+
+#[test]
+fn pings_measure_the_round_trip() {
+    let start = Instant::now();
+    let mut pinger = Pinger::default();
+    let Some(Packet::ConnectionPacket(ConnectionPacket::PingPacket(first))) = pinger.ping(start)
+    else {
+        panic!("no ping was sent");
+    };
+    assert!(pinger.ping(start + PING_INTERVAL / 2).is_none());
+    let Some(Packet::ConnectionPacket(ConnectionPacket::PingPacket(second))) =
+        pinger.ping(start + PING_INTERVAL)
+    else {
+        panic!("no second ping was sent");
+    };
+    assert_ne!(first.sequence, second.sequence);
+
+    // answers can come late and out of order, but only count once
+    let pong = |sequence| Pong { sequence };
+    let now = start + PING_INTERVAL + Duration::from_millis(80);
+    assert_eq!(
+        pinger.pong(&pong(second.sequence), now),
+        Some(Duration::from_millis(80))
+    );
+    assert_eq!(
+        pinger.pong(&pong(first.sequence), now),
+        Some(PING_INTERVAL + Duration::from_millis(80))
+    );
+    assert_eq!(pinger.pong(&pong(first.sequence), now), None);
+    assert_eq!(pinger.pong(&pong(12345), now), None);
+}
+
+#[test]
+fn traffic_is_counted_per_second() {
+    let start = Instant::now();
+    let packet = Packet::ConnectionPacket(ConnectionPacket::PongPacket(Pong { sequence: 1 }));
+    let mut traffic = Traffic::default();
+    for i in 0..30 {
+        traffic.count(&packet, start + Duration::from_millis(i * 10));
+    }
+    assert_eq!(traffic.packets_per_second, 0, "not a whole second yet");
+
+    traffic.update(start + Duration::from_secs(1));
+    assert_eq!(traffic.packets_per_second, 30);
+    assert_eq!(traffic.bytes_per_second, 30 * packet_size(&packet) as u32);
+
+    // quiet for a while
+    traffic.update(start + Duration::from_secs(3));
+    assert_eq!(traffic.packets_per_second, 0);
+}
@@ -0,0 +1,201 @@
+use std::path::Path;
+
+use super::map_tests::scratch_dir;
+use crate::entities::enemy::EnemyType;
+use crate::environment::{
+    level::Level,
+    map::{EnemySpawn, MapExit, MapPoint, convert_levels},
+    tile_type::TileType,
+    tiled::{load_tiled, parse_tmx},
+};
+
+const FIXTURES: &str = "src/tests/fixtures/tiled";
+
+fn fixture(name: &str) -> String {
+    format!("{}/{}", FIXTURES, name)
+}
+
+#[test]
+fn tmx_and_tmj_maps_agree() {
+    let tmx = load_tiled(&fixture("room.tmx")).unwrap();
+    let tmj = load_tiled(&fixture("room.tmj")).unwrap();
+    assert_eq!(tmx, tmj);
+    assert_eq!(
+        Level::load(&fixture("room.tmx")).unwrap().to_data(),
+        Level::load(&fixture("room.tmj")).unwrap().to_data()
+    );
+}
+
+#[test]
+fn tiled_rooms_become_levels() {
+    let map = load_tiled(&fixture("room.tmx")).unwrap();
+    assert_eq!(map.info.name, "Tiled room");
+    assert_eq!(map.info.properties["author"], "tests");
+    let layers: Vec<&str> = map.layers.iter().map(|layer| layer.name.as_str()).collect();
+    assert_eq!(layers, vec!["ground", "rocks", "exits"]);
+    assert_eq!(map.spawns, vec![MapPoint { x: 1, y: 1 }]);
+    assert_eq!(
+        map.enemies,
+        vec![EnemySpawn {
+            kind: EnemyType::Slime,
+            x: 3,
+            y: 2
+        }]
+    );
+    // the note is left out
+    assert_eq!(
+        map.exits,
+        vec![
+            MapExit {
+                x: 2,
+                y: 0,
+                target: "resources/levels/level2.json".to_string()
+            },
+            MapExit {
+                x: 4,
+                y: 3,
+                target: "resources/levels/level1.json".to_string()
+            },
+        ]
+    );
+
+    let level = Level::from_map(&map).unwrap();
+    assert_eq!(level.tile_size, 60);
+    assert_eq!(level.player_spawn, (60, 60));
+    let tile = |layer: usize, x: i32, y: i32| {
+        level.tiles[layer]
+            .values()
+            .find(|tile| tile.x == x * 60 && tile.y == y * 60)
+            .unwrap()
+    };
+    // collision comes from the solid property
+    assert_eq!(tile(0, 0, 0).tile_type, TileType::Wall);
+    assert!(tile(0, 0, 0).bounding_box.is_some());
+    assert_eq!(tile(0, 0, 1).tile_type, TileType::Grass);
+    assert!(tile(0, 0, 1).bounding_box.is_none());
+    assert!(tile(0, 1, 2).bounding_box.is_some());
+    // a flipped tile with its type in a property
+    assert_eq!(tile(1, 3, 1).tile_type, TileType::Rock);
+    assert!(tile(1, 3, 1).bounding_box.is_some());
+    for (layer, x, y, target) in [
+        (0, 2, 0, "resources/levels/level2.json"),
+        (2, 4, 3, "resources/levels/level1.json"),
+    ] {
+        match &tile(layer, x, y).tile_type {
+            TileType::Exit(exit) => assert_eq!(exit.next_level, target),
+            other => panic!("expected an exit, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn tiled_caves_use_several_tilesets() {
+    let map = load_tiled(&fixture("cave.tmx")).unwrap();
+    assert_eq!(map.info.name, "cave");
+    assert_eq!(map.tile_size, 32);
+    let tiles: Vec<(&str, bool)> = map
+        .tileset
+        .values()
+        .map(|tile| (tile.tile.as_str(), tile.solid))
+        .collect();
+    assert_eq!(
+        tiles,
+        vec![("sand", false), ("stone", false), ("tree", true)]
+    );
+    assert_eq!(map.layers[0].rows, vec!["1,2,0", "3,1,1"]);
+    assert_eq!(map.spawns, vec![MapPoint { x: 1, y: 0 }]);
+    // tile objects hang up from their bottom corner
+    assert_eq!(
+        map.enemies,
+        vec![EnemySpawn {
+            kind: EnemyType::Skull,
+            x: 2,
+            y: 1
+        }]
+    );
+}
+
+#[test]
+fn tiled_maps_are_converted() {
+    let dir = scratch_dir("tiled");
+    for name in ["room.tmx", "tiles.tsx"] {
+        std::fs::copy(fixture(name), dir.join(name)).unwrap();
+    }
+    let tmx = dir.join("room.tmx").to_str().unwrap().to_string();
+    let written = convert_levels(std::slice::from_ref(&tmx)).unwrap();
+    assert_eq!(written, vec![dir.join("room.json").to_str().unwrap()]);
+    assert_eq!(
+        Level::load(&written[0]).unwrap().to_data(),
+        Level::load(&tmx).unwrap().to_data()
+    );
+}
+
+// a one tile map with something swapped in
+fn tmx(map: &str, tileset: &str, data: &str, objects: &str) -> String {
+    format!(
+        r#"<map width="1" height="1" tilewidth="60" tileheight="60" {}>
+ <tileset firstgid="1" name="t" tilewidth="60" tileheight="60">{}</tileset>
+ <layer name="ground" width="1" height="1">{}</layer>
+ <objectgroup name="objects">{}</objectgroup>
+</map>"#,
+        map, tileset, data, objects
+    )
+}
+
+#[test]
+fn unusable_tiled_maps_are_rejected() {
+    let tileset = r#"<tile id="0" type="grass"/>"#;
+    let data = r#"<data encoding="csv">1</data>"#;
+    let parse = |text: String| parse_tmx(&text, Path::new(FIXTURES), "test");
+    assert!(parse(tmx("", tileset, data, "")).is_ok());
+
+    let failures = [
+        tmx(r#"infinite="1""#, tileset, data, ""),
+        tmx("", r#"<tile id="0" type="lava"/>"#, data, ""),
+        tmx("", r#"<tile id="0"/>"#, data, ""),
+        tmx("", tileset, r#"<data encoding="csv">2</data>"#, ""),
+        tmx(
+            "",
+            tileset,
+            r#"<data encoding="base64">AQAAAA==</data>"#,
+            "",
+        ),
+        tmx("", tileset, r#"<data encoding="csv">1,1</data>"#, ""),
+        tmx("", tileset, data, r#"<object type="exit" x="10" y="10"/>"#),
+        tmx(
+            "",
+            tileset,
+            data,
+            r#"<object type="enemy" name="dragon" x="10" y="10"/>"#,
+        ),
+        tmx("", tileset, data, r#"<object name="spawn" x="70" y="10"/>"#),
+        tmx(
+            "",
+            r#"<tile id="0" type="grass"/></tileset><tileset firstgid="2" source="missing.tsx">"#,
+            data,
+            "",
+        ),
+        r#"<map width="1" height="1" tilewidth="60" tileheight="30"/>"#.to_string(),
+        "<map".to_string(),
+    ];
+    for (index, text) in failures.into_iter().enumerate() {
+        assert!(parse(text).is_err(), "map {} was accepted", index);
+    }
+}
+
+#[test]
+fn empty_tiled_maps_are_rejected() {
+    let parse = |size: &str| {
+        let text = format!(
+            r#"<map {} tilewidth="60" tileheight="60">
+ <tileset firstgid="1" name="t" tilewidth="60" tileheight="60"><tile id="0" type="grass"/></tileset>
+ <layer name="ground"><data encoding="csv"></data></layer>
+</map>"#,
+            size
+        );
+        parse_tmx(&text, Path::new(FIXTURES), "test")
+    };
+    for size in [r#"width="0" height="1""#, r#"width="1" height="0""#] {
+        assert_eq!(parse(size).err(), Some("the map has no tiles".to_string()));
+    }
+}
@@ -0,0 +1,535 @@
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::networking::{
+    connection_packets::{ConnectionPacket, Goodbye, GoodbyeReason},
+    helpers::frame_packet,
+    packet::{ClientID, Packet, ServerInternal, ServerPacket},
+    player_packets::{PlayerDisconnect, PlayerPacket, PlayerPosition},
+    simulated::{NetworkConditions, SimulatedClientTransport},
+    tcp::{TcpClientTransport, TcpServerTransport},
+    transport::{ClientTransport, ServerTransport, TransportError},
+    udp::{
+        Connection, Datagram, HEARTBEAT_INTERVAL, RELIABLE_WINDOW, RESEND_INTERVAL, TIMEOUT,
+        UdpClientTransport, UdpServerTransport,
+    },
+    world_packets::{LevelChunk, WorldPacket},
+};
+
+fn position(x: f64) -> Packet {
+    Packet::PlayerPacket(PlayerPacket::PlayerPositionPacket(PlayerPosition {
+        player_id: 1,
+        x,
+        y: 0.0,
+        time: 0.0,
+        sequence: 0,
+    }))
+}
+
+fn reliable(id: u64) -> Packet {
+    Packet::ClientIDPacket(ClientID { id, session: None })
+}
+
+// delivers datagrams from one connection to the other, returns what arrived
+fn deliver(to: &mut Connection, datagrams: Vec<Datagram>, now: Instant) -> Vec<Packet> {
+    let mut packets = Vec::new();
+    for datagram in datagrams {
+        packets.extend(to.receive(datagram, now).0);
+    }
+    packets
+}
+
+#[test]
+fn reliable_channel_survives_loss_and_reordering() {
+    let now = Instant::now();
+    let mut sender = Connection::new(now);
+    let mut receiver = Connection::new(now);
+
+    let sent: Vec<Datagram> = (0..10).map(|id| sender.send(reliable(id), now)).collect();
+    // every other datagram is lost, the rest arrives backwards
+    let arrived: Vec<Datagram> = sent.into_iter().skip(1).step_by(2).rev().collect();
+    let mut received = Vec::new();
+    for datagram in arrived {
+        let (packets, acks) = receiver.receive(datagram, now);
+        received.extend(packets);
+        deliver(&mut sender, acks, now);
+    }
+    assert!(received.is_empty()); // the first one is still missing
+
+    let later = now + RESEND_INTERVAL;
+    let resent = sender.update(later);
+    assert_eq!(resent.len(), 5);
+    for datagram in resent {
+        let (packets, acks) = receiver.receive(datagram, later);
+        received.extend(packets);
+        deliver(&mut sender, acks, later);
+    }
+    assert_eq!(received, (0..10).map(reliable).collect::<Vec<_>>());
+
+    // everything was acked, only heartbeats from now on
+    assert_eq!(
+        sender.update(later + HEARTBEAT_INTERVAL),
+        vec![Datagram::Heartbeat]
+    );
+}
+
+#[test]
+fn unreliable_channel_drops_old_packets() {
+    let now = Instant::now();
+    let mut sender = Connection::new(now);
+    let mut receiver = Connection::new(now);
+
+    let old = sender.send(position(1.0), now);
+    let new = sender.send(position(2.0), now);
+    assert_eq!(
+        deliver(&mut receiver, vec![new, old], now),
+        vec![position(2.0)]
+    );
+    // unreliable packets are never resent
+    assert!(sender.update(now + RESEND_INTERVAL).is_empty());
+}
+
+#[test]
+fn reliable_packets_far_ahead_are_dropped() {
+    let now = Instant::now();
+    let mut receiver = Connection::new(now);
+    let payload = Datagram::Reliable {
+        sequence: 0,
+        payload: Vec::new(),
+    };
+    // not even acked, so nothing is kept for them
+    for sequence in [RELIABLE_WINDOW, u32::MAX] {
+        let far = Datagram::Reliable {
+            sequence,
+            payload: Vec::new(),
+        };
+        assert_eq!(receiver.receive(far, now), (vec![], vec![]));
+    }
+    assert_eq!(
+        receiver.receive(payload, now).1,
+        vec![Datagram::Ack { sequence: 0 }]
+    );
+    let last = Datagram::Reliable {
+        sequence: RELIABLE_WINDOW,
+        payload: Vec::new(),
+    };
+    assert_eq!(
+        receiver.receive(last, now).1,
+        vec![Datagram::Ack {
+            sequence: RELIABLE_WINDOW
+        }]
+    );
+}
+
+#[test]
+fn unacked_packets_stall_the_connection() {
+    let now = Instant::now();
+    // acks stop coming, but the peer is still there
+    let mut connection = Connection::new(now);
+    connection.send(reliable(1), now);
+    let later = now + TIMEOUT;
+    connection.receive(Datagram::Heartbeat, later);
+    assert!(!connection.timed_out(later));
+    assert!(!connection.stalled(later - RESEND_INTERVAL));
+    assert!(connection.stalled(later));
+
+    // too much waiting for acks
+    let mut connection = Connection::new(now);
+    for id in 0..RELIABLE_WINDOW as u64 - 1 {
+        connection.send(reliable(id), now);
+    }
+    assert!(!connection.stalled(now));
+    connection.send(reliable(0), now);
+    assert!(connection.stalled(now));
+}
+
+#[test]
+fn connection_times_out() {
+    let now = Instant::now();
+    let mut connection = Connection::new(now);
+    assert!(!connection.timed_out(now + TIMEOUT / 2));
+    connection.receive(Datagram::Heartbeat, now + TIMEOUT / 2);
+    assert!(!connection.timed_out(now + TIMEOUT));
+    assert!(connection.timed_out(now + TIMEOUT / 2 + TIMEOUT));
+}
+
+// polls both ends until the client got something or we give up
+fn poll_until<C: ClientTransport, S: ServerTransport>(
+    client: &mut C,
+    server: &mut S,
+    events: &mut Vec<ServerPacket>,
+) -> Vec<Packet> {
+    for _ in 0..200 {
+        events.extend(server.poll());
+        let packets = client.poll().unwrap();
+        if !packets.is_empty() {
+            return packets;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    panic!("Nothing arrived");
+}
+
+fn added_address(events: &[ServerPacket]) -> std::net::SocketAddr {
+    events
+        .iter()
+        .find_map(|event| match event {
+            ServerPacket::AddPlayer(addr) => Some(*addr),
+            _ => None,
+        })
+        .expect("Client never connected")
+}
+
+fn check_loopback<C: ClientTransport, S: ServerTransport>(client: &mut C, server: &mut S) {
+    let mut events = Vec::new();
+    let disconnect = Packet::PlayerPacket(PlayerPacket::PlayerDisconnectPacket(PlayerDisconnect {
+        id: 3,
+    }));
+    // sent before the connection is up
+    client.send(disconnect.clone()).unwrap();
+
+    // the server can only answer once it knows about the client
+    while !events
+        .iter()
+        .any(|event| matches!(event, ServerPacket::AddPlayer(_)))
+    {
+        events.extend(server.poll());
+        client.poll().unwrap();
+    }
+    let addr = added_address(&events);
+    server.send(addr, reliable(7));
+    assert_eq!(poll_until(client, server, &mut events), vec![reliable(7)]);
+
+    server.send(addr, position(4.0));
+    assert_eq!(poll_until(client, server, &mut events), vec![position(4.0)]);
+
+    // the packet might still be on its way, give it some time
+    for _ in 0..200 {
+        if events
+            .iter()
+            .any(|event| matches!(event, ServerPacket::ServerInternalPacket(_)))
+        {
+            break;
+        }
+        events.extend(server.poll());
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let received: Vec<Packet> = events
+        .into_iter()
+        .filter_map(|event| match event {
+            ServerPacket::ServerInternalPacket(internal) => Some(internal.packet),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(received, vec![disconnect]);
+}
+
+#[test]
+fn udp_transport_on_loopback() {
+    let mut server = UdpServerTransport::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    let mut client = UdpClientTransport::connect(&address).unwrap();
+    check_loopback(&mut client, &mut server);
+    assert!(client.is_connected());
+
+    // closing the client tells the server right away
+    drop(client);
+    let mut removed = false;
+    for _ in 0..200 {
+        removed |= server
+            .poll()
+            .iter()
+            .any(|event| matches!(event, ServerPacket::RemovePlayer(_)));
+        if removed {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(removed);
+}
+
+#[test]
+fn tcp_transport_on_loopback() {
+    let mut server = TcpServerTransport::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    let mut client = TcpClientTransport::connect(&address).unwrap();
+    check_loopback(&mut client, &mut server);
+}
+
+// what the server got from clients, polling at least once and until count packets came or two seconds went by
+fn received_by<S: ServerTransport>(server: &mut S, count: usize) -> Vec<ServerInternal> {
+    let started = Instant::now();
+    let mut received = Vec::new();
+    loop {
+        server.wait(Duration::from_millis(50));
+        received.extend(server.poll().into_iter().filter_map(|event| match event {
+            ServerPacket::ServerInternalPacket(internal) => Some(internal),
+            _ => None,
+        }));
+        if received.len() >= count || started.elapsed() > Duration::from_secs(2) {
+            return received;
+        }
+    }
+}
+
+#[test]
+fn tcp_server_reassembles_split_frames() {
+    let mut server = TcpServerTransport::bind("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut bytes = frame_packet(reliable(1)).unwrap();
+    bytes.extend(frame_packet(reliable(2)).unwrap());
+
+    // half of the size, then part of the packet, then the rest with the next one
+    let mut received = Vec::new();
+    for piece in [&bytes[..1], &bytes[1..5], &bytes[5..]] {
+        assert!(received.is_empty());
+        stream.write_all(piece).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        received.extend(received_by(&mut server, 0));
+    }
+    received.extend(received_by(&mut server, 2 - received.len()));
+    let received: Vec<Packet> = received
+        .into_iter()
+        .map(|internal| internal.packet)
+        .collect();
+    assert_eq!(received, vec![reliable(1), reliable(2)]);
+}
+
+#[test]
+fn tcp_server_drops_clients_that_stop_reading() {
+    let mut server = TcpServerTransport::bind("127.0.0.1:0").unwrap();
+    let _stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    let mut events = Vec::new();
+    while !events
+        .iter()
+        .any(|event| matches!(event, ServerPacket::AddPlayer(_)))
+    {
+        events.extend(server.poll());
+    }
+    let addr = added_address(&events);
+
+    // the client never reads, so everything piles up on our side once the socket is full
+    let chunk = Packet::WorldPacket(WorldPacket::LevelChunkPacket(LevelChunk {
+        checksum: 1,
+        index: 0,
+        count: 1,
+        data: vec![7; 16 * 1024],
+    }));
+    let mut removed = false;
+    for _ in 0..10_000 {
+        server.send(addr, chunk.clone());
+        removed = server
+            .poll()
+            .iter()
+            .any(|event| matches!(event, ServerPacket::RemovePlayer(removed) if *removed == addr));
+        if removed {
+            break;
+        }
+    }
+    assert!(removed);
+}
+
+#[test]
+fn tcp_server_handles_many_clients() {
+    let mut server = TcpServerTransport::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    let mut clients: Vec<TcpClientTransport> = (0..40)
+        .map(|_| TcpClientTransport::connect(&address).unwrap())
+        .collect();
+    for (id, client) in clients.iter_mut().enumerate() {
+        client.send(reliable(id as u64)).unwrap();
+    }
+
+    let received = received_by(&mut server, clients.len());
+    assert_eq!(received.len(), clients.len());
+    for internal in &received {
+        server.send(internal.address, internal.packet.clone());
+    }
+    // every client gets its own packet back
+    for (id, client) in clients.iter_mut().enumerate() {
+        let started = Instant::now();
+        let mut packets = Vec::new();
+        while packets.is_empty() && started.elapsed() < Duration::from_secs(2) {
+            client.wait(Duration::from_millis(50));
+            packets = client.poll().unwrap();
+        }
+        assert_eq!(packets, vec![reliable(id as u64)]);
+    }
+}
+
+#[test]
+fn waiting_ends_when_woken() {
+    let server = TcpServerTransport::bind("127.0.0.1:0").unwrap();
+    let mut client =
+        TcpClientTransport::connect(&server.local_addr().unwrap().to_string()).unwrap();
+    let waker = client.waker().unwrap();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        waker.wake().unwrap();
+    });
+    let started = Instant::now();
+    client.wait(Duration::from_secs(10));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn udp_server_can_disconnect_clients() {
+    let mut server = UdpServerTransport::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    let mut client = UdpClientTransport::connect(&address).unwrap();
+    let mut events = Vec::new();
+    while !events
+        .iter()
+        .any(|event| matches!(event, ServerPacket::AddPlayer(_)))
+    {
+        events.extend(server.poll());
+        client.poll().unwrap();
+    }
+    server.disconnect(added_address(&events));
+
+    let mut lost = false;
+    for _ in 0..200 {
+        if client.poll() == Err(TransportError::ConnectionLost) {
+            lost = true;
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(lost);
+    // nothing is reported for connections we closed ourselves
+    assert!(
+        !server
+            .poll()
+            .iter()
+            .any(|event| matches!(event, ServerPacket::RemovePlayer(_)))
+    );
+}
+
+#[test]
+fn udp_goodbyes_arrive_before_the_disconnect() {
+    let mut server = UdpServerTransport::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    let mut client = UdpClientTransport::connect(&address).unwrap();
+    let mut events = Vec::new();
+    while !events
+        .iter()
+        .any(|event| matches!(event, ServerPacket::AddPlayer(_)))
+    {
+        events.extend(server.poll());
+        client.poll().unwrap();
+    }
+    // like the server does when it shuts down or rejects someone
+    let goodbye = Packet::ConnectionPacket(ConnectionPacket::GoodbyePacket(Goodbye {
+        reason: GoodbyeReason::ServerShutdown,
+    }));
+    server.send(added_address(&events), goodbye.clone());
+    server.disconnect(added_address(&events));
+    assert!(server.is_closing());
+
+    let mut received = Vec::new();
+    let mut lost = false;
+    for _ in 0..200 {
+        server.poll();
+        match client.poll() {
+            Ok(packets) => received.extend(packets),
+            Err(err) => {
+                assert_eq!(err, TransportError::ConnectionLost);
+                lost = true;
+                break;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(received, vec![goodbye]);
+    assert!(lost);
+    assert!(!server.is_closing());
+}
+
+// a server that sends every packet straight back
+struct Echo(Arc<Mutex<Vec<Packet>>>);
+
+impl ClientTransport for Echo {
+    fn send(&mut self, packet: Packet) -> Result<(), TransportError> {
+        self.0.lock().unwrap().push(packet);
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<Packet>, TransportError> {
+        Ok(std::mem::take(&mut *self.0.lock().unwrap()))
+    }
+}
+
+fn simulated(latency_ms: u64, jitter_ms: u64, loss: f64) -> SimulatedClientTransport {
+    SimulatedClientTransport::new(
+        Box::new(Echo(Arc::default())),
+        NetworkConditions {
+            latency: Duration::from_millis(latency_ms),
+            jitter: Duration::from_millis(jitter_ms),
+            loss,
+        },
+    )
+}
+
+// polls until count packets came back or a second went by
+fn receive_echoes(client: &mut SimulatedClientTransport, count: usize) -> Vec<Packet> {
+    let started = Instant::now();
+    let mut received = Vec::new();
+    while received.len() < count && started.elapsed() < Duration::from_secs(1) {
+        received.extend(client.poll().unwrap());
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    received
+}
+
+#[test]
+fn simulated_latency_delays_both_ways() {
+    let mut client = simulated(30, 0, 0.0);
+    let started = Instant::now();
+    client.send(reliable(1)).unwrap();
+    assert!(client.poll().unwrap().is_empty());
+    assert_eq!(receive_echoes(&mut client, 1), vec![reliable(1)]);
+    assert!(started.elapsed() >= Duration::from_millis(60));
+}
+
+#[test]
+fn simulated_loss_only_drops_unreliable_packets() {
+    let mut client = simulated(0, 20, 1.0);
+    for i in 0..10 {
+        client.send(position(i as f64)).unwrap();
+        client.send(reliable(i)).unwrap();
+    }
+    // jitter can't reorder what has to arrive in order
+    let received = receive_echoes(&mut client, 10);
+    assert_eq!(received, (0..10).map(reliable).collect::<Vec<_>>());
+}
+
+#[test]
+fn network_conditions_from_args() {
+    let args = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+    assert_eq!(
+        NetworkConditions::from_args(&args(&["127.0.0.1:6000", "--udp"])),
+        Ok(None)
+    );
+    assert_eq!(
+        NetworkConditions::from_args(&args(&[
+            "--latency",
+            "100",
+            "--jitter",
+            "20",
+            "--loss",
+            "5"
+        ])),
+        Ok(Some(NetworkConditions {
+            latency: Duration::from_millis(100),
+            jitter: Duration::from_millis(20),
+            loss: 0.05,
+        }))
+    );
+    assert!(NetworkConditions::from_args(&args(&["--loss", "150"])).is_err());
+    assert!(NetworkConditions::from_args(&args(&["--latency", "-5"])).is_err());
+    assert!(NetworkConditions::from_args(&args(&["--jitter"])).is_err());
+}
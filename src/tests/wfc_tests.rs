@@ -0,0 +1,154 @@
+use rand::{SeedableRng, rngs::StdRng};
+use std::time::Instant;
+
+use crate::environment::{generator::level_tileset, tile_type::TileType};
+use crate::wfc::{WFCState, WfcError, WfcTile};
+
+fn generate(
+    width: usize,
+    height: usize,
+    tileset: &[WfcTile],
+    seed: u64,
+) -> Result<WFCState, WfcError> {
+    WFCState::generate(
+        width,
+        height,
+        tileset.to_vec(),
+        &mut StdRng::seed_from_u64(seed),
+    )
+}
+
+fn tile<'a>(tileset: &'a [WfcTile], tile_type: &TileType) -> &'a WfcTile {
+    tileset
+        .iter()
+        .find(|tile| tile.tile_type == *tile_type)
+        .unwrap()
+}
+
+// every pair of neighbours shares a socket on the sides that touch
+fn fits(state: &WFCState, tileset: &[WfcTile]) -> bool {
+    let tiles = state.tile_types();
+    let shares = |a: &WfcTile, b: &WfcTile, side: usize| {
+        a.edges[side]
+            .iter()
+            .any(|socket| b.edges[(side + 2) % 4].contains(socket))
+    };
+    for (y, row) in tiles.iter().enumerate() {
+        for (x, tile_type) in row.iter().enumerate() {
+            let here = tile(tileset, tile_type);
+            if x + 1 < row.len() && !shares(here, tile(tileset, &row[x + 1]), 1) {
+                return false;
+            }
+            if y + 1 < tiles.len() && !shares(here, tile(tileset, &tiles[y + 1][x]), 2) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// no tile may touch another of its kind, easy to paint into a corner
+fn three_colours() -> Vec<WfcTile> {
+    use TileType::{Grass, Sand, Water};
+    [Water, Grass, Sand]
+        .into_iter()
+        .map(|colour| {
+            let others: Vec<TileType> = [Water, Grass, Sand]
+                .into_iter()
+                .filter(|other| *other != colour)
+                .collect();
+            WfcTile {
+                tile_type: colour.clone(),
+                edges: [vec![colour.clone()], others.clone(), others, vec![colour]],
+                weight: 1.0,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn solvable_tilesets_are_solved() {
+    let tileset = level_tileset();
+    for seed in 0..5 {
+        let state = generate(12, 10, &tileset, seed).unwrap();
+        assert_eq!(state.tile_types().len(), 10);
+        assert_eq!(state.tile_types()[0].len(), 12);
+        assert!(fits(&state, &tileset));
+    }
+    // the same seed solves it the same way
+    assert_eq!(
+        generate(12, 10, &tileset, 1).unwrap().tile_types(),
+        generate(12, 10, &tileset, 1).unwrap().tile_types()
+    );
+}
+
+#[test]
+fn dead_ends_are_backtracked() {
+    let tileset = three_colours();
+    let mut backtracks = 0;
+    for seed in 0..10 {
+        let state = generate(10, 10, &tileset, seed).unwrap();
+        assert!(fits(&state, &tileset));
+        backtracks += state.backtracks;
+    }
+    assert!(backtracks > 0);
+}
+
+#[test]
+fn unsolvable_tilesets_are_reported() {
+    // nothing fits next to water, not even water
+    let lonely = vec![WfcTile::uniform(TileType::Water, vec![])];
+    assert_eq!(
+        generate(2, 2, &lonely, 0).err(),
+        Some(WfcError::Unsolvable { attempts: 1 })
+    );
+    // a single cell has no neighbours to disagree with
+    assert!(generate(1, 1, &lonely, 0).is_ok());
+
+    assert_eq!(generate(2, 2, &[], 0).err(), Some(WfcError::EmptyTileset));
+    let weightless = vec![WfcTile::uniform(TileType::Water, vec![TileType::Water]).weighted(0.0)];
+    assert_eq!(
+        generate(2, 2, &weightless, 0).err(),
+        Some(WfcError::BadWeight(TileType::Water))
+    );
+}
+
+#[test]
+fn big_tilesets_are_solved() {
+    // more tiles than bits in a u64, the last one is the only one that fits next to itself
+    let mut tileset = vec![WfcTile::uniform(TileType::Water, vec![]); 100];
+    tileset.push(WfcTile::uniform(TileType::Grass, vec![TileType::Grass]));
+    let tiles = generate(6, 6, &tileset, 0).unwrap().tile_types();
+    assert!(tiles.iter().flatten().all(|tile| *tile == TileType::Grass));
+}
+
+#[test]
+fn heavier_tiles_come_up_more_often() {
+    use TileType::{Grass, Water};
+    let tileset = vec![
+        WfcTile::uniform(Water, vec![Water, Grass]),
+        WfcTile::uniform(Grass, vec![Water, Grass]).weighted(9.0),
+    ];
+    let tiles = generate(20, 20, &tileset, 3).unwrap().tile_types();
+    let grass = tiles
+        .iter()
+        .flatten()
+        .filter(|tile| **tile == Grass)
+        .count();
+    assert!(grass > 300, "only {} of 400 tiles are grass", grass);
+}
+
+// cargo test --release wfc_benchmark -- --ignored --nocapture
+#[test]
+#[ignore]
+fn wfc_benchmark() {
+    let tileset = level_tileset();
+    let started = Instant::now();
+    let state = generate(256, 256, &tileset, 0).unwrap();
+    println!(
+        "256x256 level tiles: {:?}, {} backtracks",
+        started.elapsed(),
+        state.backtracks
+    );
+    assert!(fits(&state, &tileset));
+}
@@ -1,169 +1,168 @@
 use crate::environment::tile_type::TileType;
 use crate::game::find_sdl_gl_driver;
 use crate::networking::shared::{SCREEN_HEIGHT, SCREEN_WIDTH};
-use rand::{prelude::IndexedRandom, rng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+use std::fmt;
+
+mod overlapping;
+mod solver;
+
+pub use overlapping::{OverlappingModel, Symmetry};
+use solver::{Outcome, Rules, Solver};
 
 const TILE_SIZE: usize = 32;
 const GRID_WIDTH: usize = SCREEN_WIDTH as usize / TILE_SIZE;
 const GRID_HEIGHT: usize = SCREEN_HEIGHT as usize / TILE_SIZE;
 
+// how many choices the solver may take back before it starts over, and how often it starts over
+const BACKTRACKS: usize = 1000;
+const RESTARTS: usize = 10;
+
 #[derive(Debug, Clone)]
 pub struct WfcTile {
-    tile_type: TileType,
-    edges: [Vec<TileType>; 4], // Up, Right, Down, Left
+    pub tile_type: TileType,
+    pub edges: [Vec<TileType>; 4], // Up, Right, Down, Left
+    pub weight: f64,               // how often the tile is picked compared to the others
 }
 
-pub struct WFCState {
-    grid: Vec<Vec<Cell>>,
-    tileset: Vec<WfcTile>,
+impl WfcTile {
+    // the same sockets on every side
+    pub fn uniform(tile_type: TileType, sockets: Vec<TileType>) -> WfcTile {
+        WfcTile {
+            tile_type,
+            edges: [sockets.clone(), sockets.clone(), sockets.clone(), sockets],
+            weight: 1.0,
+        }
+    }
+
+    pub fn weighted(mut self, weight: f64) -> WfcTile {
+        self.weight = weight;
+        self
+    }
 }
 
-impl WFCState {
-    pub fn new(tileset: Vec<WfcTile>) -> Self {
-        let mut grid =
-            vec![vec![Cell::new(tileset.len()); GRID_WIDTH]; GRID_HEIGHT];
+#[derive(Debug, Clone, PartialEq)]
+pub enum WfcError {
+    EmptyTileset,
+    BadWeight(TileType),   // weights have to be positive
+    BadPatternSize(usize), // patterns have to fit into the example and be at least 1x1
+    Unsolvable { attempts: usize },
+}
 
-        while grid
-            .iter()
-            .any(|row| row.iter().any(|cell| !cell.collapsed))
-        {
-            let mut uncollapsed = vec![];
-            for y in 0..GRID_HEIGHT {
-                for x in 0..GRID_WIDTH {
-                    if !grid[y][x].collapsed {
-                        uncollapsed.push((x, y));
-                    }
-                }
+impl fmt::Display for WfcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WfcError::EmptyTileset => write!(f, "the tileset is empty"),
+            WfcError::BadWeight(tile_type) => {
+                write!(f, "{} has a weight that isn't positive", tile_type.name())
             }
-
-            if uncollapsed.is_empty() {
-                break;
+            WfcError::BadPatternSize(size) => {
+                write!(f, "{}x{} patterns don't fit into the example", size, size)
             }
-            uncollapsed.sort_by_key(|&(x, y)| grid[y][x].entropy());
-
-            let (x, y) = uncollapsed[0];
-
-            grid[y][x].collapse();
-
-            propagate(&mut grid, &tileset);
-        }
-        WFCState { grid, tileset }
-    }
-
-    pub fn draw(&self, canvas: &mut Canvas<Window>) {
-        for y in 0..GRID_HEIGHT {
-            for x in 0..GRID_WIDTH {
-                let cell = &self.grid[y][x];
-                if cell.collapsed {
-                    let tile_index = cell.options[0];
-                    let tile = &self.tileset[tile_index];
-                    let rect = Rect::new(
-                        (x * TILE_SIZE) as i32,
-                        (y * TILE_SIZE) as i32,
-                        TILE_SIZE as u32,
-                        TILE_SIZE as u32,
-                    );
-                    canvas.set_draw_color(tile.tile_type._get_color());
-                    canvas.fill_rect(rect).unwrap();
-                }
+            WfcError::Unsolvable { attempts } => {
+                write!(f, "no tiles fit together after {} attempts", attempts)
             }
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Cell {
-    options: Vec<usize>,
-    collapsed: bool,
+pub struct WFCState {
+    cells: Vec<usize>, // the tile of every cell, row by row
+    tileset: Vec<WfcTile>,
+    width: usize,
+    height: usize,
+    pub backtracks: usize, // choices that had to be taken back on the way
 }
 
-impl Cell {
-    fn new(tile_count: usize) -> Self {
-        Cell {
-            options: (0..tile_count).collect(),
-            collapsed: false,
+impl WFCState {
+    pub fn generate(
+        width: usize,
+        height: usize,
+        tileset: Vec<WfcTile>,
+        rng: &mut impl Rng,
+    ) -> Result<Self, WfcError> {
+        if tileset.is_empty() {
+            return Err(WfcError::EmptyTileset);
+        }
+        if let Some(tile) = tileset
+            .iter()
+            .find(|tile| !(tile.weight > 0.0 && tile.weight.is_finite()))
+        {
+            return Err(WfcError::BadWeight(tile.tile_type.clone()));
         }
-    }
 
-    fn entropy(&self) -> usize {
-        self.options.len()
+        // tiles fit next to each other if they share a socket on the sides that touch
+        let weights = tileset.iter().map(|tile| tile.weight).collect();
+        let rules = Rules::new(weights, |a, b, side| {
+            tileset[a].edges[side]
+                .iter()
+                .any(|socket| tileset[b].edges[(side + 2) % 4].contains(socket))
+        });
+        let (cells, backtracks) = solve(width, height, &rules, rng)?;
+        Ok(WFCState {
+            cells,
+            tileset,
+            width,
+            height,
+            backtracks,
+        })
     }
 
-    fn collapse(&mut self) {
-        let mut rng = rng();
-        if self.collapsed || self.options.is_empty() {
-            return;
-        }
-        let choice = *self
-            .options
-            .choose(&mut rng)
-            .expect("No options to collapse");
-        self.options = vec![choice];
-        self.collapsed = true;
+    fn tile_at(&self, x: usize, y: usize) -> &WfcTile {
+        &self.tileset[self.cells[y * self.width + x]]
     }
-}
-
-fn is_compatible(tile_a: &WfcTile, tile_b: &WfcTile, direction: usize) -> bool {
-    let opposite = (direction + 2) % 4;
-    tile_a.edges[direction]
-        .iter()
-        .any(|edge_a| tile_b.edges[opposite].contains(edge_a))
-}
-
-fn propagate(grid: &mut Vec<Vec<Cell>>, tileset: &[WfcTile]) {
-    let directions = [(0, -1), (1, 0), (0, 1), (-1, 0)];
-    let mut changed = true;
-
-    while changed {
-        changed = false;
-        let mut updates = vec![];
-        for y in 0..GRID_HEIGHT {
-            for x in 0..GRID_WIDTH {
-                let cell = &grid[y][x];
-                if cell.collapsed {
-                    continue;
-                }
-
-                let mut new_options = cell.options.clone();
 
-                for (dir, (dx, dy)) in directions.iter().enumerate() {
-                    let nx = x as isize + dx;
-                    let ny = y as isize + dy;
-                    if nx < 0 || ny < 0 || nx >= GRID_WIDTH as isize || ny >= GRID_HEIGHT as isize {
-                        continue;
-                    }
-
-                    let neighbor = &grid[ny as usize][nx as usize];
-
-                    new_options.retain(|&opt| {
-                        neighbor
-                            .options
-                            .iter()
-                            .any(|&nopt| is_compatible(&tileset[opt], &tileset[nopt], dir))
-                    });
-                }
+    // the chosen tile of every cell, row by row
+    pub fn tile_types(&self) -> Vec<Vec<TileType>> {
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| self.tile_at(x, y).tile_type.clone())
+                    .collect()
+            })
+            .collect()
+    }
 
-                if new_options.len() < cell.options.len() {
-                    updates.push((x, y, new_options));
-                }
+    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let rect = Rect::new(
+                    (x * TILE_SIZE) as i32,
+                    (y * TILE_SIZE) as i32,
+                    TILE_SIZE as u32,
+                    TILE_SIZE as u32,
+                );
+                canvas.set_draw_color(self.tile_at(x, y).tile_type._get_color());
+                canvas.fill_rect(rect).unwrap();
             }
         }
+    }
+}
 
-        if !updates.is_empty() {
-            changed = true;
-            for (x, y, new_options) in updates {
-                println!(
-                    "Propagated update at ({}, {}): options now {:?}",
-                    x, y, new_options
-                );
-                grid[y][x].options = new_options;
-            }
+// the tile of every cell and how many choices were taken back,
+// starts over with a fresh grid when the backtracks run out
+fn solve(
+    width: usize,
+    height: usize,
+    rules: &Rules,
+    rng: &mut impl Rng,
+) -> Result<(Vec<usize>, usize), WfcError> {
+    let mut backtracks = 0;
+    for attempt in 1..=RESTARTS {
+        let mut solver = Solver::new(width, height, rules, rng);
+        let outcome = solver.solve(BACKTRACKS);
+        backtracks += solver.backtracks;
+        match outcome {
+            Outcome::Solved(cells) => return Ok((cells, backtracks)),
+            Outcome::Impossible => return Err(WfcError::Unsolvable { attempts: attempt }),
+            Outcome::OutOfBudget => continue,
         }
     }
+    Err(WfcError::Unsolvable { attempts: RESTARTS })
 }
 
-pub fn run_wfc() {
+pub fn run_wfc(seed: Option<u64>) {
     // Example usage
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -188,6 +187,7 @@ pub fn run_wfc() {
                 vec![TileType::Water, TileType::Grass],
                 vec![TileType::Water, TileType::Grass],
             ],
+            weight: 1.0,
         },
         WfcTile {
             tile_type: TileType::Grass,
@@ -197,25 +197,33 @@ pub fn run_wfc() {
                 vec![TileType::Grass, TileType::Water],
                 vec![TileType::Grass, TileType::Water],
             ],
+            weight: 1.0,
         },
     ];
-    let wfc_state = WFCState::new(tileset);
+    // the same seed shows the same grids
+    let seed = seed.unwrap_or_else(rand::random);
+    println!("WFC seed {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut preview = |tileset: &Vec<WfcTile>| {
+        let state = WFCState::generate(GRID_WIDTH, GRID_HEIGHT, tileset.clone(), &mut rng)
+            .expect("The preview tiles always fit together");
+        println!("Generated a grid with {} backtracks", state.backtracks);
+        state
+    };
+    let mut wfc_state = preview(&tileset);
 
     let mut event_pump = sdl_context.event_pump().unwrap();
     'running: loop {
         for event in event_pump.poll_iter() {
             use sdl2::event::Event;
             match event {
-                //         Event::KeyDown {
-                //             keycode: Some(sdl2::keyboard::Keycode::N),
-                //             ..
-                //         } => {
-                //             // Press 'N' to generate a new WFC state
-                //             println!("Generating new WFC state...");
-                //             let new_wfc_state = WFCState::new(tileset.clone());
-                //             wfc_state.grid = new_wfc_state.grid;
-                //             wfc_state.tileset = new_wfc_state.tileset;
-                //         }
+                Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::N),
+                    ..
+                } => {
+                    // Press 'N' to generate a new WFC state
+                    wfc_state = preview(&tileset);
+                }
                 Event::Quit { .. } => {
                     break 'running;
                 }
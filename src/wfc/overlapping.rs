@@ -0,0 +1,178 @@
+use image::{Rgba, RgbaImage};
+use rand::Rng;
+use std::collections::HashMap;
+
+use super::{
+    WfcError, solve,
+    solver::{DIRECTIONS, Rules},
+};
+
+// which turned and mirrored copies of the example's patterns count too
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Symmetry {
+    None,
+    Mirror, // left to right
+    Rotate,
+    All, // every rotation, mirrored and not
+}
+
+impl Symmetry {
+    pub fn from_name(name: &str) -> Option<Symmetry> {
+        match name {
+            "none" => Some(Symmetry::None),
+            "mirror" => Some(Symmetry::Mirror),
+            "rotate" => Some(Symmetry::Rotate),
+            "all" => Some(Symmetry::All),
+            _ => None,
+        }
+    }
+}
+
+// the NxN patterns of an example image and how often they come up in it,
+// new images are made of the same patterns overlapping the same way
+#[derive(Debug, Clone)]
+pub struct OverlappingModel {
+    size: usize,
+    colours: Vec<Rgba<u8>>,
+    patterns: Vec<Vec<usize>>, // the colours of every pattern, row by row
+    weights: Vec<f64>,
+}
+
+impl OverlappingModel {
+    pub fn learn(
+        example: &RgbaImage,
+        size: usize,
+        symmetry: Symmetry,
+    ) -> Result<OverlappingModel, WfcError> {
+        let (width, height) = (example.width() as usize, example.height() as usize);
+        if size == 0 || size > width || size > height {
+            return Err(WfcError::BadPatternSize(size));
+        }
+        let mut colours: Vec<Rgba<u8>> = Vec::new();
+        let pixels: Vec<usize> = example
+            .pixels()
+            .map(
+                |pixel| match colours.iter().position(|colour| colour == pixel) {
+                    Some(index) => index,
+                    None => {
+                        colours.push(*pixel);
+                        colours.len() - 1
+                    }
+                },
+            )
+            .collect();
+
+        // in the order they're found, so the same example always gives the same model
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut patterns = Vec::new();
+        let mut weights = Vec::new();
+        // the example doesn't wrap around
+        for y in 0..=height - size {
+            for x in 0..=width - size {
+                let pattern = (0..size * size)
+                    .map(|i| pixels[(y + i / size) * width + x + i % size])
+                    .collect();
+                for variant in variants(pattern, size, symmetry) {
+                    let next_id = patterns.len();
+                    let id = *ids.entry(variant.clone()).or_insert(next_id);
+                    if id == next_id {
+                        patterns.push(variant);
+                        weights.push(0.0);
+                    }
+                    weights[id] += 1.0;
+                }
+            }
+        }
+        Ok(OverlappingModel {
+            size,
+            colours,
+            patterns,
+            weights,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn colours(&self) -> &[Rgba<u8>] {
+        &self.colours
+    }
+
+    #[cfg(test)]
+    pub fn patterns(&self) -> Vec<Vec<Rgba<u8>>> {
+        self.patterns
+            .iter()
+            .map(|pattern| pattern.iter().map(|colour| self.colours[*colour]).collect())
+            .collect()
+    }
+
+    // how often every pattern came up, copies included
+    #[cfg(test)]
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    pub fn generate(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut impl Rng,
+    ) -> Result<RgbaImage, WfcError> {
+        let size = self.size;
+        if width < size || height < size {
+            return Err(WfcError::BadPatternSize(size));
+        }
+        let rules = Rules::new(self.weights.clone(), |a, b, side| {
+            self.overlaps(a, b, DIRECTIONS[side])
+        });
+        // a pattern starts at every cell, the last ones reach to the edge
+        let (columns, rows) = (width - size + 1, height - size + 1);
+        let (cells, _) = solve(columns, rows, &rules, rng)?;
+        Ok(RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            let (column, row) = (x.min(columns - 1), y.min(rows - 1));
+            let pattern = &self.patterns[cells[row * columns + column]];
+            self.colours[pattern[(y - row) * size + x - column]]
+        }))
+    }
+
+    // pattern b moved over by (dx, dy) has the same colours as a where they overlap
+    fn overlaps(&self, a: usize, b: usize, (dx, dy): (isize, isize)) -> bool {
+        let size = self.size as isize;
+        let (a, b) = (&self.patterns[a], &self.patterns[b]);
+        for y in dy.max(0)..size.min(size + dy) {
+            for x in dx.max(0)..size.min(size + dx) {
+                if a[(y * size + x) as usize] != b[((y - dy) * size + x - dx) as usize] {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+// the pattern and its copies, duplicates are counted again
+fn variants(pattern: Vec<usize>, size: usize, symmetry: Symmetry) -> Vec<Vec<usize>> {
+    let turn = |pattern: &Vec<usize>| -> Vec<usize> {
+        (0..size * size)
+            .map(|i| pattern[(size - 1 - i % size) * size + i / size])
+            .collect()
+    };
+    let mirror = |pattern: &Vec<usize>| -> Vec<usize> {
+        (0..size * size)
+            .map(|i| pattern[i / size * size + size - 1 - i % size])
+            .collect()
+    };
+    let mut rotations = vec![pattern];
+    if matches!(symmetry, Symmetry::Rotate | Symmetry::All) {
+        for _ in 0..3 {
+            rotations.push(turn(rotations.last().unwrap()));
+        }
+    }
+    match symmetry {
+        Symmetry::Mirror | Symmetry::All => {
+            let mirrored: Vec<Vec<usize>> = rotations.iter().map(mirror).collect();
+            rotations.extend(mirrored);
+            rotations
+        }
+        Symmetry::None | Symmetry::Rotate => rotations,
+    }
+}
@@ -0,0 +1,343 @@
+use rand::Rng;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+// up, right, down, left, like the edges
+pub const DIRECTIONS: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+fn opposite(direction: usize) -> usize {
+    (direction + 2) % 4
+}
+
+// everything about a set of tiles the solver looks up over and over
+pub struct Rules {
+    count: usize,
+    words: usize, // a set of tiles is a bitset of this many u64s
+    all: Vec<u64>,
+    // for every side and tile, the tiles that may be next to it there
+    compatible: [Vec<Vec<usize>>; 4],
+    weights: Vec<f64>,
+    weight_logs: Vec<f64>, // weight * ln(weight), for the entropy
+}
+
+impl Rules {
+    // fits(a, b, side) says if tile b may be on that side of tile a,
+    // it has to agree with fits(b, a) on the other side
+    pub fn new(weights: Vec<f64>, fits: impl Fn(usize, usize, usize) -> bool) -> Rules {
+        let count = weights.len();
+        let words = count.div_ceil(64);
+        let mut all = vec![0; words];
+        for tile in 0..count {
+            all[tile / 64] |= 1 << (tile % 64);
+        }
+        let compatible = std::array::from_fn(|direction| {
+            (0..count)
+                .map(|a| (0..count).filter(|b| fits(a, *b, direction)).collect())
+                .collect()
+        });
+        Rules {
+            count,
+            words,
+            all,
+            compatible,
+            weight_logs: weights.iter().map(|weight| weight * weight.ln()).collect(),
+            weights,
+        }
+    }
+}
+
+// the tiles in a set
+fn tiles(options: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    options.iter().enumerate().flat_map(|(index, word)| {
+        let mut word = *word;
+        std::iter::from_fn(move || {
+            (word != 0).then(|| {
+                let tile = index * 64 + word.trailing_zeros() as usize;
+                word &= word - 1;
+                tile
+            })
+        })
+    })
+}
+
+// a cell waiting to be collapsed, the heap gives out the lowest entropy first,
+// entries for cells that changed since are skipped
+struct Candidate {
+    entropy: f64,
+    cell: usize,
+    version: u32,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .entropy
+            .total_cmp(&self.entropy)
+            .then(other.cell.cmp(&self.cell))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+// how one run of the solver ended
+pub enum Outcome {
+    Solved(Vec<usize>), // the tile of every cell, row by row
+    Impossible,         // every choice was tried, starting over won't help
+    OutOfBudget,
+}
+
+// one attempt at filling the grid
+pub struct Solver<'a, R: Rng> {
+    width: usize,
+    height: usize,
+    cells: Vec<u64>,       // the options of every cell, row by row
+    remaining: Vec<usize>, // how many options every cell has
+    // the weights of every cell's options and their weight * ln(weight) added up
+    weight_sums: Vec<(f64, f64)>,
+    // for every cell, tile and side, how many options of the neighbour there still fit,
+    // a tile is banned once one of them runs out
+    supports: Vec<u32>,
+    versions: Vec<u32>, // how often every cell changed
+    rules: &'a Rules,
+    rng: &'a mut R,
+    queue: BinaryHeap<Candidate>,
+    banned: Vec<(usize, usize)>, // cells and tiles that lost their support
+    dirty: Vec<usize>,           // cells that changed since they were last queued
+    queued: Vec<bool>,
+    // every tile that was banned, choices are taken back by unwinding it
+    trail: Vec<(usize, usize)>,
+    pub backtracks: usize,
+}
+
+impl<'a, R: Rng> Solver<'a, R> {
+    pub fn new(width: usize, height: usize, rules: &'a Rules, rng: &'a mut R) -> Self {
+        let cells = width * height;
+        let mut supports = Vec::with_capacity(cells * rules.count * 4);
+        for _ in 0..cells {
+            for tile in 0..rules.count {
+                for direction in 0..4 {
+                    supports.push(rules.compatible[direction][tile].len() as u32);
+                }
+            }
+        }
+        let weight_sums = (rules.weights.iter().sum(), rules.weight_logs.iter().sum());
+        Solver {
+            width,
+            height,
+            cells: rules.all.repeat(cells),
+            remaining: vec![rules.count; cells],
+            weight_sums: vec![weight_sums; cells],
+            supports,
+            versions: vec![0; cells],
+            rules,
+            rng,
+            queue: BinaryHeap::new(),
+            banned: Vec::new(),
+            dirty: (0..cells).collect(),
+            queued: vec![true; cells],
+            trail: Vec::new(),
+            backtracks: 0,
+        }
+    }
+
+    fn options(&self, cell: usize) -> &[u64] {
+        &self.cells[cell * self.rules.words..(cell + 1) * self.rules.words]
+    }
+
+    fn has(&self, cell: usize, tile: usize) -> bool {
+        self.cells[cell * self.rules.words + tile / 64] & (1 << (tile % 64)) != 0
+    }
+
+    fn neighbour(&self, cell: usize, direction: usize) -> Option<usize> {
+        let (dx, dy) = DIRECTIONS[direction];
+        let x = (cell % self.width).wrapping_add_signed(dx);
+        let y = (cell / self.width).wrapping_add_signed(dy);
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
+    fn support(&self, cell: usize, tile: usize, direction: usize) -> usize {
+        (cell * self.rules.count + tile) * 4 + direction
+    }
+
+    // collapses the cell with the lowest entropy until every cell has one tile left,
+    // a cell without any tiles takes back the last choice and rules that tile out
+    pub fn solve(&mut self, budget: usize) -> Outcome {
+        // tiles that nothing fits next to can't be anywhere with a neighbour on that side
+        for cell in 0..self.remaining.len() {
+            for tile in 0..self.rules.count {
+                for direction in 0..4 {
+                    if self.neighbour(cell, direction).is_some()
+                        && self.supports[self.support(cell, tile, direction)] == 0
+                    {
+                        self.banned.push((cell, tile));
+                    }
+                }
+            }
+        }
+        // how long the trail was before every choice, with the cell and the tile that was picked
+        let mut choices: Vec<(usize, usize, usize)> = Vec::new();
+        let mut consistent = self.propagate();
+
+        loop {
+            self.requeue();
+            if !consistent {
+                let Some((length, cell, tile)) = choices.pop() else {
+                    return Outcome::Impossible;
+                };
+                if self.backtracks == budget {
+                    return Outcome::OutOfBudget;
+                }
+                self.backtracks += 1;
+                self.undo(length);
+                // with nothing left to try here either, the choice before is taken back
+                consistent = self.ban(cell, tile) && self.propagate();
+                continue;
+            }
+
+            let Some(cell) = self.next_cell() else {
+                let cells = (0..self.remaining.len())
+                    .map(|cell| {
+                        tiles(self.options(cell))
+                            .next()
+                            .expect("Every cell has a tile")
+                    })
+                    .collect();
+                return Outcome::Solved(cells);
+            };
+            let tile = self.choose(cell);
+            choices.push((self.trail.len(), cell, tile));
+            let others: Vec<usize> = tiles(self.options(cell))
+                .filter(|other| *other != tile)
+                .collect();
+            for other in others {
+                self.ban(cell, other);
+            }
+            consistent = self.propagate();
+        }
+    }
+
+    // false if the cell ran out of tiles
+    fn ban(&mut self, cell: usize, tile: usize) -> bool {
+        let rules = self.rules;
+        self.cells[cell * rules.words + tile / 64] &= !(1 << (tile % 64));
+        self.remaining[cell] -= 1;
+        self.weight_sums[cell].0 -= rules.weights[tile];
+        self.weight_sums[cell].1 -= rules.weight_logs[tile];
+        self.trail.push((cell, tile));
+        self.touch(cell);
+        for direction in 0..4 {
+            let Some(neighbour) = self.neighbour(cell, direction) else {
+                continue;
+            };
+            // the neighbour's tiles lose a support on the side facing this cell
+            for other in &rules.compatible[direction][tile] {
+                let support = self.support(neighbour, *other, opposite(direction));
+                self.supports[support] -= 1;
+                if self.supports[support] == 0 && self.has(neighbour, *other) {
+                    self.banned.push((neighbour, *other));
+                }
+            }
+        }
+        if self.remaining[cell] == 0 {
+            self.banned.clear();
+            return false;
+        }
+        true
+    }
+
+    // bans what lost its support until nothing more does, false if a cell ran out of tiles
+    fn propagate(&mut self) -> bool {
+        while let Some((cell, tile)) = self.banned.pop() {
+            if self.has(cell, tile) && !self.ban(cell, tile) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn touch(&mut self, cell: usize) {
+        self.versions[cell] += 1;
+        if !self.queued[cell] {
+            self.queued[cell] = true;
+            self.dirty.push(cell);
+        }
+    }
+
+    // queues the cells that changed again with their new entropy
+    fn requeue(&mut self) {
+        while let Some(cell) = self.dirty.pop() {
+            self.queued[cell] = false;
+            if self.remaining[cell] < 2 {
+                continue;
+            }
+            // shannon entropy of the tiles that are left, by weight,
+            // with a little noise to break ties at random
+            let (sum, sum_log) = self.weight_sums[cell];
+            let entropy = sum.ln() - sum_log / sum + self.rng.random::<f64>() * 1e-6;
+            self.queue.push(Candidate {
+                entropy,
+                cell,
+                version: self.versions[cell],
+            });
+        }
+    }
+
+    // None once every cell is decided
+    fn next_cell(&mut self) -> Option<usize> {
+        while let Some(candidate) = self.queue.pop() {
+            if self.versions[candidate.cell] == candidate.version {
+                return Some(candidate.cell);
+            }
+        }
+        None
+    }
+
+    fn choose(&mut self, cell: usize) -> usize {
+        let weights = &self.rules.weights;
+        let options = &self.cells[cell * self.rules.words..(cell + 1) * self.rules.words];
+        let total: f64 = tiles(options).map(|tile| weights[tile]).sum();
+        let mut roll = self.rng.random_range(0.0..total);
+        for tile in tiles(options) {
+            roll -= weights[tile];
+            if roll < 0.0 {
+                return tile;
+            }
+        }
+        // rounding can leave a bit over
+        tiles(options).last().expect("No options to collapse")
+    }
+
+    // puts banned tiles back, with the supports they took away
+    fn undo(&mut self, length: usize) {
+        let rules = self.rules;
+        while self.trail.len() > length {
+            let (cell, tile) = self.trail.pop().unwrap();
+            self.cells[cell * rules.words + tile / 64] |= 1 << (tile % 64);
+            self.remaining[cell] += 1;
+            self.weight_sums[cell].0 += rules.weights[tile];
+            self.weight_sums[cell].1 += rules.weight_logs[tile];
+            self.touch(cell);
+            for direction in 0..4 {
+                let Some(neighbour) = self.neighbour(cell, direction) else {
+                    continue;
+                };
+                for other in &rules.compatible[direction][tile] {
+                    let support = self.support(neighbour, *other, opposite(direction));
+                    self.supports[support] += 1;
+                }
+            }
+        }
+    }
+}